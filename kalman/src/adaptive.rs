@@ -0,0 +1,167 @@
+#![allow(non_snake_case)]
+
+use crate::Error;
+use ndarray_linalg::Eigh;
+
+#[derive(Clone, Copy, Debug)]
+pub struct AdaptiveNoiseConfig {
+    /// between 0 and 1, a larger value averages over more steps. `1 / (1 - b)`
+    /// is roughly the number of steps the estimates are averaged over.
+    pub forgetting: f64,
+    pub adapt_Q: bool,
+    pub adapt_R: bool,
+    /// eigenvalues of the estimates are kept above this value so they stay
+    /// positive definite
+    pub min_variance: f64,
+}
+
+impl Default for AdaptiveNoiseConfig {
+    fn default() -> Self {
+        Self {
+            forgetting: 0.98,
+            adapt_Q: true,
+            adapt_R: true,
+            min_variance: 1.0e-9,
+        }
+    }
+}
+
+/// Sage-Husa style estimation of `Q` and `R` by matching the covariances of
+/// the innovations and state corrections. Works with every filter by calling
+/// [before_predict](Self::before_predict),
+/// [after_predict](Self::after_predict) and
+/// [after_update](Self::after_update) around its steps.
+#[derive(Clone, Debug)]
+pub struct AdaptiveNoise {
+    pub cfg: AdaptiveNoiseConfig,
+
+    /// number of adapted updates
+    k: i32,
+    Q: Option<ndarray::Array2<f64>>,
+    x_prior: ndarray::Array1<f64>,
+    P_prior: ndarray::Array2<f64>,
+}
+
+impl AdaptiveNoise {
+    pub fn new(cfg: AdaptiveNoiseConfig) -> Self {
+        Self {
+            cfg,
+            k: 0,
+            Q: None,
+            x_prior: ndarray::Array1::zeros(0),
+            P_prior: ndarray::Array2::zeros((0, 0)),
+        }
+    }
+
+    /// the current estimate of `Q`, `None` until the first update
+    pub fn Q(&self) -> Option<&ndarray::Array2<f64>> {
+        self.Q.as_ref()
+    }
+
+    /// replaces the `Q` of `filter` with the estimate, so call it after
+    /// [SetDt](crate::SetDt::set_dt)
+    pub fn before_predict<F>(&self, filter: &mut F)
+    where
+        F: crate::Q<Elem = f64>,
+    {
+        if let Some(Q) = &self.Q {
+            filter.Q_mut().assign(Q);
+        }
+    }
+
+    pub fn after_predict<F>(&mut self, filter: &F)
+    where
+        F: crate::Filter<Elem = f64>,
+    {
+        self.x_prior = filter.x().clone();
+        self.P_prior = filter.P().clone();
+    }
+
+    /// updates the estimates and the `R` of `filter`. Gated updates are
+    /// ignored because their innovations aren't representative.
+    pub fn after_update<F, FNS>(&mut self, filter: &mut F, fns_x: &FNS) -> Result<(), Error>
+    where
+        F: crate::Filter<Elem = f64>
+            + crate::Q<Elem = f64>
+            + crate::R<Elem = f64>
+            + crate::Innovation<Elem = f64>
+            + crate::gating::Gating<Elem = f64>,
+        FNS: crate::Subtract<f64>,
+    {
+        if filter.gated() {
+            return Ok(());
+        }
+
+        let b = self.cfg.forgetting;
+        let d = (1.0 - b) / (1.0 - b.powi(self.k + 1));
+        self.k = self.k.saturating_add(1);
+
+        if self.cfg.adapt_R {
+            let y = filter.y();
+            // innovation covariance without R
+            let Pzz = filter.S() - filter.R();
+            let R = filter.R() * (1.0 - d) + (math::outer_product(y, y) - Pzz) * d;
+            let R = self.clamp(R)?;
+            filter.R_mut().assign(&R);
+        }
+
+        if self.cfg.adapt_Q {
+            // the corrections of the state are the part of the process noise
+            // the model didn't predict
+            let dx = fns_x.subtract(filter.x(), &self.x_prior);
+            let Q_prev = self.Q.as_ref().unwrap_or_else(|| filter.Q());
+            let Q = Q_prev * (1.0 - d)
+                + (math::outer_product(&dx, &dx) + filter.P() - &self.P_prior + Q_prev) * d;
+            self.Q = Some(self.clamp(Q)?);
+        }
+
+        Ok(())
+    }
+
+    /// makes `M` symmetric and positive definite
+    fn clamp(&self, M: ndarray::Array2<f64>) -> Result<ndarray::Array2<f64>, Error> {
+        let M = (&M + &M.t()) * 0.5;
+        let (e, V) = M.eigh(ndarray_linalg::UPLO::Lower)?;
+        let e = e.mapv(|v| v.max(self.cfg.min_variance));
+
+        Ok((&V * &e).dot(&V.t()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Filter;
+
+    /// `R` has to converge to the actual measurement noise
+    #[test]
+    fn adapt_R() {
+        let dt = 0.1;
+        let sigma_z: f64 = 0.5;
+
+        let mut kf = crate::test_support::cv_filter(dt);
+        kf.R = ndarray::array![[1.0]];
+        kf.Q = ndarray::array![[0.0, 0.0], [0.0, 1.0e-6]];
+        kf.x = ndarray::array![0.0, 1.0];
+
+        let fns = crate::test_support::Functions;
+        let mut noise = crate::test_support::randn(1);
+        let mut adaptive = super::AdaptiveNoise::new(super::AdaptiveNoiseConfig {
+            forgetting: 0.995,
+            adapt_Q: false,
+            ..Default::default()
+        });
+
+        for i in 0..3000 {
+            let z = ndarray::array![(i + 1) as f64 * dt + noise.next().unwrap() * sigma_z];
+
+            adaptive.before_predict(&mut kf);
+            kf.predict().unwrap();
+            adaptive.after_predict(&kf);
+            kf.update(&z).unwrap();
+            adaptive.after_update(&mut kf, &fns).unwrap();
+        }
+
+        let r = kf.R[(0, 0)];
+        assert!((r - sigma_z.powi(2)).abs() < 0.1, "R = {r}");
+    }
+}
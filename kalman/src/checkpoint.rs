@@ -0,0 +1,115 @@
+#![allow(non_snake_case)]
+
+use crate::Error;
+
+/// state of a filter which can be saved with serde, e.g. to resume a run or
+/// to start the next one from an already converged state
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Checkpoint<A> {
+    pub x: ndarray::Array1<A>,
+    pub P: ndarray::Array2<A>,
+    pub Q: ndarray::Array2<A>,
+    pub R: ndarray::Array2<A>,
+}
+
+impl<A: Clone> Checkpoint<A> {
+    pub fn new<F>(filter: &F) -> Self
+    where
+        F: crate::Filter<Elem = A> + crate::Q<Elem = A> + crate::R<Elem = A> + ?Sized,
+    {
+        Self {
+            x: filter.x().clone(),
+            P: filter.P().clone(),
+            Q: filter.Q().clone(),
+            R: filter.R().clone(),
+        }
+    }
+
+    /// the dimensions of all matrices match the ones of `filter`
+    pub fn matches<F>(&self, filter: &F) -> bool
+    where
+        F: crate::Filter<Elem = A> + crate::Q<Elem = A> + crate::R<Elem = A> + ?Sized,
+    {
+        self.x.dim() == filter.x().dim()
+            && self.P.dim() == filter.P().dim()
+            && self.Q.dim() == filter.Q().dim()
+            && self.R.dim() == filter.R().dim()
+    }
+
+    /// fails without changing `filter` if the dimensions don't match
+    pub fn restore<F>(&self, filter: &mut F) -> Result<(), Error>
+    where
+        F: crate::Filter<Elem = A> + crate::Q<Elem = A> + crate::R<Elem = A> + ?Sized,
+    {
+        if !self.matches(filter) {
+            return Err(Error::CheckpointShape);
+        }
+
+        filter.x_mut().assign(&self.x);
+        filter.P_mut().assign(&self.P);
+        filter.Q_mut().assign(&self.Q);
+        filter.R_mut().assign(&self.R);
+
+        Ok(())
+    }
+}
+
+/// state of an [Imm](crate::imm::Imm) and all of its filters
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImmCheckpoint<A> {
+    pub filters: Vec<Checkpoint<A>>,
+    /// mode probabilities
+    pub mu: ndarray::Array1<A>,
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Filter;
+
+    fn filter() -> crate::kalman::Kalman<f64, ndarray::OwnedRepr<f64>> {
+        let mut kf = crate::test_support::cv_filter(0.1);
+        kf.Q = ndarray::array![[0.0, 0.0], [0.0, 0.01]];
+        kf.R = ndarray::array![[0.25]];
+        kf
+    }
+
+    /// a restored filter has to continue exactly like the original one
+    #[test]
+    fn resume() {
+        let zs: Vec<_> = (0..40)
+            .map(|i| ndarray::array![(i as f64 * 0.1).powi(2)])
+            .collect();
+
+        let mut kf = filter();
+        for z in &zs[..20] {
+            kf.predict().unwrap();
+            kf.update(z).unwrap();
+        }
+
+        let json = serde_json::to_string(&super::Checkpoint::new(&kf)).unwrap();
+        let checkpoint: super::Checkpoint<f64> = serde_json::from_str(&json).unwrap();
+
+        let mut resumed = filter();
+        resumed.R = ndarray::array![[1.0]];
+        checkpoint.restore(&mut resumed).unwrap();
+
+        for z in &zs[20..] {
+            kf.predict().unwrap();
+            kf.update(z).unwrap();
+            resumed.predict().unwrap();
+            resumed.update(z).unwrap();
+
+            testlib::assert_arr1_eq(&resumed.x, &kf.x);
+            testlib::assert_arr2_eq(&resumed.P, &kf.P);
+        }
+
+        let mut other = crate::kalman::Kalman::<f64, ndarray::OwnedRepr<f64>>::new(3, 1).unwrap();
+        assert!(matches!(
+            checkpoint.restore(&mut other),
+            Err(crate::Error::CheckpointShape)
+        ));
+        assert_eq!(other.x, ndarray::Array1::zeros(3));
+    }
+}
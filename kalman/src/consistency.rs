@@ -0,0 +1,197 @@
+#![allow(non_snake_case)]
+
+use crate::Error;
+use ndarray_linalg::Solve;
+
+/// records the innovations and estimation errors of a filter run to check
+/// whether `Q` and `R` match the actual noise
+#[derive(Clone, Debug, Default)]
+pub struct ConsistencyRecorder {
+    ys: Vec<ndarray::Array1<f64>>,
+    nis: Vec<f64>,
+    nees: Vec<f64>,
+    log_likelihood: f64,
+    /// number of known states
+    dim_truth: usize,
+    dim_z: usize,
+}
+
+impl ConsistencyRecorder {
+    /// call after every update. `truth` is the actual state, if known. It
+    /// may only contain the first states, e.g. of a simulation which doesn't
+    /// know the sensor placement, then the NEES only covers those. The
+    /// estimation error is calculated using `fns_x` so angles get
+    /// normalized.
+    pub fn record<F, FNS, S>(
+        &mut self,
+        filter: &F,
+        truth: Option<&ndarray::ArrayBase<S, ndarray::Ix1>>,
+        fns_x: &FNS,
+    ) -> Result<(), Error>
+    where
+        F: crate::Filter<Elem = f64> + crate::Innovation<Elem = f64>,
+        FNS: crate::Subtract<f64>,
+        S: ndarray::Data<Elem = f64>,
+    {
+        let y = filter.y();
+        self.dim_z = y.len();
+
+        self.nis.push(y.dot(&filter.S().solve(y)?));
+        self.ys.push(y.clone());
+
+        let mean = ndarray::Array1::zeros(y.len());
+        self.log_likelihood += math::multivariate::logpdf(y, &mean, filter.S(), true)?;
+
+        if let Some(truth) = truth {
+            let x = filter.x();
+            let n = truth.len();
+            if n > x.len() {
+                return Err(Error::InvalidArgument);
+            }
+
+            // unknown states don't contribute to the error
+            let mut full = x.clone();
+            full.slice_mut(ndarray::s![..n]).assign(truth);
+            let e = fns_x.subtract(x, &full);
+            let e = e.slice(ndarray::s![..n]);
+            let P = filter.P().slice(ndarray::s![..n, ..n]);
+
+            self.nees.push(e.dot(&P.solve(&e)?));
+            self.dim_truth = n;
+        }
+
+        Ok(())
+    }
+
+    /// normalized innovation squared, one value per update
+    pub fn nis(&self) -> &[f64] {
+        &self.nis
+    }
+
+    /// normalized estimation error squared, one value per update that had a
+    /// known truth
+    pub fn nees(&self) -> &[f64] {
+        &self.nees
+    }
+
+    /// sum of the log-likelihoods of all innovations, the objective for
+    /// tuning `Q` and `R` by maximum likelihood
+    pub fn log_likelihood(&self) -> f64 {
+        self.log_likelihood
+    }
+
+    /// two-sided `confidence` interval of a single NIS value
+    pub fn nis_bounds(&self, confidence: f64) -> (f64, f64) {
+        chi2_bounds(confidence, self.dim_z as f64)
+    }
+
+    /// two-sided `confidence` interval of a single NEES value
+    pub fn nees_bounds(&self, confidence: f64) -> (f64, f64) {
+        chi2_bounds(confidence, self.dim_truth as f64)
+    }
+
+    /// normalized autocorrelation of the innovations for the lags
+    /// `0..=max_lag`. The innovations of a consistent filter are white, so
+    /// all values except the first one stay within
+    /// [autocorrelation_bound](Self::autocorrelation_bound).
+    pub fn autocorrelation(&self, max_lag: usize) -> ndarray::Array1<f64> {
+        let n = self.ys.len();
+
+        (0..=max_lag)
+            .map(|lag| {
+                if lag >= n {
+                    return f64::NAN;
+                }
+
+                let mut num = 0.0;
+                let mut den_a = 0.0;
+                let mut den_b = 0.0;
+                for k in 0..n - lag {
+                    let a = &self.ys[k];
+                    let b = &self.ys[k + lag];
+                    num += a.dot(b);
+                    den_a += a.dot(a);
+                    den_b += b.dot(b);
+                }
+
+                num / (den_a * den_b).sqrt()
+            })
+            .collect()
+    }
+
+    /// two-sided `confidence` interval of the autocorrelation of white noise
+    pub fn autocorrelation_bound(&self, confidence: f64) -> f64 {
+        math::stats::norm_ppf(0.5 + confidence / 2.0) / (self.ys.len() as f64).sqrt()
+    }
+}
+
+fn chi2_bounds(confidence: f64, dof: f64) -> (f64, f64) {
+    let alpha = 1.0 - confidence;
+    (
+        math::stats::chi2_ppf(alpha / 2.0, dof),
+        math::stats::chi2_ppf(1.0 - alpha / 2.0, dof),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Filter;
+
+    /// a filter whose noise matrices match the simulated noise has to be
+    /// consistent
+    #[test]
+    fn consistent() {
+        let dt = 0.1;
+        let sigma_z = 0.5;
+        let n = 2000;
+
+        let mut kf = crate::test_support::cv_filter(dt);
+        kf.R = ndarray::array![[sigma_z * sigma_z]];
+        kf.Q = ndarray::Array2::zeros((2, 2));
+        kf.x = ndarray::array![0.0, 1.0];
+
+        let fns = crate::test_support::Functions;
+        let mut noise = crate::test_support::randn(1);
+        let mut recorder = super::ConsistencyRecorder::default();
+        // only knows the position
+        let mut partial = super::ConsistencyRecorder::default();
+        let mut log_likelihood = 0.0;
+
+        for i in 0..n {
+            let truth = ndarray::array![(i + 1) as f64 * dt, 1.0];
+            let z = ndarray::array![truth[0] + noise.next().unwrap() * sigma_z];
+
+            kf.predict().unwrap();
+            kf.update(&z).unwrap();
+            recorder.record(&kf, Some(&truth), &fns).unwrap();
+            partial
+                .record(&kf, Some(&truth.slice(ndarray::s![..1])), &fns)
+                .unwrap();
+            log_likelihood += kf.likelihood().unwrap().ln();
+        }
+
+        assert_eq!(recorder.nis().len(), n);
+        assert!((recorder.log_likelihood() - log_likelihood).abs() < 1.0e-6);
+        assert_eq!(recorder.nees().len(), n);
+        assert_eq!(partial.nees().len(), n);
+        assert_eq!(partial.nees_bounds(0.95), super::chi2_bounds(0.95, 1.0));
+        assert!(partial
+            .nees()
+            .iter()
+            .zip(recorder.nees())
+            .all(|(p, full)| *p <= *full + 1.0e-9));
+
+        // the average of n values is chi-square distributed with n * dim
+        // degrees of freedom
+        let avg_nis = recorder.nis().iter().sum::<f64>() / n as f64;
+        let lo = math::stats::chi2_ppf(0.0005, n as f64) / n as f64;
+        let hi = math::stats::chi2_ppf(0.9995, n as f64) / n as f64;
+        assert!(avg_nis > lo && avg_nis < hi, "{avg_nis} not in {lo}..{hi}");
+
+        let acf = recorder.autocorrelation(10);
+        assert!((acf[0] - 1.0).abs() < 1.0e-12);
+        let bound = recorder.autocorrelation_bound(0.999);
+        let outside = acf.iter().skip(1).filter(|v| v.abs() > bound).count();
+        assert!(outside <= 1);
+    }
+}
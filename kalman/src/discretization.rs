@@ -1,5 +1,6 @@
 use crate::Error;
 use ndarray::array;
+use ndarray::s;
 
 #[macro_export]
 macro_rules! num2t {
@@ -57,6 +58,155 @@ where
     } * var)
 }
 
+/// process noise of a state and its derivatives, driven by continuous white
+/// noise on the highest derivative with the power spectral density
+/// `spectral_density`
+#[allow(non_snake_case)]
+pub fn Q_continuous_white_noise<A>(
+    dim: usize,
+    dt: A,
+    spectral_density: A,
+) -> Result<ndarray::Array2<A>, Error>
+where
+    A: num_traits::float::Float + ndarray::ScalarOperand,
+{
+    if dim == 0 {
+        return Err(Error::InvalidArgument);
+    }
+
+    let mut factorials = Vec::with_capacity(dim);
+    let mut f = A::one();
+    for k in 0..dim {
+        if k > 0 {
+            f = f * num2t!(A, k);
+        }
+        factorials.push(f);
+    }
+
+    let mut Q = ndarray::Array2::zeros((dim, dim));
+    for i in 0..dim {
+        for j in 0..dim {
+            let p = 2 * dim - 1 - i - j;
+            Q[(i, j)] = dt.powi(p as i32)
+                / (factorials[dim - 1 - i] * factorials[dim - 1 - j] * num2t!(A, p));
+        }
+    }
+
+    Ok(Q * spectral_density)
+}
+
+/// discretizes `dx/dt = F x + w`, where `w` is white noise with the spectral
+/// density `Qc`, using Van Loan's method. Returns the state transition matrix
+/// and the process noise matrix of the time step `dt`.
+#[allow(non_snake_case)]
+pub fn van_loan<A>(
+    F: &ndarray::Array2<A>,
+    Qc: &ndarray::Array2<A>,
+    dt: A,
+) -> Result<(ndarray::Array2<A>, ndarray::Array2<A>), Error>
+where
+    A: num_traits::float::Float + ndarray::ScalarOperand + ndarray_linalg::Lapack,
+{
+    let n = F.nrows();
+    if !F.is_square() || Qc.dim() != (n, n) {
+        return Err(Error::InvalidArgument);
+    }
+
+    let mut M = ndarray::Array2::zeros((2 * n, 2 * n));
+    M.slice_mut(s![..n, ..n]).assign(&F.mapv(|v| -v * dt));
+    M.slice_mut(s![..n, n..]).assign(&(Qc * dt));
+    M.slice_mut(s![n.., n..]).assign(&(&F.t() * dt));
+    let E = math::expm(&M)?;
+
+    let Phi = E.slice(s![n.., n..]).t().to_owned();
+    let Q = Phi.dot(&E.slice(s![..n, n..]));
+    // remove the asymmetry caused by rounding errors
+    let half = A::one() / (A::one() + A::one());
+    let Q = (&Q + &Q.t()) * half;
+
+    Ok((Phi, Q))
+}
+
+/// process noise of a group of states
+#[derive(Clone, Debug)]
+#[allow(non_snake_case)]
+pub enum NoiseBlock<A> {
+    /// see [Q_discrete_white_noise], the value is the variance
+    DiscreteWhiteNoise(A),
+    /// see [Q_continuous_white_noise], the value is the spectral density
+    ContinuousWhiteNoise(A),
+    /// see [van_loan]
+    VanLoan {
+        F: ndarray::Array2<A>,
+        Qc: ndarray::Array2<A>,
+    },
+    /// doesn't depend on the time step
+    Constant(ndarray::Array2<A>),
+}
+
+/// process noise matrix made of independent blocks of states. States which
+/// aren't part of any block don't get process noise.
+#[derive(Clone, Debug)]
+pub struct BlockDiagonal<A> {
+    dim: usize,
+    blocks: Vec<(std::ops::Range<usize>, NoiseBlock<A>)>,
+}
+
+impl<A> BlockDiagonal<A>
+where
+    A: num_traits::float::Float + ndarray::ScalarOperand + ndarray_linalg::Lapack,
+{
+    pub fn new(dim: usize) -> Self {
+        Self {
+            dim,
+            blocks: Vec::new(),
+        }
+    }
+
+    /// fails if `states` is empty, out of bounds or overlaps with another
+    /// block
+    pub fn with_block(
+        mut self,
+        states: std::ops::Range<usize>,
+        block: NoiseBlock<A>,
+    ) -> Result<Self, Error> {
+        if states.is_empty()
+            || states.end > self.dim
+            || self
+                .blocks
+                .iter()
+                .any(|(other, _)| states.start < other.end && other.start < states.end)
+        {
+            return Err(Error::InvalidArgument);
+        }
+
+        self.blocks.push((states, block));
+        Ok(self)
+    }
+
+    #[allow(non_snake_case)]
+    pub fn Q(&self, dt: A) -> Result<ndarray::Array2<A>, Error> {
+        let mut Q = ndarray::Array2::zeros((self.dim, self.dim));
+
+        for (states, block) in &self.blocks {
+            let n = states.len();
+            let Qb = match block {
+                NoiseBlock::DiscreteWhiteNoise(var) => Q_discrete_white_noise(n, dt, *var)?,
+                NoiseBlock::ContinuousWhiteNoise(q) => Q_continuous_white_noise(n, dt, *q)?,
+                NoiseBlock::VanLoan { F, Qc } => van_loan(F, Qc, dt)?.1,
+                NoiseBlock::Constant(Qb) => Qb.clone(),
+            };
+            if Qb.dim() != (n, n) {
+                return Err(Error::InvalidArgument);
+            }
+
+            Q.slice_mut(s![states.clone(), states.clone()]).assign(&Qb);
+        }
+
+        Ok(Q)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -72,4 +222,81 @@ mod tests {
             &array![[0.25, 0.5, 0.5], [0.5, 1.0, 1.0], [0.5, 1.0, 1.0]],
         );
     }
+
+    #[test]
+    fn continuous_white_noise() {
+        let dt: f64 = 0.5;
+        let q = Q_continuous_white_noise(2, dt, 2.0).unwrap();
+        testlib::assert_arr2_eq(
+            &q,
+            &(array![[dt.powi(3) / 3.0, dt.powi(2) / 2.0], [dt.powi(2) / 2.0, dt]] * 2.0),
+        );
+
+        let q = Q_continuous_white_noise(3, dt, 1.0).unwrap();
+        assert!((q[(0, 0)] - dt.powi(5) / 20.0).abs() < 1.0e-12);
+        assert!((q[(1, 2)] - dt.powi(2) / 2.0).abs() < 1.0e-12);
+
+        assert!(Q_continuous_white_noise(0, dt, 1.0).is_err());
+    }
+
+    /// Van Loan's method has to match the closed form solutions
+    #[test]
+    #[allow(non_snake_case)]
+    fn van_loan() {
+        let dt: f64 = 0.1;
+        let q = 3.0;
+
+        let F = array![[0.0, 1.0], [0.0, 0.0]];
+        let Qc = array![[0.0, 0.0], [0.0, q]];
+        let (Phi, Q) = super::van_loan(&F, &Qc, dt).unwrap();
+        testlib::assert_arr2_eq(&Phi, &array![[1.0, dt], [0.0, 1.0]]);
+        testlib::assert_arr2_eq(&Q, &Q_continuous_white_noise(2, dt, q).unwrap());
+
+        let F = array![[0.0, 1.0, 0.0], [0.0, 0.0, 1.0], [0.0, 0.0, 0.0]];
+        let mut Qc = ndarray::Array2::zeros((3, 3));
+        Qc[(2, 2)] = q;
+        let (_, Q) = super::van_loan(&F, &Qc, dt).unwrap();
+        testlib::assert_arr2_eq(&Q, &Q_continuous_white_noise(3, dt, q).unwrap());
+
+        assert!(super::van_loan(&F, &array![[1.0]], dt).is_err());
+    }
+
+    #[test]
+    fn block_diagonal() {
+        let dt: f64 = 0.1;
+        let blocks = BlockDiagonal::new(6)
+            .with_block(0..2, NoiseBlock::DiscreteWhiteNoise(2.0))
+            .unwrap()
+            .with_block(3..5, NoiseBlock::ContinuousWhiteNoise(3.0))
+            .unwrap()
+            .with_block(5..6, NoiseBlock::Constant(array![[4.0]]))
+            .unwrap();
+        let q = blocks.Q(dt).unwrap();
+
+        testlib::assert_arr2_eq(
+            &q.slice(s![0..2, 0..2]),
+            &Q_discrete_white_noise(2, dt, 2.0).unwrap(),
+        );
+        testlib::assert_arr2_eq(
+            &q.slice(s![3..5, 3..5]),
+            &Q_continuous_white_noise(2, dt, 3.0).unwrap(),
+        );
+        assert_eq!(q[(5, 5)], 4.0);
+        // states without a block and the blocks are uncorrelated
+        assert_eq!(q.row(2).sum(), 0.0);
+        assert_eq!(q.slice(s![0..2, 3..6]).sum(), 0.0);
+        assert_eq!(q.slice(s![3..5, 0..2]).sum(), 0.0);
+
+        assert!(blocks
+            .clone()
+            .with_block(1..3, NoiseBlock::DiscreteWhiteNoise(1.0))
+            .is_err());
+        assert!(blocks
+            .clone()
+            .with_block(5..7, NoiseBlock::DiscreteWhiteNoise(1.0))
+            .is_err());
+        assert!(blocks
+            .with_block(2..2, NoiseBlock::DiscreteWhiteNoise(1.0))
+            .is_err());
+    }
 }
@@ -0,0 +1,379 @@
+#![allow(non_snake_case)]
+
+use crate::ukf::ApplyDt;
+use crate::ukf::Fx;
+use crate::ukf::Hx;
+use crate::Error;
+use crate::Filter;
+use crate::SetDt;
+use ndarray_linalg::solve::Inverse;
+
+/// analytic jacobian of [Fx]
+pub trait FxJacobian<A> {
+    type Elem;
+
+    fn fx_jacobian<S>(
+        &self,
+        x: &ndarray::ArrayBase<S, ndarray::Ix1>,
+        args: &A,
+    ) -> ndarray::Array2<Self::Elem>
+    where
+        S: ndarray::Data<Elem = Self::Elem>;
+}
+
+/// analytic jacobian of [Hx]
+pub trait HxJacobian {
+    type Elem;
+
+    fn hx_jacobian<S>(
+        &self,
+        x: &ndarray::ArrayBase<S, ndarray::Ix1>,
+    ) -> ndarray::Array2<Self::Elem>
+    where
+        S: ndarray::Data<Elem = Self::Elem>;
+}
+
+/// linearization of the state and measurement functions
+pub trait Jacobians<FNSX, ARGSFX, FNSZ, A> {
+    fn F(&self, fns_x: &FNSX, x: &ndarray::Array1<A>, args: &ARGSFX) -> ndarray::Array2<A>;
+    fn H(&self, fns_x: &FNSX, fns_z: &FNSZ, x: &ndarray::Array1<A>) -> ndarray::Array2<A>;
+}
+
+/// uses [FxJacobian] and [HxJacobian] of the state functions
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AnalyticJacobians;
+
+impl<FNSX, ARGSFX, FNSZ, A> Jacobians<FNSX, ARGSFX, FNSZ, A> for AnalyticJacobians
+where
+    FNSX: FxJacobian<ARGSFX, Elem = A> + HxJacobian<Elem = A>,
+{
+    fn F(&self, fns_x: &FNSX, x: &ndarray::Array1<A>, args: &ARGSFX) -> ndarray::Array2<A> {
+        fns_x.fx_jacobian(x, args)
+    }
+
+    fn H(&self, fns_x: &FNSX, _fns_z: &FNSZ, x: &ndarray::Array1<A>) -> ndarray::Array2<A> {
+        fns_x.hx_jacobian(x)
+    }
+}
+
+/// forward differences, differences of angles get normalized using
+/// [Subtract](crate::Subtract)
+#[derive(Clone, Copy, Debug)]
+pub struct FiniteDifferenceJacobians<A> {
+    /// step size relative to the magnitude of each state, but at least
+    /// `eps` itself
+    pub eps: A,
+}
+
+impl<A: num_traits::float::Float> Default for FiniteDifferenceJacobians<A> {
+    fn default() -> Self {
+        Self {
+            eps: A::epsilon().sqrt(),
+        }
+    }
+}
+
+impl<A: num_traits::float::Float> FiniteDifferenceJacobians<A> {
+    fn jacobian<F, D>(&self, x: &ndarray::Array1<A>, f: F, diff: D) -> ndarray::Array2<A>
+    where
+        F: Fn(&ndarray::Array1<A>) -> ndarray::Array1<A>,
+        D: Fn(&ndarray::Array1<A>, &ndarray::Array1<A>) -> ndarray::Array1<A>,
+    {
+        let y0 = f(x);
+        let mut J = ndarray::Array2::zeros((y0.len(), x.len()));
+
+        for i in 0..x.len() {
+            let h = self.eps * x[i].abs().max(A::one());
+            let mut xh = x.clone();
+            xh[i] = xh[i] + h;
+
+            let dy = diff(&f(&xh), &y0).mapv(|v| v / h);
+            J.column_mut(i).assign(&dy);
+        }
+
+        J
+    }
+}
+
+impl<FNSX, ARGSFX, FNSZ, A> Jacobians<FNSX, ARGSFX, FNSZ, A> for FiniteDifferenceJacobians<A>
+where
+    FNSX: Fx<ARGSFX, Elem = A> + Hx<Elem = A> + crate::Subtract<A>,
+    FNSZ: crate::Subtract<A>,
+    A: num_traits::float::Float,
+{
+    fn F(&self, fns_x: &FNSX, x: &ndarray::Array1<A>, args: &ARGSFX) -> ndarray::Array2<A> {
+        self.jacobian(x, |x| fns_x.fx(x, args), |a, b| fns_x.subtract(a, b))
+    }
+
+    fn H(&self, fns_x: &FNSX, fns_z: &FNSZ, x: &ndarray::Array1<A>) -> ndarray::Array2<A> {
+        self.jacobian(x, |x| fns_x.hx(x), |a, b| fns_z.subtract(a, b))
+    }
+}
+
+/// extended kalman filter, uses the same state functions as
+/// [Ukf](crate::ukf::Ukf)
+#[derive(Clone, Debug)]
+pub struct Ekf<FNSX, ARGSFX, FNSZ, J, A, Sz> {
+    fns_x: FNSX,
+    args_fx: ARGSFX,
+    fns_z: FNSZ,
+    jacobians: J,
+
+    // state
+    pub x: ndarray::Array1<A>,
+    pub P: ndarray::Array2<A>,
+    pub Q: ndarray::Array2<A>,
+    /// jacobian of the last predict
+    pub F: ndarray::Array2<A>,
+
+    // observation
+    pub R: ndarray::Array2<A>,
+    /// jacobian of the last update
+    pub H: ndarray::Array2<A>,
+    pub y: ndarray::Array1<A>,
+    pub S: ndarray::Array2<A>,
+
+    pub gate: Option<crate::gating::Gate<A>>,
+    gated: bool,
+
+    pd_Sz: std::marker::PhantomData<Sz>,
+}
+
+impl<FNSX, ARGSFX, FNSZ, J, A, Sz> SetDt<A> for Ekf<FNSX, ARGSFX, FNSZ, J, A, Sz>
+where
+    ARGSFX: SetDt<A> + ApplyDt<A>,
+{
+    fn set_dt(&mut self, dt: &A) {
+        self.args_fx.set_dt(dt);
+        self.args_fx.apply_dt(&mut self.Q);
+    }
+}
+
+impl<FNSX, ARGSFX, FNSZ, J, A, Sz> Filter for Ekf<FNSX, ARGSFX, FNSZ, J, A, Sz>
+where
+    FNSX: Fx<ARGSFX, Elem = A> + Hx<Elem = A> + crate::Add<A>,
+    FNSZ: crate::Subtract<A>,
+    J: Jacobians<FNSX, ARGSFX, FNSZ, A>,
+    A: Copy
+        + num_traits::float::FloatConst
+        + num_traits::float::Float
+        + ndarray::ScalarOperand
+        + ndarray_linalg::types::Lapack
+        + std::convert::From<f32>,
+    <A as ndarray_linalg::Scalar>::Real: std::convert::From<f32>,
+    Sz: ndarray::Data<Elem = A>,
+{
+    type Elem = A;
+    type Meas = ndarray::ArrayBase<Sz, ndarray::Ix1>;
+
+    fn predict(&mut self) -> Result<(), crate::Error> {
+        self.F = self.jacobians.F(&self.fns_x, &self.x, &self.args_fx);
+        self.x = self.fns_x.fx(&self.x, &self.args_fx);
+        self.P = self.F.dot(&self.P).dot(&self.F.t()) + &self.Q;
+
+        Ok(())
+    }
+
+    fn update(&mut self, z: &ndarray::ArrayBase<Sz, ndarray::Ix1>) -> Result<(), crate::Error> {
+        self.H = self.jacobians.H(&self.fns_x, &self.fns_z, &self.x);
+        self.y = self.fns_z.subtract(z, &self.fns_x.hx(&self.x));
+
+        let PHT = self.P.dot(&self.H.t());
+        self.S = self.H.dot(&PHT) + &self.R;
+
+        let R_scale = crate::gating::apply(self.gate.as_ref(), &self.y, &mut self.S, &self.R)?;
+        self.gated = R_scale != Some(A::one());
+        let R_scale = match R_scale {
+            Some(v) => v,
+            // rejected
+            None => return Ok(()),
+        };
+
+        let K = PHT.dot(&self.S.inv()?);
+
+        self.x = self.fns_x.add(&self.x, &K.dot(&self.y));
+
+        // joseph form, stays symmetric and positive definite
+        let I_KH = ndarray::Array2::<A>::eye(self.x.len()) - K.dot(&self.H);
+        self.P = &I_KH.dot(&self.P).dot(&I_KH.t()) + &(K.dot(&self.R).dot(&K.t()) * R_scale);
+
+        Ok(())
+    }
+
+    fn likelihood(&self) -> Result<A, Error> {
+        let ll = self.log_likelihood()?;
+        let mut l = num_traits::Float::exp(ll);
+        if l.is_zero() {
+            l = A::min_positive_value();
+        }
+
+        Ok(l)
+    }
+
+    fn x(&self) -> &ndarray::Array1<A> {
+        &self.x
+    }
+
+    fn x_mut(&mut self) -> &mut ndarray::Array1<A> {
+        &mut self.x
+    }
+
+    fn P(&self) -> &ndarray::Array2<A> {
+        &self.P
+    }
+
+    fn P_mut(&mut self) -> &mut ndarray::Array2<A> {
+        &mut self.P
+    }
+}
+
+impl<FNSX, ARGSFX, FNSZ, J, A, Sz> crate::Q for Ekf<FNSX, ARGSFX, FNSZ, J, A, Sz> {
+    type Elem = A;
+
+    fn Q(&self) -> &ndarray::Array2<A> {
+        &self.Q
+    }
+
+    fn Q_mut(&mut self) -> &mut ndarray::Array2<A> {
+        &mut self.Q
+    }
+}
+
+impl<FNSX, ARGSFX, FNSZ, J, A, Sz> crate::R for Ekf<FNSX, ARGSFX, FNSZ, J, A, Sz> {
+    type Elem = A;
+
+    fn R(&self) -> &ndarray::Array2<A> {
+        &self.R
+    }
+
+    fn R_mut(&mut self) -> &mut ndarray::Array2<A> {
+        &mut self.R
+    }
+}
+
+impl<FNSX, ARGSFX, FNSZ, J, A, Sz> crate::Innovation for Ekf<FNSX, ARGSFX, FNSZ, J, A, Sz> {
+    type Elem = A;
+
+    fn y(&self) -> &ndarray::Array1<A> {
+        &self.y
+    }
+
+    fn S(&self) -> &ndarray::Array2<A> {
+        &self.S
+    }
+}
+
+impl<FNSX, ARGSFX, FNSZ, J, A, Sz> crate::gating::Gating for Ekf<FNSX, ARGSFX, FNSZ, J, A, Sz> {
+    type Elem = A;
+
+    fn gate(&self) -> Option<&crate::gating::Gate<A>> {
+        self.gate.as_ref()
+    }
+
+    fn set_gate(&mut self, gate: Option<crate::gating::Gate<A>>) {
+        self.gate = gate;
+    }
+
+    fn gated(&self) -> bool {
+        self.gated
+    }
+}
+
+impl<FNSX, ARGSFX, FNSZ, J, A, Sz> Ekf<FNSX, ARGSFX, FNSZ, J, A, Sz>
+where
+    A: Copy
+        + num_traits::float::FloatConst
+        + num_traits::float::Float
+        + ndarray::ScalarOperand
+        + ndarray_linalg::types::Lapack
+        + std::convert::From<f32>,
+    <A as ndarray_linalg::Scalar>::Real: std::convert::From<f32>,
+{
+    pub fn new(
+        dim_x: usize,
+        dim_z: usize,
+        fns_x: FNSX,
+        args_fx: ARGSFX,
+        fns_z: FNSZ,
+        jacobians: J,
+    ) -> Self {
+        Self {
+            fns_x,
+            args_fx,
+            fns_z,
+            jacobians,
+
+            x: ndarray::Array::zeros(dim_x),
+            P: ndarray::Array::eye(dim_x),
+            Q: ndarray::Array::eye(dim_x),
+            F: ndarray::Array::eye(dim_x),
+
+            R: ndarray::Array::eye(dim_z),
+            H: ndarray::Array::zeros((dim_z, dim_x)),
+            y: ndarray::Array::zeros(dim_z),
+            S: ndarray::Array::zeros((dim_z, dim_z)),
+
+            gate: None,
+            gated: false,
+
+            pd_Sz: std::marker::PhantomData,
+        }
+    }
+
+    /// log-likelihood of the last measurement
+    pub fn log_likelihood(&self) -> Result<A, Error> {
+        let mean = ndarray::Array1::zeros(self.y.len());
+        Ok(math::multivariate::logpdf(&self.y, &mean, &self.S, true)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_support::Args;
+    use crate::test_support::Functions;
+    use crate::Filter;
+
+    /// a linear system has to give the same results as the linear filter
+    #[test]
+    fn linear() {
+        let dt = 0.1;
+
+        let mut kf = crate::test_support::cv_filter(dt);
+
+        let mut analytic = super::Ekf::new(
+            2,
+            1,
+            Functions,
+            Args { dt },
+            Functions,
+            super::AnalyticJacobians,
+        );
+        let mut fd = super::Ekf::new(
+            2,
+            1,
+            Functions,
+            Args { dt },
+            Functions,
+            super::FiniteDifferenceJacobians::default(),
+        );
+
+        for i in 0..50 {
+            let z = ndarray::array![(i as f64 * dt).powi(2)];
+
+            kf.predict().unwrap();
+            kf.update(&z).unwrap();
+            analytic.predict().unwrap();
+            analytic.update(&z).unwrap();
+            fd.predict().unwrap();
+            fd.update(&z).unwrap();
+
+            testlib::assert_arr1_eq(&analytic.x, &kf.x);
+            testlib::assert_arr2_eq(&analytic.P, &kf.P);
+            testlib::assert_arr1_eq(&fd.x, &kf.x);
+            testlib::assert_arr2_eq(&fd.P, &kf.P);
+        }
+
+        let diff = analytic.likelihood().unwrap() - kf.likelihood().unwrap();
+        assert!(diff.abs() < 1.0e-6);
+    }
+}
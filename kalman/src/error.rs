@@ -13,4 +13,10 @@ pub enum Error {
     DifferentFilterShapes,
     #[error("not enough filters")]
     NotEnoughFilters,
+    #[error("cholesky downdate lost positive definiteness")]
+    CholeskyDowndate,
+    #[error("all particle weights are zero")]
+    DegenerateWeights,
+    #[error("checkpoint doesn't match the filter")]
+    CheckpointShape,
 }
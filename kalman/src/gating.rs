@@ -0,0 +1,146 @@
+#![allow(non_snake_case)]
+
+use crate::Error;
+use ndarray_linalg::Solve;
+
+/// what happens to measurements outside of the gate
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GateAction {
+    /// skip the update
+    #[default]
+    Reject,
+    /// scale `R` by the ratio of the squared mahalanobis distance and the
+    /// threshold
+    InflateR,
+}
+
+/// mahalanobis distance gate for the innovation of an update
+#[derive(Clone, Copy, Debug)]
+pub struct Gate<A> {
+    /// maximum squared mahalanobis distance
+    pub threshold: A,
+    pub action: GateAction,
+}
+
+impl Gate<f64> {
+    /// a valid measurement with `dim_z` dimensions passes the gate with the
+    /// given `probability`
+    pub fn with_probability(probability: f64, dim_z: usize, action: GateAction) -> Self {
+        Self {
+            threshold: math::stats::chi2_ppf(probability, dim_z as f64),
+            action,
+        }
+    }
+}
+
+impl<A> Gate<A>
+where
+    A: num_traits::float::Float + ndarray::ScalarOperand + ndarray_linalg::types::Lapack,
+{
+    /// returns the factor `R` has to be scaled with, `None` if the
+    /// measurement has to be rejected
+    pub fn check<Sy, Ss>(
+        &self,
+        y: &ndarray::ArrayBase<Sy, ndarray::Ix1>,
+        S: &ndarray::ArrayBase<Ss, ndarray::Ix2>,
+    ) -> Result<Option<A>, Error>
+    where
+        Sy: ndarray::Data<Elem = A>,
+        Ss: ndarray::Data<Elem = A>,
+    {
+        let d2 = y.dot(&S.solve(y)?);
+        if d2 <= self.threshold {
+            return Ok(Some(A::one()));
+        }
+
+        Ok(match self.action {
+            GateAction::Reject => None,
+            GateAction::InflateR => Some(d2 / self.threshold),
+        })
+    }
+}
+
+/// filters which can gate their updates
+pub trait Gating {
+    type Elem;
+
+    fn gate(&self) -> Option<&Gate<Self::Elem>>;
+    fn set_gate(&mut self, gate: Option<Gate<Self::Elem>>);
+
+    /// the last measurement was rejected or its `R` was inflated
+    fn gated(&self) -> bool;
+}
+
+/// applies `gate` to an update, `S` gets adjusted if `R` has to be inflated.
+/// Returns the factor `R` has to be scaled with, `None` if the measurement
+/// has to be rejected.
+pub(crate) fn apply<A>(
+    gate: Option<&Gate<A>>,
+    y: &ndarray::Array1<A>,
+    S: &mut ndarray::Array2<A>,
+    R: &ndarray::Array2<A>,
+) -> Result<Option<A>, Error>
+where
+    A: num_traits::float::Float + ndarray::ScalarOperand + ndarray_linalg::types::Lapack,
+{
+    let scale = match gate {
+        Some(gate) => gate.check(y, S)?,
+        None => Some(A::one()),
+    };
+
+    if let Some(scale) = scale {
+        if scale != A::one() {
+            *S = &*S + &(R * (scale - A::one()));
+        }
+    }
+
+    Ok(scale)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn check() {
+        let S = ndarray::array![[4.0, 0.0], [0.0, 1.0]];
+        let gate = Gate::with_probability(0.99, 2, GateAction::InflateR);
+
+        assert_eq!(
+            gate.check(&ndarray::array![2.0, 1.0], &S).unwrap(),
+            Some(1.0)
+        );
+
+        // squared distance of 100
+        let scale = gate.check(&ndarray::array![0.0, 10.0], &S).unwrap();
+        assert!((scale.unwrap() - 100.0 / gate.threshold).abs() < 1.0e-12);
+
+        let gate = Gate {
+            action: GateAction::Reject,
+            ..gate
+        };
+        assert_eq!(gate.check(&ndarray::array![0.0, 10.0], &S).unwrap(), None);
+    }
+
+    #[test]
+    fn reject() {
+        use crate::Filter;
+
+        let mut kf = crate::kalman::Kalman::<f64, _>::new(1, 1).unwrap();
+        kf.H = ndarray::array![[1.0]];
+        kf.Q = ndarray::array![[0.0]];
+        kf.set_gate(Some(Gate::with_probability(0.999, 1, GateAction::Reject)));
+
+        kf.predict().unwrap();
+        kf.update(&ndarray::array![0.5]).unwrap();
+        assert!(!kf.gated());
+        let x = kf.x.clone();
+        let P = kf.P.clone();
+
+        kf.predict().unwrap();
+        kf.update(&ndarray::array![100.0]).unwrap();
+        assert!(kf.gated());
+        assert_eq!(kf.x, x);
+        assert_eq!(kf.P, P);
+    }
+}
@@ -1,21 +1,24 @@
 #![allow(non_snake_case)]
 use std::ops::DivAssign;
 
-#[derive(Debug)]
-pub struct Imm<'a, FNS, A, FR, F: ?Sized, MU, M, Sz> {
+/// interacting multiple model estimator. Runs a bank of filters with
+/// different models and mixes their estimates by the probability of each
+/// model.
+#[derive(Clone, Debug)]
+pub struct Imm<F, FNS, A> {
     fns: FNS,
 
     /// List of N filters. filters[i] is the ith Kalman filter in the IMM
     /// estimator
-    filters: &'a mut [FR],
+    filters: Vec<F>,
 
     /// mode probability: mu[i] is the probability that filter i is the correct
     /// one
-    mu: &'a mut MU,
+    mu: ndarray::Array1<A>,
 
     /// Markov chain transition matrix. M[i,j] is the probability of switching
-    /// from filter j to filter i
-    M: &'a M,
+    /// from filter i to filter j
+    M: ndarray::Array2<A>,
 
     /// Current state estimate. Any call to update() or predict() updates this
     /// variable
@@ -28,6 +31,9 @@ pub struct Imm<'a, FNS, A, FR, F: ?Sized, MU, M, Sz> {
     /// Likelihood of each individual filter's last measurement
     likelihood: ndarray::Array1<A>,
 
+    /// likelihood of the last measurement, given all filters
+    total_likelihood: A,
+
     /// Mixing probabilitity - omega[i, j] is the probabilility of mixing the
     /// state of filter i into filter j. Perhaps more understandably, it weights
     /// the states of each filter by:
@@ -38,82 +44,74 @@ pub struct Imm<'a, FNS, A, FR, F: ?Sized, MU, M, Sz> {
     /// Total probability, after interaction, that the target is in state j.
     /// We use it as the # normalization constant.
     cbar: ndarray::Array1<A>,
+}
+
+/// combines the states of several filters weighted by `mu`
+pub fn mix<'b, FNS, A, I, S>(
+    fns: &FNS,
+    states: I,
+    mu: &ndarray::ArrayBase<S, ndarray::Ix1>,
+) -> (ndarray::Array1<A>, ndarray::Array2<A>)
+where
+    FNS: crate::Add<A> + crate::Subtract<A>,
+    I: Iterator<Item = (&'b ndarray::Array1<A>, &'b ndarray::Array2<A>)> + Clone,
+    S: ndarray::Data<Elem = A>,
+    A: 'b + num_traits::float::Float + ndarray::ScalarOperand + std::ops::AddAssign,
+{
+    let (x0, P0) = states.clone().next().expect("no states to mix");
+
+    let mut x = ndarray::Array1::<A>::zeros(x0.dim());
+    for ((xi, _), &wi) in states.clone().zip(mu) {
+        x = fns.add(&x, &(xi * wi));
+    }
+
+    let mut P = ndarray::Array2::<A>::zeros(P0.dim());
+    for ((xi, Pi), &wi) in states.zip(mu) {
+        let y = fns.subtract(xi, &x);
+        P += &((math::outer_product(&y, &y) + Pi) * wi);
+    }
 
-    pd_Sz: std::marker::PhantomData<Sz>,
-    pd_F: std::marker::PhantomData<F>,
+    (x, P)
 }
 
-impl<'a, FNS, A, FR, F, MU, M, Sz, T> crate::SetDt<T> for Imm<'a, FNS, A, FR, F, MU, M, Sz>
+impl<F, FNS, A, T> crate::SetDt<T> for Imm<F, FNS, A>
 where
-    FR: AsMut<F>,
-    F: crate::SetDt<T> + ?Sized,
+    F: crate::SetDt<T>,
 {
     fn set_dt(&mut self, dt: &T) {
-        for filter in &mut *self.filters {
-            filter.as_mut().set_dt(dt);
+        for filter in &mut self.filters {
+            filter.set_dt(dt);
         }
     }
 }
 
-impl<'a, FNS, A, FR, F, Smu, SM, Sz> crate::Filter
-    for Imm<
-        'a,
-        FNS,
-        A,
-        FR,
-        F,
-        ndarray::ArrayBase<Smu, ndarray::Ix1>,
-        ndarray::ArrayBase<SM, ndarray::Ix2>,
-        Sz,
-    >
+impl<F, FNS, A> crate::Filter for Imm<F, FNS, A>
 where
     FNS: crate::Add<A> + crate::Subtract<A>,
-    FR: std::borrow::Borrow<F> + std::borrow::BorrowMut<F>,
-    F: crate::Filter<Elem = A, Meas = ndarray::ArrayBase<Sz, ndarray::Ix1>> + ?Sized,
-    Smu: ndarray::DataMut<Elem = A>,
-    SM: ndarray::Data<Elem = A>,
+    F: crate::Filter<Elem = A>,
     A: num_traits::float::Float
         + ndarray::ScalarOperand
         + std::ops::DivAssign
-        + std::ops::AddAssign
-        + std::convert::From<f32>
-        + std::fmt::Display,
-    Sz: ndarray::Data<Elem = A>,
+        + std::ops::AddAssign,
 {
     type Elem = A;
-    type Meas = ndarray::ArrayBase<Sz, ndarray::Ix1>;
+    type Meas = F::Meas;
 
     fn predict(&mut self) -> Result<(), crate::Error> {
-        let N = self.filters.len();
-
         // compute mixed initial conditions
-        let mut xs = Vec::with_capacity(N);
-        let mut Ps = Vec::with_capacity(N);
-        for w in self.omega.t().rows() {
-            let mut x = ndarray::Array1::<A>::zeros(self.x.dim());
-            ndarray::azip!((kf in &*self.filters, &wj in &w) {
-                x = self.fns.add(&x, &(kf.borrow().x() * wj));
-            });
-
-            let mut P = ndarray::Array2::<A>::zeros(self.P.dim());
-            ndarray::azip!((kf in &*self.filters, &wj in &w) {
-                let kf = kf.borrow();
-
-                let y = self.fns.subtract(kf.x(), &x);
-                P += &((math::outer_product(&y, &y) + kf.P()) * wj);
-            });
-
-            xs.push(x);
-            Ps.push(P);
-        }
+        let mixed: Vec<_> = self
+            .omega
+            .t()
+            .rows()
+            .into_iter()
+            .map(|w| mix(&self.fns, self.filters.iter().map(|f| (f.x(), f.P())), &w))
+            .collect();
 
         // compute each filter's prior using the mixed initial conditions
-        for (i, f) in self.filters.iter_mut().enumerate() {
-            let f = f.borrow_mut();
-
+        for (f, (x, P)) in self.filters.iter_mut().zip(mixed) {
             // propagate using the mixed state estimate and covariance
-            f.x_mut().assign(&xs[i]);
-            f.P_mut().assign(&Ps[i]);
+            f.x_mut().assign(&x);
+            f.P_mut().assign(&P);
             f.predict()?;
         }
 
@@ -122,20 +120,19 @@ where
         Ok(())
     }
 
-    fn update(&mut self, z: &ndarray::ArrayBase<Sz, ndarray::Ix1>) -> Result<(), crate::Error> {
+    fn update(&mut self, z: &F::Meas) -> Result<(), crate::Error> {
         // run update on each filter, and save the likelihood
         for (i, f) in self.filters.iter_mut().enumerate() {
-            let f = f.borrow_mut();
-
             f.update(z)?;
             self.likelihood[i] = f.likelihood()?;
-            println!("[{}] LH={}", i, self.likelihood[i]);
         }
 
         // update mode probabilities from total probability * likelihood
-        self.mu.assign(&(&self.cbar * &self.likelihood));
+        self.mu = &self.cbar * &self.likelihood;
         // normalize
-        self.mu.div_assign(self.mu.sum());
+        let sum = self.mu.sum();
+        self.mu.div_assign(sum);
+        self.total_likelihood = sum;
 
         self.compute_mixing_probabilities();
         self.compute_state_estimate();
@@ -144,7 +141,7 @@ where
     }
 
     fn likelihood(&self) -> Result<A, crate::Error> {
-        Err(crate::Error::InvalidArgument)
+        Ok(self.total_likelihood.max(A::min_positive_value()))
     }
 
     fn x(&self) -> &ndarray::Array1<A> {
@@ -164,48 +161,40 @@ where
     }
 }
 
-impl<'a, FNS, A, FR, F, Smu, SM, Sz>
-    Imm<
-        'a,
-        FNS,
-        A,
-        FR,
-        F,
-        ndarray::ArrayBase<Smu, ndarray::Ix1>,
-        ndarray::ArrayBase<SM, ndarray::Ix2>,
-        Sz,
-    >
+impl<F, FNS, A> Imm<F, FNS, A>
 where
     FNS: crate::Add<A> + crate::Subtract<A>,
-    FR: std::borrow::Borrow<F> + std::borrow::BorrowMut<F>,
-    F: crate::Filter<Elem = A, Meas = ndarray::ArrayBase<Sz, ndarray::Ix1>> + ?Sized,
-    Smu: ndarray::DataMut<Elem = A>,
-    SM: ndarray::Data<Elem = A>,
+    F: crate::Filter<Elem = A>,
     A: num_traits::float::Float
         + ndarray::ScalarOperand
-        + std::ops::AddAssign
-        + std::convert::From<f32>,
-    Sz: ndarray::Data<Elem = A>,
+        + std::ops::DivAssign
+        + std::ops::AddAssign,
 {
+    /// `mu` are the initial mode probabilities, `M[i,j]` is the probability
+    /// of switching from filter i to filter j
     pub fn new(
-        filters: &'a mut [FR],
-        mu: &'a mut ndarray::ArrayBase<Smu, ndarray::Ix1>,
-        M: &'a ndarray::ArrayBase<SM, ndarray::Ix2>,
+        filters: Vec<F>,
+        mu: ndarray::Array1<A>,
+        M: ndarray::Array2<A>,
         fns: FNS,
     ) -> Result<Self, crate::Error> {
         if filters.len() < 2 {
             return Err(crate::Error::NotEnoughFilters);
         }
 
-        let x_dim = filters[0].borrow().x().dim();
-        for f in &*filters {
-            if f.borrow().x().dim() != x_dim {
+        let x_dim = filters[0].x().dim();
+        for f in &filters {
+            if f.x().dim() != x_dim {
                 return Err(crate::Error::DifferentFilterShapes);
             }
         }
 
         let N = filters.len();
-        let P_dim = filters[0].borrow().P().dim();
+        if mu.dim() != N || M.dim() != (N, N) {
+            return Err(crate::Error::InvalidArgument);
+        }
+
+        let P_dim = filters[0].P().dim();
         let mut o = Self {
             fns,
             filters,
@@ -214,10 +203,9 @@ where
             x: ndarray::Array::zeros(x_dim),
             P: ndarray::Array::zeros(P_dim),
             likelihood: ndarray::Array::zeros(N),
+            total_likelihood: A::zero(),
             omega: ndarray::Array::zeros((N, N)),
             cbar: ndarray::Array::zeros(N),
-            pd_Sz: std::marker::PhantomData,
-            pd_F: std::marker::PhantomData,
         };
         o.compute_mixing_probabilities();
 
@@ -230,25 +218,18 @@ where
     /// Computes the IMM's mixed state estimate from each filter using
     /// the the mode probability self.mu to weight the estimates
     fn compute_state_estimate(&mut self) {
-        let mut x = ndarray::Array::zeros(self.x.dim());
-        ndarray::azip!((f in &*self.filters, mu in &*self.mu) {
-            x = self.fns.add(&x, &(f.borrow().x() * *mu));
-        });
+        let (x, P) = mix(
+            &self.fns,
+            self.filters.iter().map(|f| (f.x(), f.P())),
+            &self.mu,
+        );
         self.x = x;
-
-        let mut P = ndarray::Array::zeros(self.P.dim());
-        ndarray::azip!((f in &*self.filters, mu in &*self.mu) {
-            let f = f.borrow();
-
-            let y = self.fns.subtract(f.x(), &self.x);
-            P += &((math::outer_product(&y, &y) + f.P()) * *mu);
-        });
         self.P = P;
     }
 
     /// Compute the mixing probability for each filter
     fn compute_mixing_probabilities(&mut self) {
-        self.cbar = self.mu.dot(self.M);
+        self.cbar = self.mu.dot(&self.M);
         let N = self.filters.len();
         for i in 0..N {
             for j in 0..N {
@@ -257,16 +238,126 @@ where
         }
     }
 
-    pub fn filters(&mut self) -> &[FR] {
-        self.filters
+    pub fn filters(&self) -> &[F] {
+        &self.filters
+    }
+
+    pub fn filters_mut(&mut self) -> &mut [F] {
+        &mut self.filters
     }
 
-    pub fn filters_mut(&mut self) -> &mut [FR] {
+    pub fn into_filters(self) -> Vec<F> {
         self.filters
     }
 
-    pub fn mu(&self) -> &ndarray::ArrayBase<Smu, ndarray::Ix1> {
-        self.mu
+    pub fn mu(&self) -> &ndarray::Array1<A> {
+        &self.mu
+    }
+
+    pub fn M(&self) -> &ndarray::Array2<A> {
+        &self.M
+    }
+
+    /// smoothed mode probabilities using Kim's backward recursion. `mus[k]`
+    /// are the mode probabilities after update `k`.
+    pub fn smooth_mu(&self, mus: &[ndarray::Array1<A>]) -> Vec<ndarray::Array1<A>> {
+        let mut smoothed: Vec<ndarray::Array1<A>> = Vec::with_capacity(mus.len());
+        let last = match mus.last() {
+            Some(v) => v,
+            None => return smoothed,
+        };
+        smoothed.push(last.clone());
+
+        for mu in mus.iter().rev().skip(1) {
+            let next = smoothed.last().unwrap();
+            let predicted = mu.dot(&self.M);
+
+            let ratio = ndarray::Zip::from(next)
+                .and(&predicted)
+                .map_collect(|&n, &p| if p > A::zero() { n / p } else { A::zero() });
+            let mut mu = mu * &self.M.dot(&ratio);
+            let sum = mu.sum();
+            mu.div_assign(sum);
+
+            smoothed.push(mu);
+        }
+
+        smoothed.reverse();
+        smoothed
+    }
+
+    /// IMM smoother. `modes[i]` are the smoothed states and covariances of
+    /// filter `i`, e.g. from its RTS smoother, `mus[k]` are the mode
+    /// probabilities after update `k`. They get combined using the smoothed
+    /// mode probabilities, which are returned as well. The interaction of
+    /// the modes isn't smoothed, that's a good approximation as long as the
+    /// modes don't switch every few steps.
+    pub fn smooth(
+        &self,
+        modes: &[crate::RTSResult<A>],
+        mus: &[ndarray::Array1<A>],
+    ) -> Result<(crate::RTSResult<A>, Vec<ndarray::Array1<A>>), crate::Error> {
+        if modes.len() != self.filters.len()
+            || modes
+                .iter()
+                .any(|(xs, Ps)| xs.len() != mus.len() || Ps.len() != mus.len())
+        {
+            return Err(crate::Error::InvalidArgument);
+        }
+
+        let mus = self.smooth_mu(mus);
+        let (xs, Ps): (Vec<_>, Vec<_>) = mus
+            .iter()
+            .enumerate()
+            .map(|(k, mu)| mix(&self.fns, modes.iter().map(|(xs, Ps)| (&xs[k], &Ps[k])), mu))
+            .unzip();
+
+        Ok(((xs, Ps), mus))
+    }
+}
+
+impl<F, FNS, A> Imm<F, FNS, A>
+where
+    FNS: crate::Add<A> + crate::Subtract<A>,
+    F: crate::Filter<Elem = A> + crate::Q<Elem = A> + crate::R<Elem = A>,
+    A: num_traits::float::Float + ndarray::ScalarOperand + std::ops::AddAssign,
+{
+    pub fn checkpoint(&self) -> crate::checkpoint::ImmCheckpoint<A> {
+        crate::checkpoint::ImmCheckpoint {
+            filters: self
+                .filters
+                .iter()
+                .map(crate::checkpoint::Checkpoint::new)
+                .collect(),
+            mu: self.mu.clone(),
+        }
+    }
+
+    /// fails without changing any filter if the shapes don't match
+    pub fn restore(
+        &mut self,
+        checkpoint: &crate::checkpoint::ImmCheckpoint<A>,
+    ) -> Result<(), crate::Error> {
+        if checkpoint.filters.len() != self.filters.len()
+            || checkpoint.mu.dim() != self.mu.dim()
+            || !checkpoint
+                .filters
+                .iter()
+                .zip(&self.filters)
+                .all(|(c, f)| c.matches(f))
+        {
+            return Err(crate::Error::CheckpointShape);
+        }
+
+        for (c, f) in checkpoint.filters.iter().zip(&mut self.filters) {
+            c.restore(f)?;
+        }
+        self.mu.assign(&checkpoint.mu);
+
+        self.compute_mixing_probabilities();
+        self.compute_state_estimate();
+
+        Ok(())
     }
 }
 
@@ -274,6 +365,7 @@ where
 mod test {
     use crate::Filter;
     use rand::Rng;
+    use rand::SeedableRng;
 
     /// simulate a moving target
     fn turning_target(N: usize, turn_start: usize) -> ndarray::Array3<f64> {
@@ -312,7 +404,7 @@ mod test {
     /// this is the sample from Roger Labbes book
     #[test]
     fn imm() {
-        let mut rng = rand::thread_rng();
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
 
         let N = 600;
         let dt = 1.0f64;
@@ -362,27 +454,115 @@ mod test {
         let mut cano = ca.clone();
         cano.Q *= 0.0;
 
-        let mut filters = vec![ca, cano];
+        let filters = vec![ca, cano];
         let M = ndarray::array![[0.97, 0.03], [0.03, 0.97]];
-        let mut mu = ndarray::array![0.5, 0.5];
-        let fns = super::super::sigma_points::tests::LinFns::default();
-        let mut bank = super::Imm::new(&mut filters, &mut mu, &M, fns).unwrap();
+        let mu = ndarray::array![0.5, 0.5];
+        let fns = crate::test_support::Functions;
+        let mut bank = super::Imm::new(filters, mu, M, fns).unwrap();
 
         let mut xs = Vec::with_capacity(zs.len());
         let mut probs = Vec::with_capacity(zs.len());
+        let mut modes = vec![(Vec::new(), Vec::new()); 2];
         for z in zs.rows().into_iter() {
             bank.predict().unwrap();
             bank.update(&z).unwrap();
 
             xs.push(bank.x().clone());
             probs.push(bank.mu().clone());
+            for (f, (xs, Ps)) in bank.filters().iter().zip(&mut modes) {
+                xs.push(f.x.clone());
+                Ps.push(f.P.clone());
+            }
+        }
 
-            /*
-            eprintln!(
-                "x={} actual={}",
-                bank.x(),
-                imm_track.index_axis(ndarray::Axis(0), i)
-            );*/
+        let modes: Vec<_> = bank
+            .filters()
+            .iter()
+            .zip(&modes)
+            .map(|(f, (xs, Ps))| f.rts_smoother(xs, Ps, None, None).unwrap())
+            .collect();
+        let ((xss, _), mus) = bank.smooth(&modes, &probs).unwrap();
+        assert_eq!(xss.len(), N);
+        for mu in &mus {
+            assert!((mu.sum() - 1.0).abs() < 1.0e-9);
         }
+
+        // the smoothed positions have to be closer to the actual ones
+        let rms = |xs: &[ndarray::Array1<f64>]| {
+            let sum: f64 = xs
+                .iter()
+                .enumerate()
+                .map(|(i, x)| {
+                    (x[0] - imm_track[(i, 0, 0)]).powi(2) + (x[3] - imm_track[(i, 2, 0)]).powi(2)
+                })
+                .sum();
+            (sum / xs.len() as f64).sqrt()
+        };
+        assert!(rms(&xss) < rms(&xs));
+    }
+
+    fn cv_imm() -> super::Imm<
+        crate::kalman::Kalman<f64, ndarray::OwnedRepr<f64>>,
+        crate::test_support::Functions,
+        f64,
+    > {
+        let mut slow = crate::test_support::cv_filter(1.0);
+        slow.Q = ndarray::array![[0.0, 0.0], [0.0, 1.0e-4]];
+        slow.R = ndarray::array![[0.25]];
+        let mut fast = slow.clone();
+        fast.Q = ndarray::array![[0.0, 0.0], [0.0, 0.1]];
+
+        let M = ndarray::array![[0.95, 0.05], [0.05, 0.95]];
+        let mu = ndarray::array![0.5, 0.5];
+        let fns = crate::test_support::Functions;
+        super::Imm::new(vec![slow, fast], mu, M, fns).unwrap()
+    }
+
+    /// a restored IMM has to continue exactly like the original one
+    #[test]
+    fn resume() {
+        let zs: Vec<_> = (0..40)
+            .map(|i| ndarray::array![(i as f64 * 0.3).powi(2)])
+            .collect();
+
+        let mut bank = cv_imm();
+        for z in &zs[..20] {
+            bank.predict().unwrap();
+            bank.update(z).unwrap();
+        }
+
+        let json = serde_json::to_string(&bank.checkpoint()).unwrap();
+        let checkpoint: crate::checkpoint::ImmCheckpoint<f64> =
+            serde_json::from_str(&json).unwrap();
+
+        let mut resumed = cv_imm();
+        resumed.restore(&checkpoint).unwrap();
+        testlib::assert_arr1_eq(resumed.mu(), bank.mu());
+        testlib::assert_arr1_eq(resumed.x(), bank.x());
+
+        for z in &zs[20..] {
+            bank.predict().unwrap();
+            bank.update(z).unwrap();
+            resumed.predict().unwrap();
+            resumed.update(z).unwrap();
+
+            testlib::assert_arr1_eq(resumed.mu(), bank.mu());
+            testlib::assert_arr1_eq(resumed.x(), bank.x());
+            testlib::assert_arr2_eq(resumed.P(), bank.P());
+        }
+
+        let mut single = crate::checkpoint::ImmCheckpoint {
+            filters: checkpoint.filters[..1].to_vec(),
+            mu: ndarray::array![1.0],
+        };
+        assert!(matches!(
+            resumed.restore(&single),
+            Err(crate::Error::CheckpointShape)
+        ));
+        single.filters = checkpoint.filters.clone();
+        assert!(matches!(
+            resumed.restore(&single),
+            Err(crate::Error::CheckpointShape)
+        ));
     }
 }
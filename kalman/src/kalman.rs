@@ -17,6 +17,9 @@ pub struct Kalman<A, Sz> {
     pub y: ndarray::Array1<A>,
     pub S: ndarray::Array2<A>,
 
+    pub gate: Option<crate::gating::Gate<A>>,
+    gated: bool,
+
     pd_Sz: std::marker::PhantomData<Sz>,
 }
 
@@ -43,17 +46,7 @@ where
     }
 
     fn update(&mut self, z: &ndarray::ArrayBase<Sz, ndarray::Ix1>) -> Result<(), crate::Error> {
-        self.y = z - &self.H.dot(&self.x);
-        let PHT = self.P.dot(&self.H.t());
-        self.S = self.H.dot(&PHT) + &self.R;
-        let SI = self.S.inv()?;
-        let K = PHT.dot(&SI);
-        self.x = &self.x + &K.dot(&self.y);
-
-        let Ix = ndarray::Array2::<A>::eye(self.dim_x);
-        let I_KH = Ix - K.dot(&self.H);
-        self.P = &I_KH.dot(&self.P).dot(&I_KH.t()) + &K.dot(&self.R).dot(&K.t());
-        Ok(())
+        self.update_model(z, None, None)
     }
 
     fn likelihood(&self) -> Result<A, crate::Error> {
@@ -83,6 +76,58 @@ where
     }
 }
 
+impl<A, Sz> crate::Q for Kalman<A, Sz> {
+    type Elem = A;
+
+    fn Q(&self) -> &ndarray::Array2<A> {
+        &self.Q
+    }
+
+    fn Q_mut(&mut self) -> &mut ndarray::Array2<A> {
+        &mut self.Q
+    }
+}
+
+impl<A, Sz> crate::R for Kalman<A, Sz> {
+    type Elem = A;
+
+    fn R(&self) -> &ndarray::Array2<A> {
+        &self.R
+    }
+
+    fn R_mut(&mut self) -> &mut ndarray::Array2<A> {
+        &mut self.R
+    }
+}
+
+impl<A, Sz> crate::Innovation for Kalman<A, Sz> {
+    type Elem = A;
+
+    fn y(&self) -> &ndarray::Array1<A> {
+        &self.y
+    }
+
+    fn S(&self) -> &ndarray::Array2<A> {
+        &self.S
+    }
+}
+
+impl<A, Sz> crate::gating::Gating for Kalman<A, Sz> {
+    type Elem = A;
+
+    fn gate(&self) -> Option<&crate::gating::Gate<A>> {
+        self.gate.as_ref()
+    }
+
+    fn set_gate(&mut self, gate: Option<crate::gating::Gate<A>>) {
+        self.gate = gate;
+    }
+
+    fn gated(&self) -> bool {
+        self.gated
+    }
+}
+
 impl<A, Sz> Kalman<A, Sz>
 where
     A: num_traits::float::Float
@@ -105,6 +150,8 @@ where
             R: ndarray::Array2::eye(dim_z),
             y: ndarray::Array1::zeros(dim_z),
             S: ndarray::Array2::zeros((dim_z, dim_z)),
+            gate: None,
+            gated: false,
             pd_Sz: std::marker::PhantomData,
         })
     }
@@ -114,3 +161,270 @@ where
         Ok(math::multivariate::logpdf(&self.y, &mean, &self.S, true)?)
     }
 }
+
+impl<A, Sz> Kalman<A, Sz>
+where
+    A: num_traits::float::Float
+        + ndarray::ScalarOperand
+        + ndarray_linalg::types::Lapack
+        + std::ops::AddAssign,
+{
+    /// update with a measurement model other than `H` and `R`, e.g. for
+    /// sensors with their own rate or partial measurements. `z` can have a
+    /// different dimension than the regular measurements, `y` and `S` have
+    /// the dimension of `z` afterwards.
+    pub fn update_with<Szl>(
+        &mut self,
+        z: &ndarray::ArrayBase<Szl, ndarray::Ix1>,
+        H: &ndarray::Array2<A>,
+        R: &ndarray::Array2<A>,
+    ) -> Result<(), crate::Error>
+    where
+        Szl: ndarray::Data<Elem = A>,
+    {
+        self.update_model(z, Some(H), Some(R))
+    }
+
+    /// uses `H` and `R` of the filter if they're not given
+    fn update_model<Szl>(
+        &mut self,
+        z: &ndarray::ArrayBase<Szl, ndarray::Ix1>,
+        H: Option<&ndarray::Array2<A>>,
+        R: Option<&ndarray::Array2<A>>,
+    ) -> Result<(), crate::Error>
+    where
+        Szl: ndarray::Data<Elem = A>,
+    {
+        let H = H.unwrap_or(&self.H);
+        let R = R.unwrap_or(&self.R);
+
+        self.y = z - &H.dot(&self.x);
+        let PHT = self.P.dot(&H.t());
+        self.S = H.dot(&PHT) + R;
+
+        let R_scale = crate::gating::apply(self.gate.as_ref(), &self.y, &mut self.S, R)?;
+        self.gated = R_scale != Some(A::one());
+        let R_scale = match R_scale {
+            Some(v) => v,
+            // rejected
+            None => return Ok(()),
+        };
+
+        let SI = self.S.inv()?;
+        let K = PHT.dot(&SI);
+        self.x = &self.x + &K.dot(&self.y);
+
+        let Ix = ndarray::Array2::<A>::eye(self.dim_x);
+        let I_KH = Ix - K.dot(&H);
+        self.P = &I_KH.dot(&self.P).dot(&I_KH.t()) + &(K.dot(R).dot(&K.t()) * R_scale);
+        Ok(())
+    }
+}
+
+impl<A, Sz> Kalman<A, Sz>
+where
+    A: num_traits::float::Float + ndarray::ScalarOperand + ndarray_linalg::types::Lapack,
+{
+    /// Rauch-Tung-Striebel smoother. `Fs[k]` and `Qs[k]` are the matrices
+    /// that were used to predict step `k`, `F` and `Q` of the filter are
+    /// used if they're not given.
+    pub fn rts_smoother<Sx>(
+        &self,
+        xs: &[ndarray::ArrayBase<Sx, ndarray::Ix1>],
+        Ps: &[ndarray::ArrayBase<Sx, ndarray::Ix2>],
+        Fs: Option<&[ndarray::Array2<A>]>,
+        Qs: Option<&[ndarray::Array2<A>]>,
+    ) -> Result<crate::RTSResult<A>, crate::Error>
+    where
+        Sx: ndarray::Data<Elem = A>,
+    {
+        rts(
+            xs,
+            Ps,
+            move |k| Fs.map_or(&self.F, |Fs| &Fs[k]),
+            move |k| Qs.map_or(&self.Q, |Qs| &Qs[k]),
+        )
+    }
+}
+
+fn rts<'a, A, Sx>(
+    xs: &[ndarray::ArrayBase<Sx, ndarray::Ix1>],
+    Ps: &[ndarray::ArrayBase<Sx, ndarray::Ix2>],
+    Fs: impl Fn(usize) -> &'a ndarray::Array2<A>,
+    Qs: impl Fn(usize) -> &'a ndarray::Array2<A>,
+) -> Result<crate::RTSResult<A>, crate::Error>
+where
+    A: 'a + num_traits::float::Float + ndarray::ScalarOperand + ndarray_linalg::types::Lapack,
+    Sx: ndarray::Data<Elem = A>,
+{
+    assert_eq!(xs.len(), Ps.len());
+    let mut xss = Vec::with_capacity(xs.len());
+    let mut Pss = Vec::with_capacity(Ps.len());
+
+    if xs.is_empty() {
+        return Ok((xss, Pss));
+    }
+
+    xss.push(xs.last().unwrap().to_owned());
+    Pss.push(Ps.last().unwrap().to_owned());
+
+    for k in (0..xs.len() - 1).rev() {
+        let F = Fs(k + 1);
+        let x = &xs[k];
+        let P = &Ps[k];
+
+        // predicted covariance
+        let Pp = F.dot(P).dot(&F.t()) + Qs(k + 1);
+
+        // smoother gain
+        let K = P.dot(&F.t()).dot(&Pp.inv()?);
+
+        // update the smoothed estimates
+        let residual = xss.last().unwrap() - &F.dot(x);
+        xss.push(x + &K.dot(&residual));
+        Pss.push(P + &K.dot(&(Pss.last().unwrap() - &Pp)).dot(&K.t()));
+    }
+
+    xss.reverse();
+    Pss.reverse();
+
+    Ok((xss, Pss))
+}
+
+/// RTS smoother over a sliding window, usable online. The estimates get
+/// delayed by `lag` steps.
+#[derive(Clone, Debug)]
+pub struct FixedLagSmoother<A> {
+    lag: usize,
+    xs: std::collections::VecDeque<ndarray::Array1<A>>,
+    Ps: std::collections::VecDeque<ndarray::Array2<A>>,
+    Fs: std::collections::VecDeque<ndarray::Array2<A>>,
+    Qs: std::collections::VecDeque<ndarray::Array2<A>>,
+}
+
+impl<A> FixedLagSmoother<A>
+where
+    A: num_traits::float::Float + ndarray::ScalarOperand + ndarray_linalg::types::Lapack,
+{
+    pub fn new(lag: usize) -> Self {
+        Self {
+            lag,
+            xs: std::collections::VecDeque::with_capacity(lag + 1),
+            Ps: std::collections::VecDeque::with_capacity(lag + 1),
+            Fs: std::collections::VecDeque::with_capacity(lag + 1),
+            Qs: std::collections::VecDeque::with_capacity(lag + 1),
+        }
+    }
+
+    pub fn lag(&self) -> usize {
+        self.lag
+    }
+
+    /// adds the result of a filter step, `F` and `Q` are the matrices that
+    /// were used to predict it. Returns the smoothed estimate of the step
+    /// `lag` steps ago, once there is one.
+    #[allow(clippy::type_complexity)]
+    pub fn push(
+        &mut self,
+        x: ndarray::Array1<A>,
+        P: ndarray::Array2<A>,
+        F: ndarray::Array2<A>,
+        Q: ndarray::Array2<A>,
+    ) -> Result<Option<(ndarray::Array1<A>, ndarray::Array2<A>)>, crate::Error> {
+        self.xs.push_back(x);
+        self.Ps.push_back(P);
+        self.Fs.push_back(F);
+        self.Qs.push_back(Q);
+
+        if self.xs.len() <= self.lag {
+            return Ok(None);
+        }
+
+        let (mut xss, mut Pss) = self.smooth()?;
+
+        self.xs.pop_front();
+        self.Ps.pop_front();
+        self.Fs.pop_front();
+        self.Qs.pop_front();
+
+        Ok(Some((xss.swap_remove(0), Pss.swap_remove(0))))
+    }
+
+    /// smoothes and removes the estimates that weren't returned yet, used at
+    /// the end of the data
+    pub fn flush(&mut self) -> Result<crate::RTSResult<A>, crate::Error> {
+        let ret = self.smooth()?;
+
+        self.xs.clear();
+        self.Ps.clear();
+        self.Fs.clear();
+        self.Qs.clear();
+
+        Ok(ret)
+    }
+
+    fn smooth(&mut self) -> Result<crate::RTSResult<A>, crate::Error> {
+        let xs = &*self.xs.make_contiguous();
+        let Ps = &*self.Ps.make_contiguous();
+        let Fs = &*self.Fs.make_contiguous();
+        let Qs = &*self.Qs.make_contiguous();
+
+        rts(xs, Ps, |k| &Fs[k], |k| &Qs[k])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Filter;
+
+    /// the last window of the fixed-lag smoother sees the same data as the
+    /// full smoother
+    #[test]
+    fn fixed_lag() {
+        let dt = 0.1;
+        let lag = 5;
+
+        let mut kf = crate::test_support::cv_filter(dt);
+        kf.Q = ndarray::array![[0.0, 0.0], [0.0, 0.01]];
+
+        let mut flsmoother = super::FixedLagSmoother::new(lag);
+        let mut xs = Vec::new();
+        let mut Ps = Vec::new();
+        let mut xfl = Vec::new();
+        let mut Pfl = Vec::new();
+
+        for i in 0..30 {
+            let z = ndarray::array![(i as f64 * dt).powi(2)];
+            kf.predict().unwrap();
+            kf.update(&z).unwrap();
+
+            xs.push(kf.x.clone());
+            Ps.push(kf.P.clone());
+
+            if let Some((x, P)) = flsmoother
+                .push(kf.x.clone(), kf.P.clone(), kf.F.clone(), kf.Q.clone())
+                .unwrap()
+            {
+                xfl.push(x);
+                Pfl.push(P);
+            }
+        }
+        assert_eq!(xfl.len(), xs.len() - lag);
+
+        let (xs_flush, Ps_flush) = flsmoother.flush().unwrap();
+        assert_eq!(xs_flush.len(), lag);
+        xfl.extend(xs_flush);
+        Pfl.extend(Ps_flush);
+
+        let (xss, Pss) = kf.rts_smoother(&xs, &Ps, None, None).unwrap();
+        for k in xs.len() - lag..xs.len() {
+            testlib::assert_arr1_eq(&xfl[k], &xss[k]);
+            testlib::assert_arr2_eq(&Pfl[k], &Pss[k]);
+        }
+
+        // smoothing can only reduce the uncertainty
+        for k in 0..xs.len() {
+            assert!(Pss[k].diag().sum() <= Ps[k].diag().sum() + 1.0e-12);
+        }
+    }
+}
@@ -1,6 +1,12 @@
+pub mod adaptive;
+pub mod checkpoint;
+pub mod consistency;
 pub mod discretization;
+pub mod ekf;
+pub mod gating;
 pub mod imm;
 pub mod kalman;
+pub mod particle;
 pub mod sigma_points;
 pub mod ukf;
 
@@ -13,6 +19,12 @@ pub use unscented_transform::*;
 #[cfg(test)]
 extern crate lapack_src;
 
+#[cfg(test)]
+mod test_support;
+
+/// smoothed states and covariances
+pub type RTSResult<A> = (Vec<ndarray::Array1<A>>, Vec<ndarray::Array2<A>>);
+
 #[allow(non_snake_case)]
 pub trait Filter {
     type Elem;
@@ -46,6 +58,15 @@ pub trait R {
     fn R_mut(&mut self) -> &mut ndarray::Array2<Self::Elem>;
 }
 
+/// residual and its covariance of the last update
+#[allow(non_snake_case)]
+pub trait Innovation {
+    type Elem;
+
+    fn y(&self) -> &ndarray::Array1<Self::Elem>;
+    fn S(&self) -> &ndarray::Array2<Self::Elem>;
+}
+
 pub trait SetDt<T> {
     fn set_dt(&mut self, dt: &T);
 }
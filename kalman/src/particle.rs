@@ -0,0 +1,510 @@
+#![allow(non_snake_case)]
+
+use crate::ukf::ApplyDt;
+use crate::ukf::Fx;
+use crate::ukf::Hx;
+use crate::ukf::Mean;
+use crate::Error;
+use crate::Filter;
+use crate::SetDt;
+use ndarray_linalg::cholesky::Cholesky;
+use ndarray_linalg::triangular::SolveTriangular;
+use rand::Rng;
+use rand::SeedableRng;
+
+/// how the particles get drawn from the weighted set
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Resampling {
+    /// evenly spaced positions with a single random offset
+    #[default]
+    Systematic,
+    /// keeps `floor(N * w)` copies of every particle and draws the rest
+    /// randomly
+    Residual,
+}
+
+/// returns the indices of the particles to keep, `weights` have to be
+/// normalized
+pub fn resample<R: Rng>(
+    method: Resampling,
+    weights: &ndarray::Array1<f64>,
+    rng: &mut R,
+) -> Vec<usize> {
+    let n = weights.len();
+    let mut indices = Vec::with_capacity(n);
+    if n == 0 {
+        return indices;
+    }
+
+    match method {
+        Resampling::Systematic => {
+            let offset: f64 = rng.gen();
+            let mut j = 0;
+            let mut cumsum = weights[0];
+
+            for i in 0..n {
+                let u = (i as f64 + offset) / n as f64;
+                while u > cumsum && j < n - 1 {
+                    j += 1;
+                    cumsum += weights[j];
+                }
+                indices.push(j);
+            }
+        }
+        Resampling::Residual => {
+            let mut cumsum = Vec::with_capacity(n);
+            let mut total = 0.0;
+            for (i, w) in weights.iter().enumerate() {
+                let copies = (w * n as f64).floor();
+                indices.extend(std::iter::repeat(i).take(copies as usize));
+
+                total += w * n as f64 - copies;
+                cumsum.push(total);
+            }
+            indices.truncate(n);
+
+            // multinomial resampling of the residuals
+            while indices.len() < n {
+                let u = rng.gen::<f64>() * total;
+                indices.push(cumsum.partition_point(|c| *c < u).min(n - 1));
+            }
+        }
+    }
+
+    indices
+}
+
+/// samples of the standard normal distribution
+fn randn<R: Rng>(rng: &mut R, n: usize) -> ndarray::Array1<f64> {
+    // box-muller
+    ndarray::Array1::from_shape_fn(n, |_| {
+        let u1 = 1.0 - rng.gen::<f64>();
+        let u2: f64 = rng.gen();
+        (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()
+    })
+}
+
+/// sequential importance resampling filter for multimodal distributions.
+/// The estimate is calculated using the [Mean] of the model functions, so
+/// angles are averaged correctly if the model uses [math::SinCosSum] for
+/// them.
+#[derive(Clone, Debug)]
+pub struct ParticleFilter<FNSX, ARGSFX, FNSZ, Sz> {
+    fns_x: FNSX,
+    args_fx: ARGSFX,
+    fns_z: FNSZ,
+
+    // state
+    x: ndarray::Array1<f64>,
+    P: ndarray::Array2<f64>,
+    pub Q: ndarray::Array2<f64>,
+    /// `x` or `P` were set, the particles have to be drawn again
+    xP_changed: bool,
+
+    // observation
+    pub R: ndarray::Array2<f64>,
+
+    // particles
+    particles: ndarray::Array2<f64>,
+    weights: ndarray::Array1<f64>,
+    pub resampling: Resampling,
+    /// resample when the effective sample size drops below this fraction of
+    /// the number of particles
+    pub resample_threshold: f64,
+    rng: rand::rngs::StdRng,
+
+    // update
+    pub y: ndarray::Array1<f64>,
+    pub S: ndarray::Array2<f64>,
+    log_likelihood: f64,
+
+    pub gate: Option<crate::gating::Gate<f64>>,
+    gated: bool,
+
+    pd_Sz: std::marker::PhantomData<Sz>,
+}
+
+impl<FNSX, ARGSFX, FNSZ, Sz> SetDt<f64> for ParticleFilter<FNSX, ARGSFX, FNSZ, Sz>
+where
+    ARGSFX: SetDt<f64> + ApplyDt<f64>,
+{
+    fn set_dt(&mut self, dt: &f64) {
+        self.args_fx.set_dt(dt);
+        self.args_fx.apply_dt(&mut self.Q);
+    }
+}
+
+impl<FNSX, ARGSFX, FNSZ, Sz> Filter for ParticleFilter<FNSX, ARGSFX, FNSZ, Sz>
+where
+    FNSX: Fx<ARGSFX, Elem = f64>
+        + Hx<Elem = f64>
+        + Mean<f64>
+        + crate::Add<f64>
+        + crate::Subtract<f64>,
+    FNSZ: Mean<f64> + crate::Subtract<f64>,
+    Sz: ndarray::Data<Elem = f64>,
+{
+    type Elem = f64;
+    type Meas = ndarray::ArrayBase<Sz, ndarray::Ix1>;
+
+    fn predict(&mut self) -> Result<(), crate::Error> {
+        self.draw_if_changed()?;
+
+        let sqrt_Q = crate::ukf::sqrt_psd(&self.Q)?;
+        for i in 0..self.particles.nrows() {
+            let x = self.fns_x.fx(
+                &self.particles.index_axis(ndarray::Axis(0), i),
+                &self.args_fx,
+            );
+            let noise = randn(&mut self.rng, x.len()).dot(&sqrt_Q);
+
+            self.particles
+                .index_axis_mut(ndarray::Axis(0), i)
+                .assign(&self.fns_x.add(&x, &noise));
+        }
+
+        self.estimate();
+
+        Ok(())
+    }
+
+    fn update(&mut self, z: &ndarray::ArrayBase<Sz, ndarray::Ix1>) -> Result<(), crate::Error> {
+        self.draw_if_changed()?;
+
+        let n = self.particles.nrows();
+
+        // transform particles into measurement space
+        let mut particles_h = ndarray::Array2::zeros((n, z.len()));
+        for i in 0..n {
+            particles_h.index_axis_mut(ndarray::Axis(0), i).assign(
+                &self
+                    .fns_x
+                    .hx(&self.particles.index_axis(ndarray::Axis(0), i)),
+            );
+        }
+
+        // innovation of the predicted measurement, used for gating and
+        // consistency checks
+        let zp = self.fns_z.mean(&particles_h, &self.weights);
+        let mut S = self.R.clone();
+        for (hi, &wi) in particles_h.rows().into_iter().zip(&self.weights) {
+            let dz = self.fns_z.subtract(&hi, &zp);
+            S += &(math::outer_product(&dz, &dz) * wi);
+        }
+        let y = self.fns_z.subtract(z, &zp);
+
+        let R_scale = crate::gating::apply(self.gate.as_ref(), &y, &mut S, &self.R)?;
+        self.gated = R_scale != Some(1.0);
+        self.y = y;
+        self.S = S;
+        let R_scale = match R_scale {
+            Some(v) => v,
+            // rejected
+            None => return Ok(()),
+        };
+
+        // log-likelihood of the measurement for every particle
+        let mut residuals = ndarray::Array2::zeros((z.len(), n));
+        for (mut col, hi) in residuals.columns_mut().into_iter().zip(particles_h.rows()) {
+            col.assign(&self.fns_z.subtract(z, &hi));
+        }
+        let L = (&self.R * R_scale).cholesky(ndarray_linalg::UPLO::Lower)?;
+        let residuals = L.solve_triangular(
+            ndarray_linalg::UPLO::Lower,
+            ndarray_linalg::Diag::NonUnit,
+            &residuals,
+        )?;
+        let log_det = 2.0 * L.diag().mapv(f64::ln).sum();
+        let log_norm = -0.5 * (z.len() as f64 * std::f64::consts::TAU.ln() + log_det);
+        let lls = residuals.map_axis(ndarray::Axis(0), |r| log_norm - 0.5 * r.dot(&r));
+
+        // scale by the largest value so the weights don't underflow
+        let max = lls.fold(f64::NEG_INFINITY, |a, b| a.max(*b));
+        if !max.is_finite() {
+            return Err(Error::DegenerateWeights);
+        }
+        let mut weights = &self.weights * &lls.mapv(|ll| (ll - max).exp());
+        let sum = weights.sum();
+        if sum.is_nan() || sum <= 0.0 {
+            return Err(Error::DegenerateWeights);
+        }
+        weights /= sum;
+
+        self.weights = weights;
+        self.log_likelihood = max + sum.ln();
+        self.estimate();
+
+        if self.effective_sample_size() < self.resample_threshold * n as f64 {
+            self.resample();
+        }
+
+        Ok(())
+    }
+
+    /// Computed from the log-likelihood. The log-likelihood can be very
+    /// small,  meaning a large negative value such as -28000. Taking the
+    /// exp() of that results in 0.0, which can break typical algorithms
+    /// which multiply by this value, so by default we always return a
+    /// number >= `f64::MIN_POSITIVE`
+    fn likelihood(&self) -> Result<f64, Error> {
+        let ll = self.log_likelihood()?;
+        let mut l = ll.exp();
+        if l == 0.0 {
+            l = f64::MIN_POSITIVE;
+        }
+
+        Ok(l)
+    }
+
+    fn x(&self) -> &ndarray::Array1<f64> {
+        &self.x
+    }
+
+    /// the particles get drawn again using `x` and `P` before the next step
+    fn x_mut(&mut self) -> &mut ndarray::Array1<f64> {
+        self.xP_changed = true;
+        &mut self.x
+    }
+
+    fn P(&self) -> &ndarray::Array2<f64> {
+        &self.P
+    }
+
+    /// the particles get drawn again using `x` and `P` before the next step
+    fn P_mut(&mut self) -> &mut ndarray::Array2<f64> {
+        self.xP_changed = true;
+        &mut self.P
+    }
+}
+
+impl<FNSX, ARGSFX, FNSZ, Sz> crate::Q for ParticleFilter<FNSX, ARGSFX, FNSZ, Sz> {
+    type Elem = f64;
+
+    fn Q(&self) -> &ndarray::Array2<f64> {
+        &self.Q
+    }
+
+    fn Q_mut(&mut self) -> &mut ndarray::Array2<f64> {
+        &mut self.Q
+    }
+}
+
+impl<FNSX, ARGSFX, FNSZ, Sz> crate::R for ParticleFilter<FNSX, ARGSFX, FNSZ, Sz> {
+    type Elem = f64;
+
+    fn R(&self) -> &ndarray::Array2<f64> {
+        &self.R
+    }
+
+    fn R_mut(&mut self) -> &mut ndarray::Array2<f64> {
+        &mut self.R
+    }
+}
+
+impl<FNSX, ARGSFX, FNSZ, Sz> crate::Innovation for ParticleFilter<FNSX, ARGSFX, FNSZ, Sz> {
+    type Elem = f64;
+
+    fn y(&self) -> &ndarray::Array1<f64> {
+        &self.y
+    }
+
+    fn S(&self) -> &ndarray::Array2<f64> {
+        &self.S
+    }
+}
+
+impl<FNSX, ARGSFX, FNSZ, Sz> crate::gating::Gating for ParticleFilter<FNSX, ARGSFX, FNSZ, Sz> {
+    type Elem = f64;
+
+    fn gate(&self) -> Option<&crate::gating::Gate<f64>> {
+        self.gate.as_ref()
+    }
+
+    fn set_gate(&mut self, gate: Option<crate::gating::Gate<f64>>) {
+        self.gate = gate;
+    }
+
+    fn gated(&self) -> bool {
+        self.gated
+    }
+}
+
+impl<FNSX, ARGSFX, FNSZ, Sz> ParticleFilter<FNSX, ARGSFX, FNSZ, Sz>
+where
+    FNSX: Mean<f64> + crate::Add<f64> + crate::Subtract<f64>,
+{
+    /// the particles are drawn from `x` and `P` before the first step, `seed`
+    /// makes runs reproducible
+    pub fn new(
+        dim_x: usize,
+        dim_z: usize,
+        num_particles: usize,
+        fns_x: FNSX,
+        args_fx: ARGSFX,
+        fns_z: FNSZ,
+        seed: u64,
+    ) -> Self {
+        Self {
+            fns_x,
+            args_fx,
+            fns_z,
+
+            x: ndarray::Array::zeros(dim_x),
+            P: ndarray::Array::eye(dim_x),
+            Q: ndarray::Array::eye(dim_x),
+            xP_changed: true,
+
+            R: ndarray::Array::eye(dim_z),
+
+            particles: ndarray::Array::zeros((num_particles, dim_x)),
+            weights: ndarray::Array::from_elem(num_particles, 1.0 / num_particles as f64),
+            resampling: Resampling::default(),
+            resample_threshold: 0.5,
+            rng: rand::rngs::StdRng::seed_from_u64(seed),
+
+            y: ndarray::Array::zeros(dim_z),
+            S: ndarray::Array::zeros((dim_z, dim_z)),
+            log_likelihood: 0.0,
+
+            gate: None,
+            gated: false,
+
+            pd_Sz: std::marker::PhantomData,
+        }
+    }
+
+    /// one row per particle
+    pub fn particles(&self) -> &ndarray::Array2<f64> {
+        &self.particles
+    }
+
+    pub fn weights(&self) -> &ndarray::Array1<f64> {
+        &self.weights
+    }
+
+    /// number of particles which effectively contribute to the estimate
+    pub fn effective_sample_size(&self) -> f64 {
+        1.0 / self.weights.dot(&self.weights)
+    }
+
+    /// log-likelihood of the last measurement
+    pub fn log_likelihood(&self) -> Result<f64, Error> {
+        Ok(self.log_likelihood)
+    }
+
+    /// draws new particles with equal weights, usually done automatically
+    /// when the effective sample size gets too small
+    pub fn resample(&mut self) {
+        let indices = resample(self.resampling, &self.weights, &mut self.rng);
+        self.particles = self.particles.select(ndarray::Axis(0), &indices);
+        self.weights.fill(1.0 / indices.len() as f64);
+    }
+
+    fn draw_if_changed(&mut self) -> Result<(), Error> {
+        if !self.xP_changed {
+            return Ok(());
+        }
+
+        let sqrt_P = crate::ukf::sqrt_psd(&self.P)?;
+        for mut particle in self.particles.rows_mut() {
+            let noise = randn(&mut self.rng, self.x.len()).dot(&sqrt_P);
+            particle.assign(&self.fns_x.add(&self.x, &noise));
+        }
+        self.weights.fill(1.0 / self.particles.nrows() as f64);
+        self.xP_changed = false;
+
+        Ok(())
+    }
+
+    fn estimate(&mut self) {
+        self.x = self.fns_x.mean(&self.particles, &self.weights);
+
+        let mut P = ndarray::Array2::zeros(self.P.raw_dim());
+        for (particle, &w) in self.particles.rows().into_iter().zip(&self.weights) {
+            let dx = self.fns_x.subtract(&particle, &self.x);
+            P += &(math::outer_product(&dx, &dx) * w);
+        }
+        self.P = P;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_support::Args;
+    use crate::test_support::Functions;
+    use crate::Filter;
+    use rand::SeedableRng;
+
+    fn counts(indices: &[usize], n: usize) -> Vec<usize> {
+        let mut counts = vec![0; n];
+        for i in indices {
+            counts[*i] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn resample() {
+        let weights = ndarray::array![0.5, 0.25, 0.125, 0.125, 0.0, 0.0, 0.0, 0.0];
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+
+        for method in [super::Resampling::Systematic, super::Resampling::Residual] {
+            let indices = super::resample(method, &weights, &mut rng);
+            assert_eq!(counts(&indices, 8), [4, 2, 1, 1, 0, 0, 0, 0]);
+        }
+
+        // every particle gets at least floor(N * w) copies
+        let weights = ndarray::array![0.2, 0.3, 0.5];
+        for method in [super::Resampling::Systematic, super::Resampling::Residual] {
+            for _ in 0..100 {
+                let indices = super::resample(method, &weights, &mut rng);
+                let counts = counts(&indices, 3);
+                assert_eq!(counts.iter().sum::<usize>(), 3);
+                assert!(counts[2] >= 1);
+            }
+        }
+    }
+
+    /// with enough particles the estimate of a linear model has to be close
+    /// to the one of the linear filter
+    #[test]
+    fn linear() {
+        let dt = 0.1;
+        let sigma_z: f64 = 0.5;
+
+        let mut pf = super::ParticleFilter::new(2, 1, 5000, Functions, Args { dt }, Functions, 1);
+        let mut kf = crate::test_support::cv_filter(dt);
+
+        let Q = ndarray::array![[1.0e-4, 0.0], [0.0, 1.0e-3]];
+        let R = ndarray::array![[sigma_z.powi(2)]];
+        *pf.P_mut() = ndarray::array![[1.0, 0.0], [0.0, 1.0]];
+        pf.Q = Q.clone();
+        pf.R = R.clone();
+        kf.P = ndarray::array![[1.0, 0.0], [0.0, 1.0]];
+        kf.Q = Q;
+        kf.R = R;
+
+        let mut noise = crate::test_support::randn(2);
+        for i in 0..100 {
+            let z = ndarray::array![(i + 1) as f64 * dt + noise.next().unwrap() * sigma_z];
+
+            pf.predict().unwrap();
+            pf.update(&z).unwrap();
+            kf.predict().unwrap();
+            kf.update(&z).unwrap();
+
+            assert!(pf.effective_sample_size() > 0.0);
+        }
+
+        for i in 0..2 {
+            let sd = kf.P[(i, i)].sqrt();
+            assert!(
+                (pf.x()[i] - kf.x[i]).abs() < 0.5 * sd,
+                "{} != {}",
+                pf.x(),
+                kf.x
+            );
+            assert!((pf.P()[(i, i)].sqrt() / sd - 1.0).abs() < 0.3);
+        }
+    }
+}
@@ -16,6 +16,17 @@ pub trait SigmaPoints {
     where
         S: ndarray::Data<Elem = Self::Elem>;
 
+    /// same as [sigma_points](Self::sigma_points), but takes the upper
+    /// cholesky factor `U` with `P = U^T * U` instead of `P`
+    fn sigma_points_sqrt<Sx, Su>(
+        &self,
+        x: &ndarray::ArrayBase<Sx, ndarray::Ix1>,
+        U: &ndarray::ArrayBase<Su, ndarray::Ix2>,
+    ) -> Result<ndarray::Array2<Self::Elem>, crate::Error>
+    where
+        Sx: ndarray::Data<Elem = Self::Elem>,
+        Su: ndarray::Data<Elem = Self::Elem>;
+
     fn weights_covariance(&self) -> ndarray::Array1<Self::Elem>;
     fn weights_mean(&self) -> ndarray::Array1<Self::Elem>;
 }
@@ -73,12 +84,28 @@ where
     ) -> Result<ndarray::Array2<Self::Elem>, crate::Error>
     where
         S: ndarray::Data<Elem = Self::Elem>,
+    {
+        let U = P.cholesky(ndarray_linalg::UPLO::Upper)?;
+        self.sigma_points_sqrt(x, &U)
+    }
+
+    fn sigma_points_sqrt<Sx, Su>(
+        &self,
+        x: &ndarray::ArrayBase<Sx, ndarray::Ix1>,
+        U: &ndarray::ArrayBase<Su, ndarray::Ix2>,
+    ) -> Result<ndarray::Array2<Self::Elem>, crate::Error>
+    where
+        Sx: ndarray::Data<Elem = Self::Elem>,
+        Su: ndarray::Data<Elem = Self::Elem>,
     {
         assert_eq!(x.dim(), self.n);
-        assert_eq!(P.dim(), (self.n, self.n));
+        assert_eq!(U.dim(), (self.n, self.n));
 
-        let lambda = self.lambda();
-        let U = (((self.n as Self::Elem) + lambda) * P).cholesky(ndarray_linalg::UPLO::Upper)?;
+        let scale = (self.n as Self::Elem) + self.lambda();
+        if scale <= 0.0 {
+            return Err(crate::Error::InvalidArgument);
+        }
+        let U = U * scale.sqrt();
 
         let mut sigmas = ndarray::Array2::<Self::Elem>::zeros((2 * self.n + 1, self.n));
         sigmas.index_axis_mut(ndarray::Axis(0), 0).assign(x);
@@ -143,12 +170,28 @@ where
     ) -> Result<ndarray::Array2<Self::Elem>, crate::Error>
     where
         S: ndarray::Data<Elem = Self::Elem>,
+    {
+        let U = P.cholesky(ndarray_linalg::UPLO::Upper)?;
+        self.sigma_points_sqrt(x, &U)
+    }
+
+    fn sigma_points_sqrt<Sx, Su>(
+        &self,
+        x: &ndarray::ArrayBase<Sx, ndarray::Ix1>,
+        U: &ndarray::ArrayBase<Su, ndarray::Ix2>,
+    ) -> Result<ndarray::Array2<Self::Elem>, crate::Error>
+    where
+        Sx: ndarray::Data<Elem = Self::Elem>,
+        Su: ndarray::Data<Elem = Self::Elem>,
     {
         assert_eq!(x.dim(), self.n);
-        assert_eq!(P.dim(), (self.n, self.n));
+        assert_eq!(U.dim(), (self.n, self.n));
 
-        let U =
-            (((self.n as Self::Elem) + self.kappa) * P).cholesky(ndarray_linalg::UPLO::Upper)?;
+        let scale = (self.n as Self::Elem) + self.kappa;
+        if scale <= 0.0 {
+            return Err(crate::Error::InvalidArgument);
+        }
+        let U = U * scale.sqrt();
 
         let mut sigmas = ndarray::Array2::<Self::Elem>::zeros((2 * self.n + 1, self.n));
         sigmas.index_axis_mut(ndarray::Axis(0), 0).assign(x);
@@ -179,45 +222,173 @@ where
     }
 }
 
-#[cfg(test)]
-pub(crate) mod tests {
-    use super::*;
-    use ndarray::array;
+/// `n + 1` sigma points, the minimum to capture mean and covariance. Cheaper
+/// than the symmetric sets but less accurate for strongly nonlinear models.
+#[derive(Clone, Debug)]
+pub struct SimplexSigmaPoints<FNS> {
+    fns: FNS,
+
+    n: usize,
+}
+
+impl<FNS> SimplexSigmaPoints<FNS> {
+    /// n: number of dimensions
+    pub fn new(n: usize, fns: FNS) -> Self {
+        Self { fns, n }
+    }
+}
+
+impl<FNS> SigmaPoints for SimplexSigmaPoints<FNS>
+where
+    FNS: crate::Subtract<f64>,
+{
+    type Elem = f64;
+
+    fn num_sigmas(&self) -> usize {
+        self.n + 1
+    }
 
-    #[derive(Default)]
-    pub struct LinFns;
-
-    impl<A: num_traits::Float> crate::Add<A> for LinFns {
-        fn add<Sa, Sb>(
-            &self,
-            a: &ndarray::ArrayBase<Sa, ndarray::Ix1>,
-            b: &ndarray::ArrayBase<Sb, ndarray::Ix1>,
-        ) -> ndarray::Array1<A>
-        where
-            Sa: ndarray::Data<Elem = A>,
-            Sb: ndarray::Data<Elem = A>,
-        {
-            a + b
+    fn sigma_points<S>(
+        &self,
+        x: &ndarray::ArrayBase<S, ndarray::Ix1>,
+        P: &ndarray::ArrayBase<S, ndarray::Ix2>,
+    ) -> Result<ndarray::Array2<Self::Elem>, crate::Error>
+    where
+        S: ndarray::Data<Elem = Self::Elem>,
+    {
+        let U = P.cholesky(ndarray_linalg::UPLO::Upper)?;
+        self.sigma_points_sqrt(x, &U)
+    }
+
+    fn sigma_points_sqrt<Sx, Su>(
+        &self,
+        x: &ndarray::ArrayBase<Sx, ndarray::Ix1>,
+        U: &ndarray::ArrayBase<Su, ndarray::Ix2>,
+    ) -> Result<ndarray::Array2<Self::Elem>, crate::Error>
+    where
+        Sx: ndarray::Data<Elem = Self::Elem>,
+        Su: ndarray::Data<Elem = Self::Elem>,
+    {
+        assert_eq!(x.dim(), self.n);
+        assert_eq!(U.dim(), (self.n, self.n));
+
+        // orthogonal rows which sum up to zero, scaled so the points have
+        // the covariance `P`
+        let nf = self.n as Self::Elem;
+        let lambda = nf / (nf + 1.0);
+        let mut I = ndarray::Array2::<Self::Elem>::zeros((self.n, self.n + 1));
+        for k in 0..self.n {
+            let d = (k + 1) as Self::Elem;
+            let c = nf.sqrt() / (lambda * d * (d + 1.0)).sqrt();
+
+            I.slice_mut(ndarray::s![k, 0..=k]).fill(c);
+            I[(k, k + 1)] = -d * c;
+        }
+
+        let offsets = I.t().dot(U);
+
+        let mut sigmas = ndarray::Array2::<Self::Elem>::zeros((self.n + 1, self.n));
+        for (mut sigma, offset) in sigmas.rows_mut().into_iter().zip(offsets.rows()) {
+            sigma.assign(&self.fns.subtract(&offset, &x.neg()));
         }
+
+        Ok(sigmas)
+    }
+
+    fn weights_covariance(&self) -> ndarray::Array1<Self::Elem> {
+        self.weights_mean()
+    }
+
+    fn weights_mean(&self) -> ndarray::Array1<Self::Elem> {
+        ndarray::Array1::from_elem(self.n + 1, 1.0 / (self.n as Self::Elem + 1.0))
+    }
+}
+
+/// `2n` points of the spherical-radial cubature rule used by the cubature
+/// kalman filter. All weights are positive, so the covariance stays positive
+/// definite where negative center weights of other sets can break it.
+#[derive(Clone, Debug)]
+pub struct CubatureSigmaPoints<FNS> {
+    fns: FNS,
+
+    n: usize,
+}
+
+impl<FNS> CubatureSigmaPoints<FNS> {
+    /// n: number of dimensions
+    pub fn new(n: usize, fns: FNS) -> Self {
+        Self { fns, n }
+    }
+}
+
+impl<FNS> SigmaPoints for CubatureSigmaPoints<FNS>
+where
+    FNS: crate::Subtract<f64>,
+{
+    type Elem = f64;
+
+    fn num_sigmas(&self) -> usize {
+        2 * self.n
     }
 
-    impl<A: num_traits::Float> crate::Subtract<A> for LinFns {
-        fn subtract<Sa, Sb>(
-            &self,
-            a: &ndarray::ArrayBase<Sa, ndarray::Ix1>,
-            b: &ndarray::ArrayBase<Sb, ndarray::Ix1>,
-        ) -> ndarray::Array1<A>
-        where
-            Sa: ndarray::Data<Elem = A>,
-            Sb: ndarray::Data<Elem = A>,
-        {
-            a - b
+    fn sigma_points<S>(
+        &self,
+        x: &ndarray::ArrayBase<S, ndarray::Ix1>,
+        P: &ndarray::ArrayBase<S, ndarray::Ix2>,
+    ) -> Result<ndarray::Array2<Self::Elem>, crate::Error>
+    where
+        S: ndarray::Data<Elem = Self::Elem>,
+    {
+        let U = P.cholesky(ndarray_linalg::UPLO::Upper)?;
+        self.sigma_points_sqrt(x, &U)
+    }
+
+    fn sigma_points_sqrt<Sx, Su>(
+        &self,
+        x: &ndarray::ArrayBase<Sx, ndarray::Ix1>,
+        U: &ndarray::ArrayBase<Su, ndarray::Ix2>,
+    ) -> Result<ndarray::Array2<Self::Elem>, crate::Error>
+    where
+        Sx: ndarray::Data<Elem = Self::Elem>,
+        Su: ndarray::Data<Elem = Self::Elem>,
+    {
+        assert_eq!(x.dim(), self.n);
+        assert_eq!(U.dim(), (self.n, self.n));
+
+        let U = U * (self.n as Self::Elem).sqrt();
+
+        let mut sigmas = ndarray::Array2::<Self::Elem>::zeros((2 * self.n, self.n));
+        for k in 0..self.n {
+            let Uk = U.index_axis(ndarray::Axis(0), k);
+            sigmas
+                .index_axis_mut(ndarray::Axis(0), k)
+                .assign(&self.fns.subtract(&Uk, &x.neg()));
+            sigmas
+                .index_axis_mut(ndarray::Axis(0), self.n + k)
+                .assign(&self.fns.subtract(&Uk.neg().view(), &x.neg()));
         }
+
+        Ok(sigmas)
     }
 
+    fn weights_covariance(&self) -> ndarray::Array1<Self::Elem> {
+        self.weights_mean()
+    }
+
+    fn weights_mean(&self) -> ndarray::Array1<Self::Elem> {
+        ndarray::Array1::from_elem(2 * self.n, 0.5 / self.n as Self::Elem)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::Functions;
+    use ndarray::array;
+
     #[test]
     fn merwe() {
-        let fns = LinFns::default();
+        let fns = Functions;
         let points = MerweScaledSigmaPoints::new(2, 0.1, 2.0, 1.0, fns);
         let sigmas = points
             .sigma_points(&array![0.0, 0.0], &array![[1.0, 0.1], [0.1, 1.0]])
@@ -261,7 +432,7 @@ pub(crate) mod tests {
 
     #[test]
     fn julier() {
-        let fns = LinFns::default();
+        let fns = Functions;
         let points = JulierSigmaPoints::new(2, 1.0, fns);
         let sigmas = points
             .sigma_points(&array![0.0, 0.0], &array![[1.0, 0.1], [0.1, 1.0]])
@@ -290,4 +461,66 @@ pub(crate) mod tests {
             &array![0.33333333, 0.16666667, 0.16666667, 0.16666667, 0.16666667],
         );
     }
+
+    /// the weighted points have to reproduce the mean and the covariance
+    fn check_moments<FP: SigmaPoints<Elem = f64>>(points: &FP) {
+        let x = array![1.0, 2.0, -0.5];
+        let P = array![[2.0, 0.3, 0.1], [0.3, 1.0, -0.2], [0.1, -0.2, 0.5]];
+
+        let sigmas = points.sigma_points(&x, &P).unwrap();
+        let wc = points.weights_covariance();
+        let wm = points.weights_mean();
+        assert_eq!(sigmas.nrows(), points.num_sigmas());
+
+        let mean = wm.dot(&sigmas);
+        testlib::assert_arr1_eq(&mean, &x);
+
+        let mut cov = ndarray::Array2::<f64>::zeros((3, 3));
+        for (sigma, w) in sigmas.rows().into_iter().zip(&wc) {
+            let d = &sigma - &x;
+            cov += &(math::outer_product(&d, &d) * *w);
+        }
+        testlib::assert_arr2_eq(&cov, &P);
+    }
+
+    #[test]
+    fn simplex() {
+        let points = SimplexSigmaPoints::new(3, Functions);
+        assert_eq!(points.num_sigmas(), 4);
+        check_moments(&points);
+    }
+
+    #[test]
+    fn cubature() {
+        let points = CubatureSigmaPoints::new(2, Functions);
+        let sigmas = points
+            .sigma_points(&array![0.0, 0.0], &array![[1.0, 0.1], [0.1, 1.0]])
+            .unwrap();
+
+        testlib::assert_arr2_eq(
+            &sigmas,
+            &array![
+                [1.41421356, 0.14142136],
+                [0.0, 1.40712473],
+                [-1.41421356, -0.14142136],
+                [0.0, -1.40712473]
+            ],
+        );
+        testlib::assert_arr1_eq(&points.weights_mean(), &array![0.25, 0.25, 0.25, 0.25]);
+
+        check_moments(&CubatureSigmaPoints::new(3, Functions));
+    }
+
+    #[test]
+    fn invalid_scale() {
+        let x = array![0.0, 0.0];
+        let P = array![[1.0, 0.1], [0.1, 1.0]];
+
+        // n + lambda = alpha^2 * (n + kappa) = 0
+        let merwe = MerweScaledSigmaPoints::new(2, 0.1, 2.0, -2.0, Functions);
+        assert!(merwe.sigma_points(&x, &P).is_err());
+
+        let julier = JulierSigmaPoints::new(2, -3.0, Functions);
+        assert!(julier.sigma_points(&x, &P).is_err());
+    }
 }
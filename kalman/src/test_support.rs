@@ -0,0 +1,118 @@
+//! fixtures shared by the filter tests, all built around a constant velocity
+//! model which measures the position
+
+#![allow(non_snake_case)]
+
+use rand::Rng as _;
+use rand::SeedableRng as _;
+
+/// linear filter of the constant velocity model
+pub fn cv_filter(dt: f64) -> crate::kalman::Kalman<f64, ndarray::OwnedRepr<f64>> {
+    let mut kf = crate::kalman::Kalman::new(2, 1).unwrap();
+    kf.F = ndarray::array![[1.0, dt], [0.0, 1.0]];
+    kf.H = ndarray::array![[1.0, 0.0]];
+    kf
+}
+
+/// standard normal samples, seeded so tests are reproducible
+pub fn randn(seed: u64) -> impl Iterator<Item = f64> {
+    rand::rngs::StdRng::seed_from_u64(seed).sample_iter(rand_distr::StandardNormal)
+}
+
+/// the constant velocity model as functions for the nonlinear filters
+#[derive(Clone, Copy, Default)]
+pub struct Functions;
+
+pub struct Args {
+    pub dt: f64,
+}
+
+impl crate::ukf::Fx<Args> for Functions {
+    type Elem = f64;
+
+    fn fx<S>(&self, x: &ndarray::ArrayBase<S, ndarray::Ix1>, args: &Args) -> ndarray::Array1<f64>
+    where
+        S: ndarray::Data<Elem = Self::Elem>,
+    {
+        ndarray::array![x[0] + x[1] * args.dt, x[1]]
+    }
+}
+
+impl crate::ukf::Hx for Functions {
+    type Elem = f64;
+
+    fn hx<S>(&self, x: &ndarray::ArrayBase<S, ndarray::Ix1>) -> ndarray::Array1<f64>
+    where
+        S: ndarray::Data<Elem = Self::Elem>,
+    {
+        ndarray::array![x[0]]
+    }
+}
+
+impl crate::ekf::FxJacobian<Args> for Functions {
+    type Elem = f64;
+
+    fn fx_jacobian<S>(
+        &self,
+        _x: &ndarray::ArrayBase<S, ndarray::Ix1>,
+        args: &Args,
+    ) -> ndarray::Array2<f64>
+    where
+        S: ndarray::Data<Elem = Self::Elem>,
+    {
+        ndarray::array![[1.0, args.dt], [0.0, 1.0]]
+    }
+}
+
+impl crate::ekf::HxJacobian for Functions {
+    type Elem = f64;
+
+    fn hx_jacobian<S>(&self, _x: &ndarray::ArrayBase<S, ndarray::Ix1>) -> ndarray::Array2<f64>
+    where
+        S: ndarray::Data<Elem = Self::Elem>,
+    {
+        ndarray::array![[1.0, 0.0]]
+    }
+}
+
+impl crate::ukf::Mean<f64> for Functions {
+    fn mean<Ss, Swm>(
+        &self,
+        sigmas: &ndarray::ArrayBase<Ss, ndarray::Ix2>,
+        Wm: &ndarray::ArrayBase<Swm, ndarray::Ix1>,
+    ) -> ndarray::Array1<f64>
+    where
+        Ss: ndarray::Data<Elem = f64>,
+        Swm: ndarray::Data<Elem = f64>,
+    {
+        Wm.dot(sigmas)
+    }
+}
+
+impl<A: num_traits::Float> crate::Add<A> for Functions {
+    fn add<Sa, Sb>(
+        &self,
+        a: &ndarray::ArrayBase<Sa, ndarray::Ix1>,
+        b: &ndarray::ArrayBase<Sb, ndarray::Ix1>,
+    ) -> ndarray::Array1<A>
+    where
+        Sa: ndarray::Data<Elem = A>,
+        Sb: ndarray::Data<Elem = A>,
+    {
+        a + b
+    }
+}
+
+impl<A: num_traits::Float> crate::Subtract<A> for Functions {
+    fn subtract<Sa, Sb>(
+        &self,
+        a: &ndarray::ArrayBase<Sa, ndarray::Ix1>,
+        b: &ndarray::ArrayBase<Sb, ndarray::Ix1>,
+    ) -> ndarray::Array1<A>
+    where
+        Sa: ndarray::Data<Elem = A>,
+        Sb: ndarray::Data<Elem = A>,
+    {
+        a - b
+    }
+}
@@ -4,9 +4,11 @@ use crate::Error;
 use crate::Filter;
 use crate::SetDt;
 use ndarray::azip;
+use ndarray_linalg::cholesky::Cholesky;
 use ndarray_linalg::solve::Inverse;
-
-type RTSResult<A> = (Vec<ndarray::Array1<A>>, Vec<ndarray::Array2<A>>);
+use ndarray_linalg::triangular::SolveTriangular;
+use ndarray_linalg::Eigh;
+use ndarray_linalg::QR;
 
 pub trait Mean<A> {
     fn mean<Ss, Swm>(
@@ -55,7 +57,6 @@ pub struct Ukf<'a, FP, FNSX, ARGSFX, FNSZ, A, Sz> {
     pub Q: ndarray::Array2<A>,
 
     // observation
-    z: ndarray::Array1<A>,
     pub R: ndarray::Array2<A>,
 
     // sigma points
@@ -65,15 +66,30 @@ pub struct Ukf<'a, FP, FNSX, ARGSFX, FNSZ, A, Sz> {
 
     // predict
     sigmas_f: ndarray::Array2<A>,
+    /// `sigmas_f` don't match `x` and `P` after an update, they're redrawn
+    /// by the next update
+    sigmas_stale: bool,
 
     // update
-    sigmas_h: ndarray::Array2<A>,
     pub y: ndarray::Array1<A>,
     pub S: ndarray::Array2<A>,
 
+    pub gate: Option<crate::gating::Gate<A>>,
+    gated: bool,
+
     pd_Sz: std::marker::PhantomData<Sz>,
 }
 
+/// result of an update, applied to the filter afterwards so the
+/// measurement model can be borrowed from it
+struct Correction<A> {
+    y: ndarray::Array1<A>,
+    S: ndarray::Array2<A>,
+    gated: bool,
+    /// the new state and covariance, `None` if the measurement was rejected
+    xP: Option<(ndarray::Array1<A>, ndarray::Array2<A>)>,
+}
+
 impl<'a, FP, FNSX, ARGSFX, FNSZ, A, Sz> SetDt<A> for Ukf<'a, FP, FNSX, ARGSFX, FNSZ, A, Sz>
 where
     ARGSFX: SetDt<A> + ApplyDt<A>,
@@ -128,48 +144,15 @@ where
 
         // update sigma points to reflect the new variance of the points
         self.sigmas_f = self.points_fn.sigma_points(&self.x, &self.P)?;
+        self.sigmas_stale = false;
 
         Ok(())
     }
 
     fn update(&mut self, z: &ndarray::ArrayBase<Sz, ndarray::Ix1>) -> Result<(), crate::Error> {
-        // transform sigma points into measurement space
-        for i in 0..self.sigmas_f.nrows() {
-            self.sigmas_h.index_axis_mut(ndarray::Axis(0), i).assign(
-                &self
-                    .fns_x
-                    .hx(&self.sigmas_f.index_axis(ndarray::Axis(0), i)),
-            );
-        }
-
-        // mean and covariance of prediction passed through UT
-        let (zp, S) = crate::unscented_transform(
-            &self.sigmas_h,
-            &self.Wm,
-            &self.Wc,
-            &self.R,
-            |sigmas, mean| self.fns_z.mean(sigmas, mean),
-            |a, b| self.fns_z.subtract(a, b),
-        );
-
-        // residual of z
-        let y = self.fns_z.subtract(z, &zp);
-
-        // compute cross variance of the state and the measurements
-        let Pxz = self.cross_variance(&self.x, &zp, &self.sigmas_f, &self.sigmas_h);
-
-        // Kalman gain
-        let K = Pxz.dot(&S.inv()?);
-
-        // new state estimate
-        self.x = &self.x + &K.dot(&y);
-        self.P = &self.P - &K.dot(&S.dot(&K.t()));
-
-        // provide internal results
-        self.y = y;
-        self.S = S;
-
-        Ok(())
+        self.redraw_sigmas()?;
+        let correction = self.correction(z, &self.fns_x, &self.fns_z, &self.R)?;
+        self.apply_correction(correction)
     }
 
     /// Computed from the log-likelihood. The log-likelihood can be very
@@ -228,6 +211,38 @@ impl<'a, FP, FNSX, ARGSFX, FNSZ, A, Sz> crate::R for Ukf<'a, FP, FNSX, ARGSFX, F
     }
 }
 
+impl<'a, FP, FNSX, ARGSFX, FNSZ, A, Sz> crate::Innovation
+    for Ukf<'a, FP, FNSX, ARGSFX, FNSZ, A, Sz>
+{
+    type Elem = A;
+
+    fn y(&self) -> &ndarray::Array1<A> {
+        &self.y
+    }
+
+    fn S(&self) -> &ndarray::Array2<A> {
+        &self.S
+    }
+}
+
+impl<'a, FP, FNSX, ARGSFX, FNSZ, A, Sz> crate::gating::Gating
+    for Ukf<'a, FP, FNSX, ARGSFX, FNSZ, A, Sz>
+{
+    type Elem = A;
+
+    fn gate(&self) -> Option<&crate::gating::Gate<A>> {
+        self.gate.as_ref()
+    }
+
+    fn set_gate(&mut self, gate: Option<crate::gating::Gate<A>>) {
+        self.gate = gate;
+    }
+
+    fn gated(&self) -> bool {
+        self.gated
+    }
+}
+
 impl<'a, FP, FNSX, ARGSFX, FNSZ, A, Sz> Ukf<'a, FP, FNSX, ARGSFX, FNSZ, A, Sz>
 where
     FP: crate::sigma_points::SigmaPoints<Elem = A>,
@@ -258,7 +273,6 @@ where
             P: ndarray::Array::ones((dim_x, dim_x)),
             Q: ndarray::Array::eye(dim_x),
 
-            z: ndarray::Array::zeros(dim_z),
             R: ndarray::Array::eye(dim_z),
 
             points_fn,
@@ -266,34 +280,129 @@ where
             Wm: points_fn.weights_mean(),
 
             sigmas_f: ndarray::Array::zeros((points_fn.num_sigmas(), dim_x)),
-            sigmas_h: ndarray::Array::zeros((points_fn.num_sigmas(), dim_z)),
+            sigmas_stale: false,
             y: ndarray::Array::zeros(dim_z),
             S: ndarray::Array::zeros((dim_z, dim_z)),
 
+            gate: None,
+            gated: false,
+
             pd_Sz: std::marker::PhantomData,
         }
     }
 
-    fn cross_variance<Sx, Szl, Sf, Sh>(
+    /// update with a measurement model other than the one the filter was
+    /// created with, e.g. for sensors with their own rate or partial
+    /// measurements. `z` and `R` can have a different dimension than the
+    /// regular measurements, `y` and `S` have the dimension of `z`
+    /// afterwards.
+    pub fn update_with<H, Szl>(
+        &mut self,
+        z: &ndarray::ArrayBase<Szl, ndarray::Ix1>,
+        model: &H,
+        R: &ndarray::Array2<A>,
+    ) -> Result<(), Error>
+    where
+        H: Hx<Elem = A> + Mean<A> + crate::Subtract<A>,
+        Szl: ndarray::Data<Elem = A>,
+    {
+        self.redraw_sigmas()?;
+        let correction = self.correction(z, model, model, R)?;
+        self.apply_correction(correction)
+    }
+
+    /// further updates before the next predict have to start from the
+    /// corrected state
+    fn redraw_sigmas(&mut self) -> Result<(), Error> {
+        if self.sigmas_stale {
+            self.sigmas_f = self.points_fn.sigma_points(&self.x, &self.P)?;
+            self.sigmas_stale = false;
+        }
+
+        Ok(())
+    }
+
+    fn correction<H, FZ, Szl>(
         &self,
-        x: &ndarray::ArrayBase<Sx, ndarray::Ix1>,
         z: &ndarray::ArrayBase<Szl, ndarray::Ix1>,
-        sigmas_f: &ndarray::ArrayBase<Sf, ndarray::Ix2>,
-        sigmas_h: &ndarray::ArrayBase<Sh, ndarray::Ix2>,
-    ) -> ndarray::Array2<A>
+        hx: &H,
+        fns_z: &FZ,
+        R: &ndarray::Array2<A>,
+    ) -> Result<Correction<A>, Error>
     where
-        Sx: ndarray::Data<Elem = A>,
+        H: Hx<Elem = A>,
+        FZ: Mean<A> + crate::Subtract<A>,
         Szl: ndarray::Data<Elem = A>,
-        Sf: ndarray::Data<Elem = A>,
-        Sh: ndarray::Data<Elem = A>,
     {
-        let mut Pxz = ndarray::Array2::<A>::zeros((self.x.dim(), self.z.dim()));
-        azip!((&Wci in &self.Wc, sfi in sigmas_f.rows(), shi in sigmas_h.rows()) {
-            let dx = self.fns_x.subtract(&sfi, x);
-            let dz = self.fns_z.subtract(&shi, z);
+        // transform sigma points into measurement space
+        let mut sigmas_h = ndarray::Array2::zeros((self.sigmas_f.nrows(), z.len()));
+        for i in 0..self.sigmas_f.nrows() {
+            sigmas_h
+                .index_axis_mut(ndarray::Axis(0), i)
+                .assign(&hx.hx(&self.sigmas_f.index_axis(ndarray::Axis(0), i)));
+        }
+
+        // mean and covariance of prediction passed through UT
+        let (zp, mut S) = crate::unscented_transform(
+            &sigmas_h,
+            &self.Wm,
+            &self.Wc,
+            R,
+            |sigmas, mean| fns_z.mean(sigmas, mean),
+            |a, b| fns_z.subtract(a, b),
+        );
+
+        // residual of z
+        let y = fns_z.subtract(z, &zp);
+
+        let R_scale = crate::gating::apply(self.gate.as_ref(), &y, &mut S, R)?;
+        let gated = R_scale != Some(A::one());
+        if R_scale.is_none() {
+            // rejected
+            return Ok(Correction {
+                y,
+                S,
+                gated,
+                xP: None,
+            });
+        }
+
+        // compute cross variance of the state and the measurements
+        let mut Pxz = ndarray::Array2::<A>::zeros((self.x.dim(), z.len()));
+        azip!((&Wci in &self.Wc, sfi in self.sigmas_f.rows(), shi in sigmas_h.rows()) {
+            let dx = self.fns_x.subtract(&sfi, &self.x);
+            let dz = fns_z.subtract(&shi, &zp);
             Pxz += &(math::outer_product(&dx, &dz) * Wci);
         });
-        Pxz
+
+        // Kalman gain
+        let K = Pxz.dot(&S.inv()?);
+
+        // new state estimate
+        let x = &self.x + &K.dot(&y);
+        let P = &self.P - &K.dot(&S.dot(&K.t()));
+
+        Ok(Correction {
+            y,
+            S,
+            gated,
+            xP: Some((x, P)),
+        })
+    }
+
+    fn apply_correction(&mut self, correction: Correction<A>) -> Result<(), Error> {
+        if let Some((x, P)) = correction.xP {
+            self.x = x;
+            self.P = P;
+            self.sigmas_stale = true;
+        }
+
+        // provide internal results
+        self.y = correction.y;
+        self.S = correction.S;
+        self.gated = correction.gated;
+
+        Ok(())
     }
 
     /// log-likelihood of the last measurement
@@ -308,7 +417,7 @@ where
         Ps: &[ndarray::ArrayBase<Sx, ndarray::Ix2>],
         Qs: Option<&[ndarray::Array2<A>]>,
         dts: &[A],
-    ) -> Result<RTSResult<A>, crate::Error>
+    ) -> Result<crate::RTSResult<A>, crate::Error>
     where
         Sx: ndarray::Data<Elem = A>,
         ARGSFX: crate::SetDt<A>,
@@ -381,3 +490,540 @@ where
         Ok((xss, Pss))
     }
 }
+
+/// rank-1 update of the upper cholesky factor `U`, so that `U^T * U`
+/// becomes `U^T * U + sign * v * v^T`
+fn cholupdate(
+    U: &mut ndarray::Array2<f64>,
+    mut v: ndarray::Array1<f64>,
+    sign: f64,
+) -> Result<(), Error> {
+    let n = v.len();
+    for k in 0..n {
+        let r2 = U[(k, k)].powi(2) + sign * v[k].powi(2);
+        if !r2.is_finite() || r2 <= 0.0 {
+            return Err(Error::CholeskyDowndate);
+        }
+
+        let r = r2.sqrt();
+        let c = r / U[(k, k)];
+        let s = v[k] / U[(k, k)];
+        U[(k, k)] = r;
+
+        for j in k + 1..n {
+            U[(k, j)] = (U[(k, j)] + sign * s * v[j]) / c;
+            v[j] = c * v[j] - s * U[(k, j)];
+        }
+    }
+
+    Ok(())
+}
+
+/// returns `B` with `B^T * B = A`. Unlike cholesky this works for singular
+/// matrices, which most process noise matrices are.
+pub(crate) fn sqrt_psd(A: &ndarray::Array2<f64>) -> Result<ndarray::Array2<f64>, Error> {
+    let (e, V) = A.eigh(ndarray_linalg::UPLO::Lower)?;
+
+    let mut B = V.reversed_axes();
+    for (mut row, e) in B.rows_mut().into_iter().zip(e) {
+        row *= e.max(0.0).sqrt();
+    }
+
+    Ok(B)
+}
+
+/// square-root version of [unscented_transform](crate::unscented_transform),
+/// returns the upper cholesky factor of the covariance
+fn unscented_transform_sqrt<Ss>(
+    sigmas: &ndarray::ArrayBase<Ss, ndarray::Ix2>,
+    mean: &ndarray::Array1<f64>,
+    Wc: &ndarray::Array1<f64>,
+    noise: &ndarray::Array2<f64>,
+    residual_fn: impl Fn(&ndarray::ArrayView1<f64>, &ndarray::Array1<f64>) -> ndarray::Array1<f64>,
+) -> Result<ndarray::Array2<f64>, Error>
+where
+    Ss: ndarray::Data<Elem = f64>,
+{
+    let (nsigmas, n) = sigmas.dim();
+    assert_eq!(Wc.dim(), nsigmas);
+    assert_eq!(noise.dim(), (n, n));
+
+    // the first sigma point is handled by a rank-1 update because its weight
+    // can be negative
+    let mut A = ndarray::Array2::<f64>::zeros((nsigmas - 1 + n, n));
+    for k in 1..nsigmas {
+        if Wc[k] < 0.0 {
+            return Err(Error::InvalidArgument);
+        }
+
+        A.row_mut(k - 1)
+            .assign(&(residual_fn(&sigmas.row(k), mean) * Wc[k].sqrt()));
+    }
+    A.slice_mut(ndarray::s![nsigmas - 1.., ..])
+        .assign(&sqrt_psd(noise)?);
+
+    let (_, mut U) = A.qr()?;
+
+    // QR doesn't guarantee a positive diagonal
+    for k in 0..n {
+        if U[(k, k)] < 0.0 {
+            U.row_mut(k).mapv_inplace(|v| -v);
+        }
+    }
+
+    let y0 = residual_fn(&sigmas.row(0), mean) * Wc[0].abs().sqrt();
+    cholupdate(&mut U, y0, Wc[0].signum())?;
+
+    Ok(U)
+}
+
+/// Square-root UKF, propagates the cholesky factor of `P` instead of `P`
+/// itself. This keeps `P` positive definite on long runs where the
+/// subtraction in the update of [Ukf] can make it lose that property.
+#[derive(Clone, Debug)]
+pub struct SrUkf<'a, FP, FNSX, ARGSFX, FNSZ, Sz> {
+    fns_x: FNSX,
+    args_fx: ARGSFX,
+    fns_z: FNSZ,
+
+    // state
+    pub x: ndarray::Array1<f64>,
+    P: ndarray::Array2<f64>,
+    /// upper cholesky factor of `P`
+    sqrt_P: ndarray::Array2<f64>,
+    /// `P` was changed using [P_mut](Filter::P_mut)
+    P_changed: bool,
+    pub Q: ndarray::Array2<f64>,
+
+    // observation
+    z: ndarray::Array1<f64>,
+    pub R: ndarray::Array2<f64>,
+
+    // sigma points
+    points_fn: &'a FP,
+    pub Wc: ndarray::Array1<f64>,
+    pub Wm: ndarray::Array1<f64>,
+
+    // predict
+    sigmas_f: ndarray::Array2<f64>,
+
+    // update
+    sigmas_h: ndarray::Array2<f64>,
+    pub y: ndarray::Array1<f64>,
+    pub S: ndarray::Array2<f64>,
+
+    pub gate: Option<crate::gating::Gate<f64>>,
+    gated: bool,
+
+    pd_Sz: std::marker::PhantomData<Sz>,
+}
+
+impl<'a, FP, FNSX, ARGSFX, FNSZ, Sz> SetDt<f64> for SrUkf<'a, FP, FNSX, ARGSFX, FNSZ, Sz>
+where
+    ARGSFX: SetDt<f64> + ApplyDt<f64>,
+{
+    fn set_dt(&mut self, dt: &f64) {
+        self.args_fx.set_dt(dt);
+        self.args_fx.apply_dt(&mut self.Q);
+    }
+}
+
+impl<'a, FP, FNSX, ARGSFX, FNSZ, Sz> Filter for SrUkf<'a, FP, FNSX, ARGSFX, FNSZ, Sz>
+where
+    FP: crate::sigma_points::SigmaPoints<Elem = f64>,
+    FNSX: Fx<ARGSFX, Elem = f64>
+        + Hx<Elem = f64>
+        + Mean<f64>
+        + crate::Add<f64>
+        + crate::Subtract<f64>,
+    FNSZ: Mean<f64> + crate::Subtract<f64>,
+    Sz: ndarray::Data<Elem = f64>,
+{
+    type Elem = f64;
+    type Meas = ndarray::ArrayBase<Sz, ndarray::Ix1>;
+
+    fn predict(&mut self) -> Result<(), crate::Error> {
+        self.refactorize()?;
+
+        // calculate sigma points for given mean and covariance
+        let sigmas = self.points_fn.sigma_points_sqrt(&self.x, &self.sqrt_P)?;
+
+        for i in 0..sigmas.nrows() {
+            self.sigmas_f.index_axis_mut(ndarray::Axis(0), i).assign(
+                &self
+                    .fns_x
+                    .fx(&sigmas.index_axis(ndarray::Axis(0), i), &self.args_fx),
+            );
+        }
+
+        // and pass sigmas through the unscented transform to compute prior
+        let x_prior = self.fns_x.mean(&self.sigmas_f, &self.Wm);
+        let sqrt_P_prior =
+            unscented_transform_sqrt(&self.sigmas_f, &x_prior, &self.Wc, &self.Q, |a, b| {
+                self.fns_x.subtract(a, b)
+            })?;
+
+        self.x = x_prior;
+        self.sqrt_P = sqrt_P_prior;
+        self.P = self.sqrt_P.t().dot(&self.sqrt_P);
+
+        // update sigma points to reflect the new variance of the points
+        self.sigmas_f = self.points_fn.sigma_points_sqrt(&self.x, &self.sqrt_P)?;
+
+        Ok(())
+    }
+
+    fn update(&mut self, z: &ndarray::ArrayBase<Sz, ndarray::Ix1>) -> Result<(), crate::Error> {
+        self.refactorize()?;
+
+        // transform sigma points into measurement space
+        for i in 0..self.sigmas_f.nrows() {
+            self.sigmas_h.index_axis_mut(ndarray::Axis(0), i).assign(
+                &self
+                    .fns_x
+                    .hx(&self.sigmas_f.index_axis(ndarray::Axis(0), i)),
+            );
+        }
+
+        // mean and covariance of prediction passed through UT
+        let zp = self.fns_z.mean(&self.sigmas_h, &self.Wm);
+        let mut sqrt_S =
+            unscented_transform_sqrt(&self.sigmas_h, &zp, &self.Wc, &self.R, |a, b| {
+                self.fns_z.subtract(a, b)
+            })?;
+
+        // residual of z
+        let y = self.fns_z.subtract(z, &zp);
+
+        let mut S = sqrt_S.t().dot(&sqrt_S);
+        let R_scale = crate::gating::apply(self.gate.as_ref(), &y, &mut S, &self.R)?;
+        self.gated = R_scale != Some(1.0);
+        match R_scale {
+            None => {
+                // rejected
+                self.y = y;
+                self.S = S;
+                return Ok(());
+            }
+            Some(R_scale) if R_scale != 1.0 => {
+                let R = &self.R * R_scale;
+                sqrt_S = unscented_transform_sqrt(&self.sigmas_h, &zp, &self.Wc, &R, |a, b| {
+                    self.fns_z.subtract(a, b)
+                })?;
+            }
+            Some(_) => (),
+        }
+
+        // compute cross variance of the state and the measurements
+        let mut Pxz = ndarray::Array2::<f64>::zeros((self.x.dim(), self.z.dim()));
+        azip!((&Wci in &self.Wc, sfi in self.sigmas_f.rows(), shi in self.sigmas_h.rows()) {
+            let dx = self.fns_x.subtract(&sfi, &self.x);
+            let dz = self.fns_z.subtract(&shi, &zp);
+            Pxz += &(math::outer_product(&dx, &dz) * Wci);
+        });
+
+        // Kalman gain, K = Pxz * (Sz^T * Sz)^-1 using two triangular solves
+        let Kt = sqrt_S.t().solve_triangular(
+            ndarray_linalg::UPLO::Lower,
+            ndarray_linalg::Diag::NonUnit,
+            &Pxz.t().to_owned(),
+        )?;
+        let Kt = sqrt_S.solve_triangular(
+            ndarray_linalg::UPLO::Upper,
+            ndarray_linalg::Diag::NonUnit,
+            &Kt,
+        )?;
+        let K = Kt.reversed_axes();
+
+        // new state estimate
+        self.x = self.fns_x.add(&self.x, &K.dot(&y));
+
+        // P - K * S * K^T as one downdate per column of K * Sz^T
+        let U = K.dot(&sqrt_S.t());
+        for u in U.columns() {
+            cholupdate(&mut self.sqrt_P, u.to_owned(), -1.0)?;
+        }
+        self.P = self.sqrt_P.t().dot(&self.sqrt_P);
+
+        // provide internal results
+        self.y = y;
+        self.S = S;
+
+        Ok(())
+    }
+
+    /// clamped to `f64::MIN_POSITIVE` like the one of [Ukf]
+    fn likelihood(&self) -> Result<f64, Error> {
+        let ll = self.log_likelihood()?;
+        let mut l = ll.exp();
+        if l == 0.0 {
+            l = f64::MIN_POSITIVE;
+        }
+
+        Ok(l)
+    }
+
+    fn x(&self) -> &ndarray::Array1<f64> {
+        &self.x
+    }
+
+    fn x_mut(&mut self) -> &mut ndarray::Array1<f64> {
+        &mut self.x
+    }
+
+    fn P(&self) -> &ndarray::Array2<f64> {
+        &self.P
+    }
+
+    /// the cholesky factor gets recalculated on the next predict or update
+    fn P_mut(&mut self) -> &mut ndarray::Array2<f64> {
+        self.P_changed = true;
+        &mut self.P
+    }
+}
+
+impl<'a, FP, FNSX, ARGSFX, FNSZ, Sz> crate::Q for SrUkf<'a, FP, FNSX, ARGSFX, FNSZ, Sz> {
+    type Elem = f64;
+
+    fn Q(&self) -> &ndarray::Array2<f64> {
+        &self.Q
+    }
+
+    fn Q_mut(&mut self) -> &mut ndarray::Array2<f64> {
+        &mut self.Q
+    }
+}
+
+impl<'a, FP, FNSX, ARGSFX, FNSZ, Sz> crate::R for SrUkf<'a, FP, FNSX, ARGSFX, FNSZ, Sz> {
+    type Elem = f64;
+
+    fn R(&self) -> &ndarray::Array2<f64> {
+        &self.R
+    }
+
+    fn R_mut(&mut self) -> &mut ndarray::Array2<f64> {
+        &mut self.R
+    }
+}
+
+impl<'a, FP, FNSX, ARGSFX, FNSZ, Sz> crate::Innovation for SrUkf<'a, FP, FNSX, ARGSFX, FNSZ, Sz> {
+    type Elem = f64;
+
+    fn y(&self) -> &ndarray::Array1<f64> {
+        &self.y
+    }
+
+    fn S(&self) -> &ndarray::Array2<f64> {
+        &self.S
+    }
+}
+
+impl<'a, FP, FNSX, ARGSFX, FNSZ, Sz> crate::gating::Gating
+    for SrUkf<'a, FP, FNSX, ARGSFX, FNSZ, Sz>
+{
+    type Elem = f64;
+
+    fn gate(&self) -> Option<&crate::gating::Gate<f64>> {
+        self.gate.as_ref()
+    }
+
+    fn set_gate(&mut self, gate: Option<crate::gating::Gate<f64>>) {
+        self.gate = gate;
+    }
+
+    fn gated(&self) -> bool {
+        self.gated
+    }
+}
+
+impl<'a, FP, FNSX, ARGSFX, FNSZ, Sz> SrUkf<'a, FP, FNSX, ARGSFX, FNSZ, Sz>
+where
+    FP: crate::sigma_points::SigmaPoints<Elem = f64>,
+{
+    pub fn new(
+        dim_x: usize,
+        dim_z: usize,
+        points_fn: &'a FP,
+        fns_x: FNSX,
+        args_fx: ARGSFX,
+        fns_z: FNSZ,
+    ) -> Self {
+        Self {
+            fns_x,
+            args_fx,
+            fns_z,
+
+            x: ndarray::Array::zeros(dim_x),
+            P: ndarray::Array::eye(dim_x),
+            sqrt_P: ndarray::Array::eye(dim_x),
+            P_changed: false,
+            Q: ndarray::Array::eye(dim_x),
+
+            z: ndarray::Array::zeros(dim_z),
+            R: ndarray::Array::eye(dim_z),
+
+            points_fn,
+            Wc: points_fn.weights_covariance(),
+            Wm: points_fn.weights_mean(),
+
+            sigmas_f: ndarray::Array::zeros((points_fn.num_sigmas(), dim_x)),
+            sigmas_h: ndarray::Array::zeros((points_fn.num_sigmas(), dim_z)),
+            y: ndarray::Array::zeros(dim_z),
+            S: ndarray::Array::zeros((dim_z, dim_z)),
+
+            gate: None,
+            gated: false,
+
+            pd_Sz: std::marker::PhantomData,
+        }
+    }
+
+    /// upper cholesky factor of `P`
+    pub fn sqrt_P(&self) -> &ndarray::Array2<f64> {
+        &self.sqrt_P
+    }
+
+    fn refactorize(&mut self) -> Result<(), Error> {
+        if self.P_changed {
+            self.sqrt_P = self.P.cholesky(ndarray_linalg::UPLO::Upper)?;
+            self.P_changed = false;
+        }
+
+        Ok(())
+    }
+
+    /// log-likelihood of the last measurement
+    pub fn log_likelihood(&self) -> Result<f64, Error> {
+        let mean = ndarray::Array1::zeros(self.y.len());
+        Ok(math::multivariate::logpdf(&self.y, &mean, &self.S, true)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_support::Args;
+    use crate::test_support::Functions;
+    use crate::Filter;
+
+    /// measures only the velocity
+    struct Velocity;
+
+    impl super::Hx for Velocity {
+        type Elem = f64;
+
+        fn hx<S>(&self, x: &ndarray::ArrayBase<S, ndarray::Ix1>) -> ndarray::Array1<f64>
+        where
+            S: ndarray::Data<Elem = Self::Elem>,
+        {
+            ndarray::array![x[1]]
+        }
+    }
+
+    impl super::Mean<f64> for Velocity {
+        fn mean<Ss, Swm>(
+            &self,
+            sigmas: &ndarray::ArrayBase<Ss, ndarray::Ix2>,
+            Wm: &ndarray::ArrayBase<Swm, ndarray::Ix1>,
+        ) -> ndarray::Array1<f64>
+        where
+            Ss: ndarray::Data<Elem = f64>,
+            Swm: ndarray::Data<Elem = f64>,
+        {
+            Wm.dot(sigmas)
+        }
+    }
+
+    impl crate::Subtract<f64> for Velocity {
+        fn subtract<Sa, Sb>(
+            &self,
+            a: &ndarray::ArrayBase<Sa, ndarray::Ix1>,
+            b: &ndarray::ArrayBase<Sb, ndarray::Ix1>,
+        ) -> ndarray::Array1<f64>
+        where
+            Sa: ndarray::Data<Elem = f64>,
+            Sb: ndarray::Data<Elem = f64>,
+        {
+            a - b
+        }
+    }
+
+    /// the velocity is measured at a lower rate and in between updates of
+    /// the position. For a linear model the UKF has to match the linear
+    /// filter.
+    #[test]
+    fn multi_rate() {
+        let dt = 0.1;
+        let points_fn =
+            crate::sigma_points::MerweScaledSigmaPoints::new(2, 1.0, 2.0, 1.0, Functions);
+
+        let mut ukf = super::Ukf::new(2, 1, &points_fn, Functions, Args { dt }, Functions);
+        let mut kf = crate::test_support::cv_filter(dt);
+
+        let P = ndarray::array![[1.0, 0.1], [0.1, 2.0]];
+        let Q = ndarray::array![[0.0, 0.0], [0.0, 0.01]];
+        ukf.P = P.clone();
+        ukf.Q = Q.clone();
+        kf.P = P;
+        kf.Q = Q;
+
+        let H_v = ndarray::array![[0.0, 1.0]];
+        let R_v = ndarray::array![[0.5]];
+
+        for i in 0..50 {
+            let t = i as f64 * dt;
+
+            ukf.predict().unwrap();
+            kf.predict().unwrap();
+
+            // position dropouts
+            if i % 7 != 3 {
+                let z = ndarray::array![t.powi(2)];
+                ukf.update(&z).unwrap();
+                kf.update(&z).unwrap();
+            }
+
+            if i % 5 == 0 {
+                let z = ndarray::array![2.0 * t];
+                ukf.update_with(&z, &Velocity, &R_v).unwrap();
+                kf.update_with(&z, &H_v, &R_v).unwrap();
+            }
+
+            testlib::assert_arr1_eq(&ukf.x, &kf.x);
+            testlib::assert_arr2_eq(&ukf.P, &kf.P);
+        }
+    }
+
+    /// both variants have to give the same results
+    #[test]
+    fn sqrt() {
+        let dt = 0.1;
+        let points_fn =
+            crate::sigma_points::MerweScaledSigmaPoints::new(2, 1.0, 2.0, 1.0, Functions);
+
+        let mut ukf = super::Ukf::new(2, 1, &points_fn, Functions, Args { dt }, Functions);
+        let mut srukf = super::SrUkf::new(2, 1, &points_fn, Functions, Args { dt }, Functions);
+
+        let P = ndarray::array![[1.0, 0.1], [0.1, 2.0]];
+        let Q = ndarray::array![[0.0, 0.0], [0.0, 0.01]];
+        ukf.P = P.clone();
+        ukf.Q = Q.clone();
+        *srukf.P_mut() = P;
+        srukf.Q = Q;
+
+        for i in 0..50 {
+            let z = ndarray::array![(i as f64 * dt).powi(2)];
+
+            ukf.predict().unwrap();
+            ukf.update(&z).unwrap();
+            srukf.predict().unwrap();
+            srukf.update(&z).unwrap();
+
+            testlib::assert_arr1_eq(&srukf.x, &ukf.x);
+            testlib::assert_arr2_eq(srukf.P(), &ukf.P);
+            testlib::assert_arr2_eq(&srukf.sqrt_P().t().dot(srukf.sqrt_P()), &ukf.P);
+        }
+
+        let diff = srukf.likelihood().unwrap() - ukf.likelihood().unwrap();
+        assert!(diff.abs() < 1.0e-6);
+    }
+}
@@ -2,6 +2,7 @@ mod error;
 pub use error::Error;
 
 pub mod multivariate;
+pub mod stats;
 
 pub const GRAVITY: f64 = 9.80665;
 
@@ -208,6 +209,23 @@ pub fn xy2rt(x: f64, y: f64) -> (f64, f64) {
     (xy2r(x, y), xy2t(x, y))
 }
 
+/// standard pressure at sea level, unit: hPa
+pub const PRESSURE_SEA_LEVEL: f64 = 1013.25;
+
+/// pressure altitude of a barometer reading
+/// pressure unit: hPa
+/// return unit: m
+pub fn pressure2altitude(pressure: f64) -> f64 {
+    145_366.45 * (1.0 - (pressure / PRESSURE_SEA_LEVEL).powf(0.190_284)) * 0.3048
+}
+
+/// inverse of [pressure2altitude]
+/// altitude unit: m
+/// return unit: hPa
+pub fn altitude2pressure(altitude: f64) -> f64 {
+    PRESSURE_SEA_LEVEL * (1.0 - altitude / 0.3048 / 145_366.45).powf(1.0 / 0.190_284)
+}
+
 pub struct Iir<T> {
     coeff: T,
     prev: Option<T>,
@@ -235,6 +253,62 @@ where
     }
 }
 
+/// matrix exponential, uses a [6/6] padé approximation with scaling and
+/// squaring
+pub fn expm<S, A>(a: &ndarray::ArrayBase<S, ndarray::Ix2>) -> Result<ndarray::Array2<A>, Error>
+where
+    S: ndarray::Data<Elem = A>,
+    A: ndarray_linalg::Lapack + num_traits::Float + ndarray::ScalarOperand,
+{
+    use ndarray_linalg::Inverse;
+
+    const ORDER: usize = 6;
+
+    if !a.is_square() {
+        return Err(Error::NotSquare);
+    }
+
+    let one = A::one();
+    let half = one / (one + one);
+    let cast = |v: usize| <A as num_traits::NumCast>::from(v).unwrap();
+
+    // scale the matrix until the approximation is accurate
+    let norm = a
+        .rows()
+        .into_iter()
+        .map(|row| row.fold(A::zero(), |acc, v| acc + num_traits::Float::abs(*v)))
+        .fold(A::zero(), num_traits::Float::max);
+    let mut scale = one;
+    let mut squarings = 0;
+    while norm * scale > half {
+        scale = scale * half;
+        squarings += 1;
+    }
+    let a = a.mapv(|v| v * scale);
+
+    let mut c = one;
+    let mut term = ndarray::Array2::<A>::eye(a.nrows());
+    let mut num = term.clone();
+    let mut den = term.clone();
+    for k in 1..=ORDER {
+        c = c * cast(ORDER - k + 1) / cast(k * (2 * ORDER - k + 1));
+        term = term.dot(&a);
+        num = num + &term * c;
+        if k % 2 == 0 {
+            den = den + &term * c;
+        } else {
+            den = den - &term * c;
+        }
+    }
+
+    let mut e = den.inv()?.dot(&num);
+    for _ in 0..squarings {
+        e = e.dot(&e);
+    }
+
+    Ok(e)
+}
+
 #[cfg(test)]
 mod tests {
     use approx::assert_abs_diff_eq;
@@ -304,6 +378,28 @@ mod tests {
         assert_abs_diff_eq!(C, (85.6f64).to_radians(), epsilon = 1.0e-3);
     }
 
+    #[test]
+    fn expm() {
+        let e = super::expm(&ndarray::Array2::<f64>::zeros((3, 3))).unwrap();
+        testlib::assert_arr2_eq(&e, &ndarray::Array2::eye(3));
+
+        let e = super::expm(&array![[0.0, 1.0], [0.0, 0.0]]).unwrap();
+        testlib::assert_arr2_eq(&e, &array![[1.0, 1.0], [0.0, 1.0]]);
+
+        let e = super::expm(&array![[1.0, 0.0], [0.0, -2.0]]).unwrap();
+        testlib::assert_arr2_eq(&e, &array![[1.0f64.exp(), 0.0], [0.0, (-2.0f64).exp()]]);
+
+        // large enough to need scaling
+        let t = 3.0f64;
+        let e = super::expm(&array![[0.0, -t], [t, 0.0]]).unwrap();
+        testlib::assert_arr2_eq(&e, &array![[t.cos(), -t.sin()], [t.sin(), t.cos()]]);
+
+        assert!(matches!(
+            super::expm(&ndarray::Array2::<f64>::zeros((2, 3))),
+            Err(crate::Error::NotSquare)
+        ));
+    }
+
     #[test]
     fn rot2d() {
         let x = super::rot2d(&array![0.0, 1.0], std::f64::consts::FRAC_PI_2);
@@ -397,4 +493,22 @@ mod tests {
             epsilon = 1.0e-15
         );
     }
+
+    #[test]
+    fn pressure_altitude() {
+        assert_abs_diff_eq!(
+            super::pressure2altitude(super::PRESSURE_SEA_LEVEL),
+            0.0,
+            epsilon = 1.0e-9
+        );
+        assert_abs_diff_eq!(super::pressure2altitude(900.0), 988.09, epsilon = 0.01);
+
+        for altitude in [-100.0, 0.0, 123.4, 2000.0] {
+            assert_abs_diff_eq!(
+                super::pressure2altitude(super::altitude2pressure(altitude)),
+                altitude,
+                epsilon = 1.0e-6
+            );
+        }
+    }
 }
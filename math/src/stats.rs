@@ -0,0 +1,213 @@
+/// natural logarithm of the gamma function, using the Lanczos approximation
+pub fn ln_gamma(x: f64) -> f64 {
+    const COEFFS: [f64; 6] = [
+        76.180_091_729_471_46,
+        -86.505_320_329_416_77,
+        24.014_098_240_830_91,
+        -1.231_739_572_450_155,
+        0.001_208_650_973_866_179,
+        -0.000_005_395_239_384_953,
+    ];
+
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let mut ser = 1.000_000_000_190_015;
+    for (i, c) in COEFFS.iter().enumerate() {
+        ser += c / (x + 1.0 + i as f64);
+    }
+
+    -tmp + (2.506_628_274_631 * ser / x).ln()
+}
+
+/// regularized lower incomplete gamma function
+pub fn gamma_p(a: f64, x: f64) -> f64 {
+    const MAX_ITERATIONS: usize = 10_000;
+    const EPS: f64 = 1.0e-15;
+
+    if x <= 0.0 {
+        return 0.0;
+    }
+
+    let gln = ln_gamma(a);
+
+    if x < a + 1.0 {
+        // series representation
+        let mut ap = a;
+        let mut del = 1.0 / a;
+        let mut sum = del;
+        for _ in 0..MAX_ITERATIONS {
+            ap += 1.0;
+            del *= x / ap;
+            sum += del;
+            if del.abs() < sum.abs() * EPS {
+                break;
+            }
+        }
+
+        sum * (-x + a * x.ln() - gln).exp()
+    } else {
+        // continued fraction, modified Lentz's method
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / f64::MIN_POSITIVE;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for i in 1..MAX_ITERATIONS {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+
+            d = an * d + b;
+            if d.abs() < f64::MIN_POSITIVE {
+                d = f64::MIN_POSITIVE;
+            }
+            c = b + an / c;
+            if c.abs() < f64::MIN_POSITIVE {
+                c = f64::MIN_POSITIVE;
+            }
+
+            d = 1.0 / d;
+            let del = d * c;
+            h *= del;
+            if (del - 1.0).abs() < EPS {
+                break;
+            }
+        }
+
+        1.0 - (-x + a * x.ln() - gln).exp() * h
+    }
+}
+
+/// cumulative distribution function of the chi-square distribution with `k`
+/// degrees of freedom
+pub fn chi2_cdf(x: f64, k: f64) -> f64 {
+    gamma_p(k / 2.0, x / 2.0)
+}
+
+/// inverse of [chi2_cdf]
+pub fn chi2_ppf(p: f64, k: f64) -> f64 {
+    if p <= 0.0 {
+        return 0.0;
+    }
+    if p >= 1.0 {
+        return f64::INFINITY;
+    }
+
+    let mut lo = 0.0;
+    let mut hi = k.max(1.0);
+    while chi2_cdf(hi, k) < p {
+        lo = hi;
+        hi *= 2.0;
+    }
+
+    for _ in 0..100 {
+        let mid = (lo + hi) / 2.0;
+        if chi2_cdf(mid, k) < p {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+
+    (lo + hi) / 2.0
+}
+
+/// inverse of the standard normal cumulative distribution function, using
+/// Acklam's approximation. The relative error is below 1.2e-9.
+pub fn norm_ppf(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969_683_028_665_376e1,
+        2.209_460_984_245_205e2,
+        -2.759_285_104_469_687e2,
+        1.383_577_518_672_69e2,
+        -3.066_479_806_614_716e1,
+        2.506_628_277_459_239,
+    ];
+    const B: [f64; 5] = [
+        -5.447_609_879_822_406e1,
+        1.615_858_368_580_409e2,
+        -1.556_989_798_598_866e2,
+        6.680_131_188_771_972e1,
+        -1.328_068_155_288_572e1,
+    ];
+    const C: [f64; 6] = [
+        -7.784_894_002_430_293e-3,
+        -3.223_964_580_411_365e-1,
+        -2.400_758_277_161_838,
+        -2.549_732_539_343_734,
+        4.374_664_141_464_968,
+        2.938_163_982_698_783,
+    ];
+    const D: [f64; 4] = [
+        7.784_695_709_041_462e-3,
+        3.224_671_290_700_398e-1,
+        2.445_134_137_142_996,
+        3.754_408_661_907_416,
+    ];
+    const P_LOW: f64 = 0.024_25;
+
+    if p <= 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    if p >= 1.0 {
+        return f64::INFINITY;
+    }
+
+    let tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+
+    if p < P_LOW {
+        tail((-2.0 * p.ln()).sqrt())
+    } else if p <= 1.0 - P_LOW {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    } else {
+        -tail((-2.0 * (1.0 - p).ln()).sqrt())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn chi2() {
+        assert_abs_diff_eq!(
+            super::chi2_cdf(3.841_458_820_694_124, 1.0),
+            0.95,
+            epsilon = 1.0e-9
+        );
+        assert_abs_diff_eq!(
+            super::chi2_ppf(0.975, 1.0),
+            5.023_886_187_314_888,
+            epsilon = 1.0e-6
+        );
+        assert_abs_diff_eq!(
+            super::chi2_ppf(0.025, 1.0),
+            0.000_982_069_117_175_254,
+            epsilon = 1.0e-9
+        );
+        assert_abs_diff_eq!(
+            super::chi2_ppf(0.95, 6.0),
+            12.591_587_243_743_977,
+            epsilon = 1.0e-6
+        );
+    }
+
+    #[test]
+    fn norm() {
+        assert_abs_diff_eq!(super::norm_ppf(0.5), 0.0, epsilon = 1.0e-9);
+        assert_abs_diff_eq!(
+            super::norm_ppf(0.975),
+            1.959_963_984_540_054,
+            epsilon = 1.0e-8
+        );
+        assert_abs_diff_eq!(
+            super::norm_ppf(0.001),
+            -3.090_232_306_167_813,
+            epsilon = 1.0e-8
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use clap::Parser as _;
+use sensoreval::*;
+
+// this forces them to get linked into the binaries
+extern crate blas_src;
+extern crate lapack_src;
+
+/// Fit simulator parameters to a recording by minimizing the accelerometer
+/// and gyroscope residuals
+#[derive(clap::Parser)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// Fit config file to use
+    config: std::path::PathBuf,
+
+    /// Write the simulator config with the fitted values, has to be in the
+    /// directory of the template
+    #[arg(long)]
+    output: Option<std::path::PathBuf>,
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    let fit = fit::Fit::load(&cli.config).expect("can't load fit config");
+    if let Some(output) = &cli.output {
+        // the written config keeps the relative paths of the template
+        config::check_output_dir(fit.basedir(), output).expect("invalid output");
+    }
+    let result = fit.run().expect("can't fit");
+
+    println!("cost={} iterations={}", result.cost, result.iterations);
+    for (i, param) in fit.cfg.parameters.iter().enumerate() {
+        println!(
+            "{} = {} +- {}",
+            param.path, result.values[i], result.stddev[i]
+        );
+    }
+
+    if let Some(output) = &cli.output {
+        let value = fit
+            .to_value(&result.values)
+            .expect("can't set fitted values");
+        let s = toml::to_string(&value).expect("can't serialize config");
+        std::fs::write(output, s).expect("can't write output");
+    }
+}
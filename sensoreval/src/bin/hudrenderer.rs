@@ -197,6 +197,18 @@ struct Cli {
     force_generic: bool,
 }
 
+/// adds energy rows if the data comes from a simulation
+fn plot_energy(plot: &mut sensoreval_utils::Plot, cfg: &config::Config, samples: &[Data]) {
+    let sd = match &cfg.data.source {
+        config::DataSource::SimulatorData(sd) => sd,
+        _ => return,
+    };
+    let model = sd.model.to_model_enum(sd.dt).expect("can't create model");
+    let x: Vec<f64> = samples.iter().map(|s| s.time_seconds()).collect();
+
+    plot.add_energy(samples, &x, &model).expect("can't plot energy");
+}
+
 fn main() {
     let cli = Cli::parse();
 
@@ -257,6 +269,7 @@ fn main() {
 
             // plot
             renderctx.plot(&mut plot).expect("can't plot");
+            plot_energy(&mut plot, &cfg, &samples);
 
             if let Some(simcfgname) = &cli.simcfg {
                 let mut simcfg = config::load(simcfgname).expect("can't load sim config");
@@ -269,6 +282,7 @@ fn main() {
 
                 plot.set_trace_prefix(Some("sim-"));
                 simrenderctx.plot(&mut plot).expect("can't plot sim");
+                plot_energy(&mut plot, &simcfg, &simsamples);
                 plot.set_trace_prefix::<&str>(None);
             }
 
@@ -497,13 +511,18 @@ fn main() {
                 _ => panic!("psim works with a simulator data source only"),
             };
 
-            // TODO: add control_input support
-
-            sensoreval_psim::run::run_sim(
+            if let Err(e) = sensoreval_psim::run::run_sim(
                 sd.dt,
+                sd.integrator,
                 &sd.model,
                 ndarray::Array::from(sd.initial.clone()),
-            );
+                sd.start_off,
+                sd.control_input.clone(),
+                sd.state_updates.clone(),
+            ) {
+                println!("can't run the simulation: {e}");
+                std::process::exit(1);
+            }
         }
     }
 }
@@ -0,0 +1,53 @@
+use clap::Parser as _;
+use sensoreval::*;
+
+// this forces them to get linked into the binaries
+extern crate blas_src;
+extern crate lapack_src;
+
+/// Run a simulator config without GUI and write the samples to a file
+#[derive(clap::Parser)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// Config file to use
+    config: std::path::PathBuf,
+
+    /// Output file, supported extensions: csv, npz, raw
+    output: std::path::PathBuf,
+
+    /// Config with a sensordata source. Its axis map, calibration and video
+    /// offset get reverted when writing raw data
+    #[arg(long)]
+    sensorcfg: Option<std::path::PathBuf>,
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    let cfg = config::load(&cli.config).expect("can't load config");
+    if !matches!(cfg.data.source, config::DataSource::SimulatorData(_)) {
+        panic!("simulate works with a simulator data source only");
+    }
+
+    let samples = cfg.load_data().expect("can't simulate");
+    if cli.output.extension() == Some(std::ffi::OsStr::new("raw")) {
+        let sensorcfg = match &cli.sensorcfg {
+            Some(path) => config::load(path).expect("can't load sensor config"),
+            None => config::Config::for_calibration(""),
+        };
+        let mut file = std::io::BufWriter::new(
+            std::fs::File::create(&cli.output).expect("can't create output"),
+        );
+        datareader::write_all_samples_output(&mut file, &samples, &sensorcfg)
+            .expect("can't write output");
+        std::io::Write::flush(&mut file).expect("can't write output");
+    } else {
+        export::write_file(&cli.output, &samples).expect("can't write output");
+    }
+
+    println!(
+        "wrote {} samples to {}",
+        samples.len(),
+        cli.output.display()
+    );
+}
@@ -0,0 +1,105 @@
+use clap::Parser as _;
+use sensoreval::*;
+use std::io::Write;
+
+// this forces them to get linked into the binaries
+extern crate blas_src;
+extern crate lapack_src;
+
+/// Run a simulator config many times with varying parameters and summarize
+/// the results
+#[derive(clap::Parser)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// Sweep config file to use
+    config: std::path::PathBuf,
+
+    /// Write the results as CSV
+    #[arg(long)]
+    output: Option<std::path::PathBuf>,
+
+    /// Plot the metrics over the first parameter
+    #[arg(long)]
+    plot: bool,
+
+    /// Number of simulations to run in parallel, defaults to the number of
+    /// CPUs
+    #[arg(long)]
+    threads: Option<usize>,
+}
+
+fn write_table<W: Write>(
+    mut w: W,
+    sweep: &sweep::Sweep,
+    runs: &[sweep::Run],
+    sep: &str,
+) -> std::io::Result<()> {
+    let header: Vec<&str> = sweep
+        .cfg
+        .parameters
+        .iter()
+        .map(|p| p.path.as_str())
+        .chain(sweep::Metrics::NAMES)
+        .collect();
+    writeln!(w, "{}", header.join(sep))?;
+
+    for run in runs {
+        let row: Vec<String> = run
+            .values
+            .iter()
+            .chain(run.metrics.values().iter())
+            .map(|v| v.to_string())
+            .collect();
+        writeln!(w, "{}", row.join(sep))?;
+    }
+
+    Ok(())
+}
+
+fn plot(sweep: &sweep::Sweep, runs: &[sweep::Run]) -> Result<(), Error> {
+    let mut plot = sensoreval_utils::Plot::new("/tmp/sensoreval-plot.html")?;
+
+    let (name, x): (&str, Vec<f64>) = match sweep.cfg.parameters.first() {
+        Some(param) => (&param.path, runs.iter().map(|r| r.values[0]).collect()),
+        None => ("run", (0..runs.len()).map(|i| i as f64).collect()),
+    };
+
+    for (i, metric) in sweep::Metrics::NAMES.iter().enumerate() {
+        let y: Vec<f64> = runs.iter().map(|r| r.metrics.values()[i]).collect();
+
+        let mut t = sensoreval_utils::Plot::default_line();
+        t.x(&x).y(&y).name(name);
+        t.mode().flags().markers(true);
+        t.line().color(sensoreval_utils::COLOR_A);
+        plot.add_trace_to_rowname_ensure(&mut t, metric)?;
+    }
+
+    plot.finish()?;
+    Ok(())
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    let sweep = sweep::Sweep::load(&cli.config).expect("can't load sweep config");
+    let nthreads = cli.threads.unwrap_or_else(|| {
+        std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+    });
+
+    let runs = sweep.run_all(nthreads).expect("can't simulate");
+
+    write_table(std::io::stdout().lock(), &sweep, &runs, "\t").expect("can't print results");
+
+    if let Some(output) = &cli.output {
+        let mut file =
+            std::io::BufWriter::new(std::fs::File::create(output).expect("can't create output"));
+        write_table(&mut file, &sweep, &runs, ",").expect("can't write output");
+        file.flush().expect("can't write output");
+    }
+
+    if cli.plot {
+        plot(&sweep, &runs).expect("can't plot");
+    }
+}
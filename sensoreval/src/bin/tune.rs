@@ -0,0 +1,53 @@
+use clap::Parser as _;
+use sensoreval::*;
+
+// this forces them to get linked into the binaries
+extern crate blas_src;
+extern crate lapack_src;
+
+/// Tune the noise parameters of the HUD estimator by maximizing the
+/// likelihood of the measurements or minimizing the estimation error
+#[derive(clap::Parser)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// Tune config file to use
+    config: std::path::PathBuf,
+
+    /// Write the config with the tuned values, has to be in the directory
+    /// of the template
+    #[arg(long)]
+    output: Option<std::path::PathBuf>,
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    let tune = tune::Tune::load(&cli.config).expect("can't load tune config");
+    if let Some(output) = &cli.output {
+        // the written config keeps the relative paths of the template
+        config::check_output_dir(tune.basedir(), output).expect("invalid output");
+    }
+    let result = tune.run().expect("can't tune");
+
+    println!(
+        "cost={} initial_cost={} iterations={}",
+        result.cost, result.initial_cost, result.iterations
+    );
+    for (i, param) in tune.cfg.parameters.iter().enumerate() {
+        println!("{} = {}", param.path, result.values[i]);
+    }
+
+    let block = tune
+        .to_renderer_value(&result.values)
+        .expect("can't set tuned values");
+    let s = toml::to_string(&block).expect("can't serialize config");
+    println!("\n{s}");
+
+    if let Some(output) = &cli.output {
+        let value = tune
+            .to_value(&result.values)
+            .expect("can't set tuned values");
+        let s = toml::to_string(&value).expect("can't serialize config");
+        std::fs::write(output, s).expect("can't write output");
+    }
+}
@@ -66,6 +66,24 @@ impl AxisMap {
             self.copy_single(dst, src, i);
         }
     }
+
+    /// undo [copy](Self::copy): `src` is mapped data, `dst` receives the
+    /// sensor axes
+    pub fn copy_inverse<A, T>(&self, dst: &mut A, src: &[T])
+    where
+        A: std::ops::IndexMut<usize, Output = T>,
+        T: Copy + std::ops::Neg<Output = T>,
+    {
+        for (srcidx, dstidx) in self.0.iter().enumerate() {
+            assert!(*dstidx != 0);
+
+            let mut tmp = src[srcidx];
+            if *dstidx < 0 {
+                tmp = -tmp;
+            }
+            dst[dstidx.unsigned_abs() - 1] = tmp;
+        }
+    }
 }
 
 /// sensordata data source
@@ -119,6 +137,12 @@ pub struct SimulatorData {
     /// unit: seconds
     #[serde(default)]
     pub start_off: f64,
+    /// numerical integration method
+    #[serde(default)]
+    pub integrator: sensoreval_psim::integrator::Integrator,
+    /// used for synthesizing magnetometer and barometer data
+    #[serde(default)]
+    pub environment: sensoreval_psim::environment::Environment,
 
     pub model: sensoreval_psim::models::Params,
 }
@@ -287,10 +311,18 @@ impl Config {
         let rot = unwrap_opt_or!(self.data.rot.as_ref(), return);
         sensoreval_psim::utils::rotate_imudata(rot, &mut sample.accel);
         sensoreval_psim::utils::rotate_imudata(rot, &mut sample.gyro);
+
+        // recordings keep their magnetometer data as it was, only the
+        // synthesized field has to follow the rotated IMU
+        if let DataSource::SimulatorData(_) = &self.data.source {
+            sensoreval_psim::utils::rotate_imudata(rot, &mut sample.mag);
+        }
     }
 
-    fn load_data_sim(d: &SimulatorData) -> Result<Vec<crate::Data>, Error> {
-        let mut model = d.model.to_model_enum(d.dt);
+    /// simulate without the configured rotation and noise
+    pub fn load_data_sim(d: &SimulatorData) -> Result<Vec<crate::Data>, Error> {
+        let mut model = d.model.to_model_enum(d.dt)?;
+        model.set_integrator(d.integrator);
         let nsamples = (d.duration / d.dt) as usize;
         let mut ret = Vec::new();
 
@@ -308,11 +340,14 @@ impl Config {
             let mut sample = crate::Data {
                 time: t_us,
                 time_baro: t_us,
+                temperature: d.environment.temperature,
+                pressure: model.to_pressure(&x, &d.environment),
                 actual: Some(actual),
                 ..crate::Data::default()
             };
             model.to_accel(&x, &mut sample.accel);
             model.to_gyro(&x, &mut sample.gyro);
+            model.to_mag(&x, &d.environment, &mut sample.mag);
             ret.push(sample);
 
             if let Some(control_input) = timed_array_ci.next(t + d.dt) {
@@ -327,7 +362,7 @@ impl Config {
                 }
             }
 
-            model.step(&mut x);
+            model.step(&mut x)?;
         }
 
         Ok(ret)
@@ -380,15 +415,45 @@ fn path2abs(dir: &std::path::Path, relpath: &str) -> String {
     String::from(dir.join(std::path::Path::new(&relpath)).to_str().unwrap())
 }
 
+/// fails if `output` isn't in `cfgdir`, the relative paths of a config
+/// based on one from `cfgdir` would point to the wrong files there
+pub fn check_output_dir(cfgdir: &std::path::Path, output: &std::path::Path) -> Result<(), Error> {
+    let outdir = match output.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => std::path::Path::new("."),
+    };
+    let cfgdir = if cfgdir.as_os_str().is_empty() {
+        std::path::Path::new(".")
+    } else {
+        cfgdir
+    };
+
+    if outdir.canonicalize()? != cfgdir.canonicalize()? {
+        return Err(Error::OutputNotInConfigDir(cfgdir.display().to_string()));
+    }
+
+    Ok(())
+}
+
 /// load config file
 pub fn load<P: AsRef<std::path::Path>>(filename: P) -> Result<Config, Error> {
     let cfgdir = std::path::Path::new(filename.as_ref())
         .parent()
         .expect("can't get parent dir of config");
 
+    from_value(load_value(filename.as_ref())?, cfgdir)
+}
+
+/// load a config file without interpreting it, so it can be modified
+/// before passing it to [from_value]
+pub fn load_value<P: AsRef<std::path::Path>>(filename: P) -> Result<toml::Value, Error> {
     let buffer = std::fs::read_to_string(filename.as_ref())?;
     let parser = toml::de::Deserializer::new(&buffer);
-    let value = toml::Value::deserialize(parser)?;
+    Ok(toml::Value::deserialize(parser)?)
+}
+
+/// relative paths are relative to `cfgdir`
+pub fn from_value(value: toml::Value, cfgdir: &std::path::Path) -> Result<Config, Error> {
     let mut has_unsupported: bool = false;
     let mut cfg: Config = serde_ignored::deserialize(value, |path| {
         println!("unsupported config: {:?}", path.to_string());
@@ -413,6 +478,22 @@ pub fn load<P: AsRef<std::path::Path>>(filename: P) -> Result<Config, Error> {
         }
     }
 
+    if let DataSource::SimulatorData(sd) = &mut cfg.data.source {
+        sd.model.set_basedir(cfgdir);
+        sd.integrator.validate()?;
+        sd.model.validate()?;
+    }
+
+    if let HudRenderer::Pendulum(pendulum) = &mut cfg.hud.renderer {
+        pendulum.validate()?;
+        if let Some(v) = &pendulum.checkpoint_load {
+            pendulum.checkpoint_load = Some(path2abs(cfgdir, v));
+        }
+        if let Some(v) = &pendulum.checkpoint_save {
+            pendulum.checkpoint_save = Some(path2abs(cfgdir, v));
+        }
+    }
+
     if let Some(v) = cfg.video.filename {
         cfg.video.filename = Some(path2abs(cfgdir, &v));
     }
@@ -423,6 +504,57 @@ pub fn load<P: AsRef<std::path::Path>>(filename: P) -> Result<Config, Error> {
     Ok(cfg)
 }
 
+/// sets a float inside of a toml value. `path` is dot-separated and array
+/// elements are selected by their index, e.g. `data.source.initial.0`. The
+/// last key gets created if it's missing.
+pub fn set_value(mut value: &mut toml::Value, path: &str, v: f64) -> Result<(), Error> {
+    let invalid = || Error::InvalidConfigPath(path.to_string());
+    let mut keys = path.split('.').peekable();
+
+    while let Some(key) = keys.next() {
+        let is_last = keys.peek().is_none();
+
+        value = match value {
+            toml::Value::Table(t) => {
+                if is_last {
+                    t.insert(key.to_string(), toml::Value::Float(v));
+                    return Ok(());
+                }
+                t.get_mut(key).ok_or_else(invalid)?
+            }
+            toml::Value::Array(a) => {
+                let index: usize = key.parse().map_err(|_| invalid())?;
+                a.get_mut(index).ok_or_else(invalid)?
+            }
+            _ => return Err(invalid()),
+        };
+
+        if is_last {
+            *value = toml::Value::Float(v);
+        }
+    }
+
+    Ok(())
+}
+
+/// returns a number inside of a toml value, see [set_value] for the format
+/// of `path`
+pub fn get_value(mut value: &toml::Value, path: &str) -> Option<f64> {
+    for key in path.split('.') {
+        value = match value {
+            toml::Value::Table(t) => t.get(key)?,
+            toml::Value::Array(a) => a.get(key.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+
+    match value {
+        toml::Value::Float(v) => Some(*v),
+        toml::Value::Integer(v) => Some(*v as f64),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -449,4 +581,137 @@ mod test {
         map.copy(&mut dst, &src);
         assert_eq!(dst, array![10, 30, -20]);
     }
+
+    #[test]
+    fn axismap_inverse() {
+        let mut mapped = ndarray::Array::zeros(3);
+        let mut dst = ndarray::Array::zeros(3);
+        let src: [isize; 3] = [10, 20, 30];
+
+        for map in [vec![1, 2, 3], vec![3, -1, 2], vec![-2, 3, -1]] {
+            let map = AxisMap(map);
+            map.copy(&mut mapped, &src);
+            map.copy_inverse(&mut dst, mapped.as_slice().unwrap());
+            assert_eq!(dst, array![10, 20, 30]);
+        }
+    }
+
+    #[test]
+    fn rotate_sample() {
+        let sample = || crate::Data {
+            accel: array![1.0, 0.0, 0.0],
+            mag: array![0.0, 20.0, -40.0],
+            ..crate::Data::default()
+        };
+        // 90 degrees around up
+        let rot = Some(vec![0.0, 0.0, std::f64::consts::FRAC_PI_2]);
+
+        let mut recording = Config::for_calibration("");
+        recording.data.rot = rot.clone();
+        let mut rotated = sample();
+        recording.rotate_sample(&mut rotated);
+        assert!((&rotated.accel - &array![0.0, 1.0, 0.0])
+            .iter()
+            .all(|v| v.abs() < 1e-9));
+        assert_eq!(rotated.mag, sample().mag);
+
+        let mut simulation = Config::for_calibration("");
+        simulation.data.rot = rot;
+        simulation.data.source = DataSource::SimulatorData(
+            toml::from_str(
+                r#"
+                dt = 0.01
+                duration = 1
+                initial = [0.0, 0.0]
+                [model]
+                type = "pendulum"
+                radius = 1
+                "#,
+            )
+            .unwrap(),
+        );
+        let mut rotated = sample();
+        simulation.rotate_sample(&mut rotated);
+        assert!((&rotated.accel - &array![0.0, 1.0, 0.0])
+            .iter()
+            .all(|v| v.abs() < 1e-9));
+        assert!((&rotated.mag - &array![-20.0, 0.0, -40.0])
+            .iter()
+            .all(|v| v.abs() < 1e-9));
+    }
+
+    #[test]
+    fn check_output_dir() {
+        let cfgdir = std::env::temp_dir().join(format!("sensoreval-output-{}", std::process::id()));
+        let subdir = cfgdir.join("sub");
+        std::fs::create_dir_all(&subdir).unwrap();
+
+        assert!(super::check_output_dir(&cfgdir, &cfgdir.join("out.toml")).is_ok());
+        assert!(super::check_output_dir(&subdir, &subdir.join("../out.toml")).is_err());
+        assert!(matches!(
+            super::check_output_dir(&cfgdir, &subdir.join("out.toml")),
+            Err(Error::OutputNotInConfigDir(_))
+        ));
+
+        std::fs::remove_dir_all(&cfgdir).unwrap();
+    }
+
+    #[test]
+    fn pendulum_unsupported() {
+        let pendulum = |extra: &str| -> crate::hudrenderers::pendulum::Config {
+            toml::from_str(&format!(
+                r#"
+                initial = [0, 0, 0, 0, 0, 0, 0]
+                initial_cov = [1, 1, 1, 1, 1, 1, 1]
+                active_row = 0
+                {extra}
+                [stdev]
+                accel = {{ x = 1, y = 1, z = 1 }}
+                gyro = {{ x = 1, y = 1, z = 1 }}
+                mag = {{ x = 1, y = 1, z = 1 }}
+                "#
+            ))
+            .unwrap()
+        };
+
+        assert!(pendulum("enable_rts_smoother = true").validate().is_ok());
+        assert!(pendulum("filter = \"ekf\"").validate().is_ok());
+        for filter in ["srukf", "ekf", "particle"] {
+            let cfg = pendulum(&format!(
+                "filter = \"{filter}\"\nenable_rts_smoother = true"
+            ));
+            assert!(matches!(cfg.validate(), Err(Error::InvalidConfig(_))));
+        }
+        let cfg = pendulum("filter = \"imm\"\nadaptive = {}");
+        assert!(matches!(cfg.validate(), Err(Error::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn set_value() {
+        let mut value: toml::Value = toml::from_str(
+            r#"
+            [data.source]
+            initial = [0.0, 1.0]
+            [data.source.model]
+            radius = 1
+            "#,
+        )
+        .unwrap();
+
+        super::set_value(&mut value, "data.source.model.radius", 2.5).unwrap();
+        super::set_value(&mut value, "data.source.model.sensor_pos", 0.5).unwrap();
+        super::set_value(&mut value, "data.source.initial.1", 3.0).unwrap();
+
+        let source = &value["data"]["source"];
+        assert_eq!(source["model"]["radius"].as_float(), Some(2.5));
+        assert_eq!(source["model"]["sensor_pos"].as_float(), Some(0.5));
+        assert_eq!(source["initial"][1].as_float(), Some(3.0));
+
+        assert!(super::set_value(&mut value, "data.source.initial.2", 0.0).is_err());
+        assert!(super::set_value(&mut value, "data.missing.radius", 0.0).is_err());
+
+        assert_eq!(get_value(&value, "data.source.model.radius"), Some(2.5));
+        assert_eq!(get_value(&value, "data.source.initial.0"), Some(0.0));
+        assert_eq!(get_value(&value, "data.source.initial.5"), None);
+    }
 }
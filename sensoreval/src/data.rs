@@ -62,12 +62,8 @@ impl std::ops::Div<usize> for Data {
 }
 
 impl Data {
-    fn pressure_altitude_feet(&self) -> f64 {
-        145_366.45 * (1.0 - (self.pressure / 1013.25).powf(0.190_284))
-    }
-
     pub fn pressure_altitude(&self) -> f64 {
-        self.pressure_altitude_feet() * 0.3048
+        math::pressure2altitude(self.pressure)
     }
 
     pub fn time_seconds(&self) -> f64 {
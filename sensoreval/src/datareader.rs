@@ -2,6 +2,7 @@ use crate::config;
 use crate::Data;
 use crate::Error;
 use ndarray::array;
+use ndarray_linalg::solve::Inverse;
 use sensoreval_utils::DrainFilterTrait;
 use serde::Deserialize;
 use serde::Serialize;
@@ -13,7 +14,7 @@ use std::mem;
 // in theory, this struct should never need alignment anyway
 // because it's a sequence of 8-byte primitives
 //#[repr(C, packed)]
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize)]
 struct RawData {
     /// timestamp for accel, gyro and mag. unit: micro seconds
     time_imu: u64,
@@ -198,6 +199,135 @@ fn time_imu2video(cfg: &config::SensorData, us: u64) -> Option<u64> {
     }
 }
 
+/// inverse of [time_imu2video]
+fn time_video2imu(cfg: &config::SensorData, us: u64) -> Option<u64> {
+    match cfg.video_off {
+        x if x > 0 => {
+            let off: u64 = x.try_into().unwrap();
+            us.checked_sub(off)
+        }
+        x if x < 0 => {
+            let off: u64 = (-x).try_into().unwrap();
+            Some(us.checked_add(off).unwrap())
+        }
+        _ => Some(us),
+    }
+}
+
+/// datawriter context, does the inverse of [Context] so processed samples
+/// can be turned back into raw data
+pub struct Writer {
+    /// calibration info and the inverse of its `accel_t`
+    calibration: Option<(Calibration, ndarray::Array2<f64>)>,
+    /// pressure of previous sample
+    pressure_prev: Option<f64>,
+}
+
+impl Writer {
+    pub fn new(calibration: Option<Calibration>) -> Result<Self, Error> {
+        let calibration = match calibration {
+            Some(c) => {
+                let accel_t_inv = c.accel_t.inv()?;
+                Some((c, accel_t_inv))
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            calibration,
+            pressure_prev: None,
+        })
+    }
+
+    /// write a single sample to sink, undoing the processing cfg describes.
+    /// Samples which would end up before the start of the IMU data get
+    /// skipped.
+    #[allow(deprecated)]
+    pub fn write_sample<W: std::io::Write>(
+        &mut self,
+        sink: &mut W,
+        sample: &Data,
+        cfg: &config::SensorData,
+    ) -> Result<(), Error> {
+        let time_imu = unwrap_opt_or!(time_video2imu(cfg, sample.time), return Ok(()));
+        let time_baro = unwrap_opt_or!(time_video2imu(cfg, sample.time_baro), return Ok(()));
+
+        let mut accel = ndarray::Array1::zeros(3);
+        let mut gyro = ndarray::Array1::zeros(3);
+        let mut mag = [0.0; 3];
+        cfg.axismap
+            .copy_inverse(&mut accel, sample.accel.as_slice().unwrap());
+        cfg.axismap
+            .copy_inverse(&mut gyro, sample.gyro.as_slice().unwrap());
+        cfg.axismap
+            .copy_inverse(&mut mag, sample.mag.as_slice().unwrap());
+
+        if let Some((calibration, accel_t_inv)) = &self.calibration {
+            gyro += &calibration.gyro_offs;
+            accel = accel_t_inv.dot(&accel) + &calibration.accel_offs;
+        }
+
+        // m/s^2 -> g
+        for a in &mut accel {
+            *a /= math::GRAVITY;
+        }
+
+        // rad/s -> dps
+        for g in &mut gyro {
+            *g = (*g).to_degrees();
+        }
+
+        let mut pressure = sample.pressure;
+        if cfg.pressure_coeff > 0. {
+            if let Some(pressure_prev) = self.pressure_prev {
+                pressure =
+                    pressure * cfg.pressure_coeff - pressure_prev * (cfg.pressure_coeff - 1.0);
+            }
+        }
+        self.pressure_prev = Some(sample.pressure);
+
+        let rawdata = RawData {
+            time_imu,
+            accel: [accel[0], accel[1], accel[2]],
+            gyro: [gyro[0], gyro[1], gyro[2]],
+            mag,
+            time_baro,
+            temperature: sample.temperature,
+            pressure,
+            _quat: [0.0; 4],
+        };
+        bincode::serialize_into(sink, &rawdata)?;
+
+        Ok(())
+    }
+}
+
+/// write samples in the format [read_all_samples_input] expects, using the
+/// [SensorData](../config/struct.SensorData.html) source of cfg
+pub fn write_all_samples_output<W: std::io::Write>(
+    sink: &mut W,
+    samples: &[Data],
+    cfg: &config::Config,
+) -> Result<(), Error> {
+    let datacfg = if let config::DataSource::SensorData(sd) = &cfg.data.source {
+        sd
+    } else {
+        return Err(Error::UnsupportedDatatype);
+    };
+
+    let calibration = match &datacfg.calibration {
+        Some(calfile) => Some(Calibration::load(calfile)?),
+        None => None,
+    };
+    let mut writer = Writer::new(calibration)?;
+
+    for sample in samples {
+        writer.write_sample(sink, sample, datacfg)?;
+    }
+
+    Ok(())
+}
+
 /// read samples from source until EOF from a [SensorData](../config/struct.SensorData.html) source
 pub fn read_all_samples_input<S: std::io::Read>(
     source: &mut S,
@@ -311,3 +441,100 @@ pub fn read_all_samples_cfg(cfg: &config::Config) -> Result<Vec<Data>, Error> {
         .success());
     res
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn raw_roundtrip() {
+        let cfg = config::Config::for_calibration("");
+        let samples: Vec<Data> = (0..4)
+            .map(|i| Data {
+                time: i * 1000,
+                time_baro: i * 1000,
+                accel: array![1.0, -2.0, 9.81] * i as f64,
+                gyro: array![0.1, 0.2, -0.3] * i as f64,
+                mag: array![20.0, 0.0, -40.0],
+                temperature: 20.0,
+                pressure: 1013.25 - i as f64,
+                actual: None,
+            })
+            .collect();
+
+        let mut buf = Vec::new();
+        write_all_samples_output(&mut buf, &samples, &cfg).unwrap();
+        let read = read_all_samples_input(&mut buf.as_slice(), &cfg).unwrap();
+
+        assert_eq!(read.len(), samples.len());
+        for (a, b) in read.iter().zip(samples.iter()) {
+            assert_eq!(a.time, b.time);
+            assert_eq!(a.time_baro, b.time_baro);
+            assert!((&a.accel - &b.accel).iter().all(|v| v.abs() < 1e-9));
+            assert!((&a.gyro - &b.gyro).iter().all(|v| v.abs() < 1e-9));
+            assert_eq!(a.mag, b.mag);
+            assert_eq!(a.pressure, b.pressure);
+        }
+    }
+
+    /// undoes calibration, axis map, video offset and pressure smoothing
+    #[test]
+    fn raw_roundtrip_processed() {
+        let calpath = std::env::temp_dir().join(format!(
+            "sensoreval-raw-roundtrip-{}.cal",
+            std::process::id()
+        ));
+        let calibration = Calibration::new(
+            array![0.01, -0.02, 0.03],
+            array![0.1, -0.2, 0.05],
+            array![[1.02, 0.01, 0.0], [0.0, 0.98, -0.02], [0.01, 0.0, 1.01]],
+        );
+        bincode::serialize_into(std::fs::File::create(&calpath).unwrap(), &calibration).unwrap();
+
+        let samples: Vec<Data> = (0..6)
+            .map(|i| Data {
+                time: 1000 + i * 1000,
+                time_baro: 1000 + i * 1000,
+                accel: array![1.0, -2.0, 9.81] * (i + 1) as f64,
+                gyro: array![0.1, 0.2, -0.3] * (i + 1) as f64,
+                mag: array![20.0, 5.0, -40.0] + i as f64,
+                temperature: 20.0,
+                pressure: 1013.25 - (i * i) as f64,
+                actual: None,
+            })
+            .collect();
+
+        for video_off in [2500, -2500] {
+            let mut cfg = config::Config::for_calibration("");
+            if let config::DataSource::SensorData(sd) = &mut cfg.data.source {
+                sd.video_off = video_off;
+                sd.axismap = serde_json::from_str("[-2, 3, 1]").unwrap();
+                sd.pressure_coeff = 4.0;
+                sd.calibration = Some(calpath.to_str().unwrap().to_string());
+            }
+
+            let mut buf = Vec::new();
+            write_all_samples_output(&mut buf, &samples, &cfg).unwrap();
+            let read = read_all_samples_input(&mut buf.as_slice(), &cfg).unwrap();
+
+            // samples before the start of the IMU data can't be written
+            let expected: Vec<_> = samples
+                .iter()
+                .filter(|s| s.time as i64 >= video_off)
+                .collect();
+            assert_eq!(read.len(), expected.len());
+            assert!(read.len() < samples.len() || video_off < 0);
+
+            for (a, b) in read.iter().zip(expected) {
+                assert_eq!(a.time, b.time);
+                assert_eq!(a.time_baro, b.time_baro);
+                assert!((&a.accel - &b.accel).iter().all(|v| v.abs() < 1e-9));
+                assert!((&a.gyro - &b.gyro).iter().all(|v| v.abs() < 1e-9));
+                assert_eq!(a.mag, b.mag);
+                assert!((a.pressure - b.pressure).abs() < 1e-9);
+            }
+        }
+
+        std::fs::remove_file(&calpath).unwrap();
+    }
+}
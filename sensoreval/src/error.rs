@@ -7,24 +7,50 @@ pub enum Error {
     #[error(transparent)]
     Io(#[from] std::io::Error),
     #[error(transparent)]
+    Kalman(#[from] kalman::Error),
+    #[error(transparent)]
+    Linalg(#[from] ndarray_linalg::error::LinalgError),
+    #[error(transparent)]
+    Normal(#[from] rand_distr::NormalError),
+    #[error(transparent)]
+    SerdeJson(#[from] serde_json::Error),
+    #[error(transparent)]
     SerdePickle(#[from] serde_pickle::error::Error),
     #[error(transparent)]
+    SensorevalPsim(#[from] sensoreval_psim::Error),
+    #[error(transparent)]
     SensorevalUtils(#[from] sensoreval_utils::Error),
     #[error(transparent)]
     TomlDe(#[from] toml::de::Error),
+    #[error(transparent)]
+    TomlSer(#[from] toml::ser::Error),
+    #[error(transparent)]
+    WriteNpz(#[from] ndarray_npy::WriteNpzError),
 
     #[error("blender render not found")]
     BlenderRenderNotFound,
     #[error("EOF")]
     Eof,
+    #[error("invalid config: {0}")]
+    InvalidConfig(String),
+    #[error("invalid config path: {0}")]
+    InvalidConfigPath(String),
     #[error("no dataset")]
     NoDataSet,
+    #[error("no estimate for the dataset")]
+    NoEstimate,
     #[error("no HUD renderer")]
     NoHudRenderer,
+    #[error("the output has to be in the config directory {0}")]
+    OutputNotInConfigDir(String),
+    #[error("not a simulator config")]
+    NoSimulatorData,
     #[error("sample not found")]
     SampleNotFound,
     #[error("sunsupported configs")]
     UnsupportedConfigs,
     #[error("unsupported datatype")]
     UnsupportedDatatype,
+    #[error("unsupported file type")]
+    UnsupportedFileType,
 }
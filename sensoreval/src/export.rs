@@ -0,0 +1,218 @@
+use crate::Data;
+use crate::Error;
+
+fn state_len(samples: &[Data]) -> usize {
+    samples
+        .iter()
+        .filter_map(|s| s.actual.as_ref())
+        .map(|a| a.len())
+        .max()
+        .unwrap_or(0)
+}
+
+/// write samples as CSV, one row per sample
+///
+/// units are the same as in [Data], the time is in seconds. States are
+/// written as `state_N` columns if the samples contain them.
+pub fn write_csv<W: std::io::Write>(mut w: W, samples: &[Data]) -> Result<(), Error> {
+    let nstates = state_len(samples);
+
+    write!(
+        w,
+        "time,accel_x,accel_y,accel_z,gyro_x,gyro_y,gyro_z,mag_x,mag_y,mag_z,temperature,pressure"
+    )?;
+    for i in 0..nstates {
+        write!(w, ",state_{i}")?;
+    }
+    writeln!(w)?;
+
+    for sample in samples {
+        write!(w, "{}", sample.time_seconds())?;
+        for v in sample
+            .accel
+            .iter()
+            .chain(sample.gyro.iter())
+            .chain(sample.mag.iter())
+        {
+            write!(w, ",{v}")?;
+        }
+        write!(w, ",{},{}", sample.temperature, sample.pressure)?;
+
+        for i in 0..nstates {
+            match sample.actual.as_ref().and_then(|a| a.get(i)) {
+                Some(v) => write!(w, ",{v}")?,
+                None => write!(w, ",nan")?,
+            }
+        }
+        writeln!(w)?;
+    }
+
+    Ok(())
+}
+
+/// write samples as a numpy `.npz` archive
+///
+/// It contains the arrays `time` (N), `accel`, `gyro`, `mag` (N x 3),
+/// `temperature`, `pressure` (N) and `state` (N x M) if the samples contain
+/// states.
+pub fn write_npz<W: std::io::Write + std::io::Seek>(w: W, samples: &[Data]) -> Result<(), Error> {
+    let n = samples.len();
+    let nstates = state_len(samples);
+
+    let time = ndarray::Array::from_iter(samples.iter().map(|s| s.time_seconds()));
+    let temperature = ndarray::Array::from_iter(samples.iter().map(|s| s.temperature));
+    let pressure = ndarray::Array::from_iter(samples.iter().map(|s| s.pressure));
+
+    let mut accel = ndarray::Array2::<f64>::zeros((n, 3));
+    let mut gyro = ndarray::Array2::<f64>::zeros((n, 3));
+    let mut mag = ndarray::Array2::<f64>::zeros((n, 3));
+    let mut state = ndarray::Array2::<f64>::from_elem((n, nstates), f64::NAN);
+    for (i, sample) in samples.iter().enumerate() {
+        accel.row_mut(i).assign(&sample.accel);
+        gyro.row_mut(i).assign(&sample.gyro);
+        mag.row_mut(i).assign(&sample.mag);
+
+        if let Some(actual) = &sample.actual {
+            state
+                .slice_mut(ndarray::s![i, ..actual.len()])
+                .assign(actual);
+        }
+    }
+
+    let mut npz = ndarray_npy::NpzWriter::new(w);
+    npz.add_array("time", &time)?;
+    npz.add_array("accel", &accel)?;
+    npz.add_array("gyro", &gyro)?;
+    npz.add_array("mag", &mag)?;
+    npz.add_array("temperature", &temperature)?;
+    npz.add_array("pressure", &pressure)?;
+    if nstates > 0 {
+        npz.add_array("state", &state)?;
+    }
+    npz.finish()?;
+
+    Ok(())
+}
+
+/// write samples to `path`, the format is chosen by the file extension
+pub fn write_file<P: AsRef<std::path::Path>>(path: P, samples: &[Data]) -> Result<(), Error> {
+    let path = path.as_ref();
+
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("csv") => write_csv(
+            std::io::BufWriter::new(std::fs::File::create(path)?),
+            samples,
+        ),
+        Some("npz") => write_npz(std::fs::File::create(path)?, samples),
+        _ => Err(Error::UnsupportedFileType),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ndarray::array;
+
+    /// the second sample knows less states and the third one none
+    fn samples() -> Vec<Data> {
+        vec![
+            Data {
+                time: 0,
+                accel: array![1.0, 2.0, 3.0],
+                gyro: array![0.5, 0.0, -0.5],
+                mag: array![20.0, 0.0, -40.0],
+                temperature: 20.0,
+                pressure: 1013.25,
+                actual: Some(array![0.1, 0.2, 0.3]),
+                ..Data::default()
+            },
+            Data {
+                time: 500_000,
+                actual: Some(array![0.4]),
+                ..Data::default()
+            },
+            Data {
+                time: 1_500_000,
+                ..Data::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn csv() {
+        let mut buf = Vec::new();
+        write_csv(&mut buf, &samples()).unwrap();
+
+        let s = String::from_utf8(buf).unwrap();
+        let lines: Vec<_> = s.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "time,accel_x,accel_y,accel_z,gyro_x,gyro_y,gyro_z,mag_x,mag_y,mag_z,\
+                 temperature,pressure,state_0,state_1,state_2",
+                "0,1,2,3,0.5,0,-0.5,20,0,-40,20,1013.25,0.1,0.2,0.3",
+                "0.5,0,0,0,0,0,0,0,0,0,0,0,0.4,nan,nan",
+                "1.5,0,0,0,0,0,0,0,0,0,0,0,nan,nan,nan",
+            ]
+        );
+
+        // no state columns without states
+        let mut buf = Vec::new();
+        write_csv(&mut buf, &samples()[2..]).unwrap();
+        let s = String::from_utf8(buf).unwrap();
+        assert!(s.lines().next().unwrap().ends_with(",pressure"));
+    }
+
+    #[test]
+    fn npz() {
+        let samples = samples();
+        let mut cursor = std::io::Cursor::new(Vec::new());
+        write_npz(&mut cursor, &samples).unwrap();
+
+        cursor.set_position(0);
+        let mut npz = ndarray_npy::NpzReader::new(cursor).unwrap();
+        let names = npz.names().unwrap();
+        let name = |n: &str| {
+            names
+                .iter()
+                .find(|v| v.trim_end_matches(".npy") == n)
+                .unwrap_or_else(|| panic!("{n} not in {names:?}"))
+                .clone()
+        };
+
+        let time: ndarray::Array1<f64> = npz.by_name(&name("time")).unwrap();
+        testlib::assert_arr1_eq(&time, &array![0.0, 0.5, 1.5]);
+        let pressure: ndarray::Array1<f64> = npz.by_name(&name("pressure")).unwrap();
+        testlib::assert_arr1_eq(&pressure, &array![1013.25, 0.0, 0.0]);
+        let temperature: ndarray::Array1<f64> = npz.by_name(&name("temperature")).unwrap();
+        testlib::assert_arr1_eq(&temperature, &array![20.0, 0.0, 0.0]);
+
+        for (key, first) in [
+            ("accel", &samples[0].accel),
+            ("gyro", &samples[0].gyro),
+            ("mag", &samples[0].mag),
+        ] {
+            let arr: ndarray::Array2<f64> = npz.by_name(&name(key)).unwrap();
+            assert_eq!(arr.dim(), (3, 3));
+            testlib::assert_arr1_eq(&arr.row(0), first);
+            testlib::assert_arr1_eq(&arr.row(1), &array![0.0, 0.0, 0.0]);
+        }
+
+        let state: ndarray::Array2<f64> = npz.by_name(&name("state")).unwrap();
+        assert_eq!(state.dim(), (3, 3));
+        testlib::assert_arr1_eq(&state.row(0), &array![0.1, 0.2, 0.3]);
+        assert!((state[(1, 0)] - 0.4).abs() < 1.0e-12);
+        assert!(state.slice(ndarray::s![1, 1..]).iter().all(|v| v.is_nan()));
+        assert!(state.row(2).iter().all(|v| v.is_nan()));
+    }
+
+    #[test]
+    fn unsupported_extension() {
+        let path = std::env::temp_dir().join("sensoreval-export-test.txt");
+        assert!(matches!(
+            write_file(&path, &samples()),
+            Err(Error::UnsupportedFileType)
+        ));
+        assert!(!path.exists());
+    }
+}
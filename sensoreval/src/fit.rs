@@ -0,0 +1,339 @@
+use crate::config;
+use crate::Data;
+use crate::Error;
+use ndarray_linalg::solve::Inverse;
+use ndarray_linalg::Solve;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct FitParameter {
+    /// path inside of the simulator config, see [config::set_value]
+    pub path: String,
+    /// taken from the simulator config if not set
+    pub initial: Option<f64>,
+}
+
+fn default_accel_sigma() -> f64 {
+    1.0
+}
+
+fn default_gyro_sigma() -> f64 {
+    0.1
+}
+
+fn default_max_iterations() -> usize {
+    100
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct FitConfig {
+    /// config of the recording, relative to the fit config
+    pub data: String,
+    /// simulator config used as template, relative to the fit config. Its
+    /// `start_off` and `duration` have to cover the fitted time range.
+    pub simulator: String,
+    /// start of the fitted range in recording time, unit: s
+    #[serde(default)]
+    pub start: f64,
+    /// end of the fitted range in recording time, unit: s
+    pub end: Option<f64>,
+    /// weight of the accelerometer residuals, unit: m/s^2
+    #[serde(default = "default_accel_sigma")]
+    pub accel_sigma: f64,
+    /// weight of the gyroscope residuals, unit: rad/s
+    #[serde(default = "default_gyro_sigma")]
+    pub gyro_sigma: f64,
+    #[serde(default = "default_max_iterations")]
+    pub max_iterations: usize,
+    pub parameters: Vec<FitParameter>,
+}
+
+#[derive(Clone, Debug)]
+pub struct FitResult {
+    /// one value per [FitParameter]
+    pub values: ndarray::Array1<f64>,
+    /// standard deviations of `values`, scaled by the residual variance
+    pub stddev: ndarray::Array1<f64>,
+    /// half the sum of the squared weighted residuals
+    pub cost: f64,
+    pub iterations: usize,
+}
+
+/// fits simulator parameters to a recording using Levenberg–Marquardt
+pub struct Fit {
+    pub cfg: FitConfig,
+    recorded: Vec<Data>,
+    template: toml::Value,
+    basedir: std::path::PathBuf,
+}
+
+impl Fit {
+    pub fn load<P: AsRef<std::path::Path>>(filename: P) -> Result<Self, Error> {
+        let dir = filename
+            .as_ref()
+            .parent()
+            .expect("can't get parent dir of config");
+
+        let buffer = std::fs::read_to_string(filename.as_ref())?;
+        let cfg: FitConfig = toml::from_str(&buffer)?;
+        if cfg.parameters.is_empty() {
+            return Err(Error::InvalidConfig("no parameters to fit".to_string()));
+        }
+
+        let end = cfg.end.unwrap_or(f64::INFINITY);
+        let recorded: Vec<Data> = config::load(dir.join(&cfg.data))?
+            .load_data()?
+            .into_iter()
+            .filter(|s| (cfg.start..=end).contains(&s.time_seconds()))
+            .collect();
+        if recorded.is_empty() {
+            return Err(Error::NoDataSet);
+        }
+
+        let path = dir.join(&cfg.simulator);
+        let basedir = path
+            .parent()
+            .expect("can't get parent dir of config")
+            .to_path_buf();
+        let template = config::load_value(&path)?;
+
+        Ok(Self {
+            cfg,
+            recorded,
+            template,
+            basedir,
+        })
+    }
+
+    /// directory the relative paths of the template are relative to
+    pub fn basedir(&self) -> &std::path::Path {
+        &self.basedir
+    }
+
+    pub fn initial(&self) -> Result<ndarray::Array1<f64>, Error> {
+        self.cfg
+            .parameters
+            .iter()
+            .map(|p| {
+                p.initial
+                    .or_else(|| config::get_value(&self.template, &p.path))
+                    .ok_or_else(|| Error::InvalidConfigPath(p.path.clone()))
+            })
+            .collect()
+    }
+
+    /// returns the simulator config with the parameters set to `values`
+    pub fn to_value(&self, values: &ndarray::Array1<f64>) -> Result<toml::Value, Error> {
+        let mut value = self.template.clone();
+        for (param, v) in self.cfg.parameters.iter().zip(values) {
+            config::set_value(&mut value, &param.path, *v)?;
+        }
+
+        Ok(value)
+    }
+
+    /// weighted accel and gyro differences for every recorded sample
+    fn residuals(&self, values: &ndarray::Array1<f64>) -> Result<ndarray::Array1<f64>, Error> {
+        let mut cfg = config::from_value(self.to_value(values)?, &self.basedir)?;
+        // noise would make the residuals non-deterministic
+        cfg.data.noise = config::DataNoise::default();
+
+        let sd = match &cfg.data.source {
+            config::DataSource::SimulatorData(sd) => sd,
+            _ => return Err(Error::NoSimulatorData),
+        };
+        let (dt, start_off) = (sd.dt, sd.start_off);
+        let simulated = cfg.load_data()?;
+
+        let mut ret = Vec::with_capacity(self.recorded.len() * 6);
+        for sample in &self.recorded {
+            let id = ((sample.time_seconds() - start_off) / dt).round();
+            if id < 0.0 || id as usize >= simulated.len() {
+                return Err(Error::SampleNotFound);
+            }
+            let sim = &simulated[id as usize];
+
+            ret.extend(
+                (&sim.accel - &sample.accel)
+                    .iter()
+                    .map(|v| v / self.cfg.accel_sigma),
+            );
+            ret.extend(
+                (&sim.gyro - &sample.gyro)
+                    .iter()
+                    .map(|v| v / self.cfg.gyro_sigma),
+            );
+        }
+
+        Ok(ndarray::Array1::from(ret))
+    }
+
+    pub fn run(&self) -> Result<FitResult, Error> {
+        levenberg_marquardt(
+            |values| self.residuals(values),
+            self.initial()?,
+            self.cfg.max_iterations,
+        )
+    }
+}
+
+/// forward differences
+fn jacobian<F>(
+    residuals: &F,
+    values: &ndarray::Array1<f64>,
+    r: &ndarray::Array1<f64>,
+) -> Result<ndarray::Array2<f64>, Error>
+where
+    F: Fn(&ndarray::Array1<f64>) -> Result<ndarray::Array1<f64>, Error>,
+{
+    let mut jac = ndarray::Array2::zeros((r.len(), values.len()));
+
+    for i in 0..values.len() {
+        let h = f64::EPSILON.sqrt() * values[i].abs().max(1.0);
+        let mut shifted = values.clone();
+        shifted[i] += h;
+
+        let ri = residuals(&shifted)?;
+        jac.column_mut(i).assign(&((ri - r) / h));
+    }
+
+    Ok(jac)
+}
+
+/// minimizes half the sum of the squared `residuals`, starting at `initial`
+pub fn levenberg_marquardt<F>(
+    residuals: F,
+    initial: ndarray::Array1<f64>,
+    max_iterations: usize,
+) -> Result<FitResult, Error>
+where
+    F: Fn(&ndarray::Array1<f64>) -> Result<ndarray::Array1<f64>, Error>,
+{
+    let mut values = initial;
+    let mut r = residuals(&values)?;
+    let mut cost = r.dot(&r) / 2.0;
+    let mut lambda = 1.0e-3;
+    let mut iterations = 0;
+
+    while iterations < max_iterations {
+        iterations += 1;
+
+        let jac = jacobian(&residuals, &values, &r)?;
+        let jtj = jac.t().dot(&jac);
+        let g = jac.t().dot(&r);
+
+        let mut improved = false;
+        while lambda < 1.0e10 {
+            let mut a = jtj.clone();
+            for i in 0..a.nrows() {
+                a[(i, i)] += lambda * jtj[(i, i)].max(1.0e-12);
+            }
+
+            let delta = a.solve(&-&g)?;
+            let candidate = &values + &delta;
+            let rc = residuals(&candidate)?;
+            let cost_c = rc.dot(&rc) / 2.0;
+
+            if cost_c < cost {
+                let converged = (cost - cost_c) <= 1.0e-12 * cost
+                    || delta.dot(&delta).sqrt() <= 1.0e-10 * (values.dot(&values).sqrt() + 1.0e-10);
+
+                values = candidate;
+                r = rc;
+                cost = cost_c;
+                lambda /= 10.0;
+                improved = !converged;
+                break;
+            }
+
+            lambda *= 10.0;
+        }
+
+        if !improved {
+            break;
+        }
+    }
+
+    // covariance of the parameters, scaled by the residual variance. It
+    // doesn't exist if a parameter doesn't affect the residuals.
+    let jac = jacobian(&residuals, &values, &r)?;
+    let dof = (r.len() as f64 - values.len() as f64).max(1.0);
+    let stddev = match jac.t().dot(&jac).inv() {
+        Ok(inv) => (inv * (2.0 * cost / dof))
+            .diag()
+            .mapv(|v| v.max(0.0).sqrt()),
+        Err(_) => ndarray::Array1::from_elem(values.len(), f64::NAN),
+    };
+
+    Ok(FitResult {
+        values,
+        stddev,
+        cost,
+        iterations,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ndarray::array;
+    use rand::Rng as _;
+    use rand::SeedableRng as _;
+
+    #[test]
+    fn line() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        let x = ndarray::Array1::linspace(0.0, 10.0, 50);
+        let y = x.mapv(|x| 2.0 + 0.5 * x + rng.gen_range(-0.1..0.1));
+
+        let result =
+            levenberg_marquardt(|p| Ok(p[0] + p[1] * &x - &y), array![0.0, 0.0], 100).unwrap();
+
+        // linear least squares has a closed-form solution
+        let a = ndarray::stack![ndarray::Axis(1), ndarray::Array1::<f64>::ones(x.len()), x];
+        let ata_inv = a.t().dot(&a).inv().unwrap();
+        let expected = ata_inv.dot(&a.t().dot(&y));
+        let r = a.dot(&expected) - &y;
+        let variance = r.dot(&r) / (x.len() - 2) as f64;
+        let expected_stddev = ata_inv.diag().mapv(|v| (v * variance).sqrt());
+
+        testlib::assert_arr1_eq(&result.values, &expected);
+        testlib::assert_arr1_eq(&result.stddev, &expected_stddev);
+        assert!((result.cost - r.dot(&r) / 2.0).abs() < 1.0e-9);
+    }
+
+    #[test]
+    fn exponential() {
+        let x = ndarray::Array1::linspace(0.0, 2.0, 20);
+        let y = x.mapv(|x| 3.0 * (-1.5 * x).exp());
+
+        let result = levenberg_marquardt(
+            |p| Ok(x.mapv(|x| p[0] * (p[1] * x).exp()) - &y),
+            array![1.0, 0.0],
+            100,
+        )
+        .unwrap();
+
+        testlib::assert_arr1_eq(&result.values, &array![3.0, -1.5]);
+        assert!(result.cost < 1.0e-12);
+    }
+
+    #[test]
+    fn unidentifiable() {
+        let x = ndarray::Array1::linspace(0.0, 1.0, 10);
+
+        // the second parameter doesn't affect the residuals
+        let result = levenberg_marquardt(
+            |p| Ok(x.mapv(|x| p[0] * x - 2.0 * x)),
+            array![0.0, 1.0],
+            100,
+        )
+        .unwrap();
+
+        assert!((result.values[0] - 2.0).abs() < 1.0e-6);
+        assert_eq!(result.values[1], 1.0);
+        assert!(result.stddev.iter().all(|v| v.is_nan()));
+    }
+}
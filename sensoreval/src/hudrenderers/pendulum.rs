@@ -5,23 +5,233 @@ use crate::Data;
 use crate::Error;
 use crate::PlotUtils;
 use bincode::config::Options;
+use kalman::consistency::ConsistencyRecorder;
+use kalman::gating::Gating;
+use kalman::sigma_points::SigmaPoints;
 use kalman::ukf::Fx;
 use kalman::ukf::Hx;
 use kalman::Filter;
 use kalman::Normalize;
 use kalman::SetDt;
+use kalman::Subtract;
 use ndarray::array;
 use ndarray::azip;
 use ndarray::s;
 use sensoreval_graphics::utils::CairoEx;
 use sensoreval_graphics::utils::ToUtilFont;
+use sensoreval_psim::models::pendulum::Brake as PendulumBrake;
+use sensoreval_psim::models::pendulum::Motor as PendulumMotor;
+use sensoreval_psim::models::pendulum::Physical as PendulumPhysical;
 use sensoreval_psim::models::pendulum::State as PendulumState;
 use sensoreval_psim::models::pendulum::StateArgs as PendulumStateArgs;
 use sensoreval_psim::Model;
 use sensoreval_psim::ToImuSample;
 use sensoreval_utils::macros::*;
+use sensoreval_utils::StateUtils;
 use serde::Deserialize;
 
+/// state estimator
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FilterType {
+    #[default]
+    #[serde(rename = "ukf")]
+    Ukf,
+    /// square-root UKF, numerically more robust on long recordings
+    #[serde(rename = "srukf")]
+    SrUkf,
+    /// uses finite-difference jacobians
+    #[serde(rename = "ekf")]
+    Ekf,
+    /// handles ambiguous angles, e.g. near the top or on full loops
+    #[serde(rename = "particle")]
+    Particle,
+    /// UKFs for the phases of a ride, configured by `imm`
+    #[serde(rename = "imm")]
+    Imm,
+}
+
+fn default_merwe_alpha() -> f64 {
+    0.1
+}
+
+fn default_merwe_beta() -> f64 {
+    2.0
+}
+
+fn default_merwe_kappa() -> f64 {
+    -4.0
+}
+
+/// parameters of [MerweScaledSigmaPoints](kalman::sigma_points::MerweScaledSigmaPoints)
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MerweParams {
+    /// between 0 and 1, a larger value spreads the sigma points further from
+    /// the mean
+    #[serde(default = "default_merwe_alpha")]
+    pub alpha: f64,
+    /// 2 is a good choice for gaussian problems
+    #[serde(default = "default_merwe_beta")]
+    pub beta: f64,
+    #[serde(default = "default_merwe_kappa")]
+    pub kappa: f64,
+}
+
+impl Default for MerweParams {
+    fn default() -> Self {
+        Self {
+            alpha: default_merwe_alpha(),
+            beta: default_merwe_beta(),
+            kappa: default_merwe_kappa(),
+        }
+    }
+}
+
+/// parameters of [JulierSigmaPoints](kalman::sigma_points::JulierSigmaPoints)
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct JulierParams {
+    #[serde(default)]
+    pub kappa: f64,
+}
+
+/// sigma point set of the UKF and the SR-UKF
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum SigmaPointsType {
+    #[serde(rename = "merwe")]
+    Merwe(MerweParams),
+    #[serde(rename = "julier")]
+    Julier(JulierParams),
+    /// `n + 1` points, the cheapest set
+    #[serde(rename = "simplex")]
+    Simplex,
+    /// spherical-radial cubature rule, all weights are positive
+    #[serde(rename = "cubature")]
+    Cubature,
+}
+
+impl Default for SigmaPointsType {
+    fn default() -> Self {
+        Self::Merwe(MerweParams::default())
+    }
+}
+
+/// what happens to measurements outside of the gate
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GateAction {
+    #[default]
+    #[serde(rename = "reject")]
+    Reject,
+    #[serde(rename = "inflate_r")]
+    InflateR,
+}
+
+impl From<GateAction> for kalman::gating::GateAction {
+    fn from(action: GateAction) -> Self {
+        match action {
+            GateAction::Reject => Self::Reject,
+            GateAction::InflateR => Self::InflateR,
+        }
+    }
+}
+
+fn default_gate_probability() -> f64 {
+    0.9999
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Gate {
+    /// probability of a valid measurement to pass the gate
+    #[serde(default = "default_gate_probability")]
+    pub probability: f64,
+    #[serde(default)]
+    pub action: GateAction,
+}
+
+fn default_adaptive_forgetting() -> f64 {
+    0.98
+}
+
+fn default_num_particles() -> usize {
+    1000
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// sage-husa style estimation of the noise matrices
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Adaptive {
+    /// between 0 and 1, larger values adapt slower
+    #[serde(default = "default_adaptive_forgetting")]
+    pub forgetting: f64,
+    /// estimate the process noise, replaces the hardcoded one
+    #[serde(default = "default_true")]
+    pub adapt_q: bool,
+    /// estimate the measurement noise, starts at `stdev`
+    #[serde(default = "default_true")]
+    pub adapt_r: bool,
+}
+
+fn default_imm_input() -> f64 {
+    1.0
+}
+
+fn default_imm_stay_probability() -> f64 {
+    0.98
+}
+
+/// modes of the IMM estimator. Free swinging is always one of them, at
+/// least one of `motor` and `brake` is required.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ImmModes {
+    /// drive of the "motor on" mode
+    #[serde(default)]
+    pub motor: Option<PendulumMotor>,
+    /// control input of `motor`, always applied into the direction of
+    /// movement
+    #[serde(default = "default_imm_input")]
+    pub throttle: f64,
+    /// brake of the "braking" mode
+    #[serde(default)]
+    pub brake: Option<PendulumBrake>,
+    #[serde(default = "default_imm_input")]
+    pub brake_pressure: f64,
+    /// probability of staying in a mode from one sample to the next
+    #[serde(default = "default_imm_stay_probability")]
+    pub stay_probability: f64,
+}
+
+fn default_process_noise() -> f64 {
+    0.001
+}
+
+/// variances of the piecewise white noise models of the process noise
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ProcessNoise {
+    /// of the pendulum angle and its rate
+    #[serde(default = "default_process_noise")]
+    pub theta: f64,
+    /// of the sensor rotation
+    #[serde(default = "default_process_noise")]
+    pub rotation: f64,
+}
+
+impl Default for ProcessNoise {
+    fn default() -> Self {
+        Self {
+            theta: default_process_noise(),
+            rotation: default_process_noise(),
+        }
+    }
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct Config {
     /// standard deviation of the measurements, used for matrix R
@@ -33,14 +243,130 @@ pub struct Config {
     /// initial conditions, used for matrix P
     pub initial_cov: Vec<f64>,
 
+    /// used for matrix Q
+    #[serde(default)]
+    pub process_noise: ProcessNoise,
+
+    #[serde(default)]
+    pub filter: FilterType,
+
+    /// only used by the UKF and the SR-UKF
+    #[serde(default)]
+    pub sigma_points: SigmaPointsType,
+
+    /// only used by the particle filter
+    #[serde(default = "default_num_particles")]
+    pub num_particles: usize,
+
+    /// only used by the IMM filter
+    #[serde(default)]
+    pub imm: Option<ImmModes>,
+
+    /// mahalanobis distance gating of the measurements, e.g. against bumps
+    /// of the sensor
+    #[serde(default)]
+    pub gate: Option<Gate>,
+
+    /// adapt Q and R to the innovations, so calm and violent parts of a
+    /// ride don't need different tuning
+    #[serde(default)]
+    pub adaptive: Option<Adaptive>,
+
+    /// start from the filter state saved in this json file instead of
+    /// `initial` and `initial_cov`, if it exists
+    #[serde(default)]
+    pub checkpoint_load: Option<String>,
+
+    /// save the final filter state to this json file, e.g. to start the next
+    /// ride from it. The IMM saves all its modes and their probabilities.
+    #[serde(default)]
+    pub checkpoint_save: Option<String>,
+
+    /// only supported by the UKF and the IMM, also smoothes the covariances
+    /// shown in the plots
     #[serde(default)]
     pub enable_rts_smoother: bool,
 
     pub active_row: usize,
+
+    /// model the ride as a physical instead of a point-mass pendulum
+    #[serde(default)]
+    pub physical: Option<PendulumPhysical>,
+}
+
+impl Config {
+    /// rejects options the selected filter doesn't support
+    pub fn validate(&self) -> Result<(), Error> {
+        let filter = match self.filter {
+            FilterType::Ukf | FilterType::Imm => None,
+            FilterType::SrUkf => Some("the SR-UKF"),
+            FilterType::Ekf => Some("the EKF"),
+            FilterType::Particle => Some("the particle filter"),
+        };
+        if let (true, Some(filter)) = (self.enable_rts_smoother, filter) {
+            return Err(Error::InvalidConfig(format!(
+                "the RTS smoother isn't supported by {filter}"
+            )));
+        }
+
+        if self.filter == FilterType::Imm && self.adaptive.is_some() {
+            return Err(Error::InvalidConfig(
+                "adaptive noise estimation isn't supported by the IMM".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Default)]
-struct XFunctions;
+struct XFunctions {
+    physical: Option<PendulumPhysical>,
+    /// motor or brake of an IMM mode
+    motor: Option<PendulumMotor>,
+    control_input: f64,
+}
+
+impl XFunctions {
+    pub fn new(cfg: &Config) -> Self {
+        Self {
+            physical: cfg.physical.clone(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_motor(cfg: &Config, motor: PendulumMotor, control_input: f64) -> Self {
+        Self {
+            motor: Some(motor),
+            control_input,
+            ..Self::new(cfg)
+        }
+    }
+
+    fn model(&self, x: &ndarray::ArrayView1<f64>, dt: f64) -> sensoreval_psim::models::Pendulum {
+        let params = sensoreval_psim::models::PendulumParams {
+            radius: x[X::Radius],
+            sensor_pos: x[X::SensorPos],
+            motor: self.motor.clone(),
+            physical: self.physical.clone(),
+        };
+        let mut model = sensoreval_psim::models::Pendulum::new(params, dt);
+
+        if let Some(motor) = &self.motor {
+            // the ground motor and the brake already act against or into the
+            // direction of movement
+            let ci = match motor {
+                PendulumMotor::PivotMotor(_) | PendulumMotor::TireDrive(_) => {
+                    self.control_input.copysign(x[X::ThetaD])
+                }
+                _ => self.control_input,
+            };
+            model.set_control_input(Some(&[ci]));
+        }
+
+        model
+    }
+}
 
 #[derive(State, KalmanMath, UKFMath)]
 #[state(fnstruct = "XFunctions")]
@@ -74,13 +400,152 @@ enum Z {
     GyroU,
 }
 
+/// the sigma point set selected in the config
+enum SigmaPointsFn {
+    Merwe(kalman::sigma_points::MerweScaledSigmaPoints<XFunctions>),
+    Julier(kalman::sigma_points::JulierSigmaPoints<XFunctions>),
+    Simplex(kalman::sigma_points::SimplexSigmaPoints<XFunctions>),
+    Cubature(kalman::sigma_points::CubatureSigmaPoints<XFunctions>),
+}
+
+impl SigmaPointsFn {
+    pub fn new(cfg: &SigmaPointsType) -> Self {
+        let n = X::len();
+        match cfg {
+            SigmaPointsType::Merwe(p) => {
+                Self::Merwe(kalman::sigma_points::MerweScaledSigmaPoints::new(
+                    n,
+                    p.alpha,
+                    p.beta,
+                    p.kappa,
+                    XFunctions::default(),
+                ))
+            }
+            SigmaPointsType::Julier(p) => Self::Julier(
+                kalman::sigma_points::JulierSigmaPoints::new(n, p.kappa, XFunctions::default()),
+            ),
+            SigmaPointsType::Simplex => Self::Simplex(
+                kalman::sigma_points::SimplexSigmaPoints::new(n, XFunctions::default()),
+            ),
+            SigmaPointsType::Cubature => Self::Cubature(
+                kalman::sigma_points::CubatureSigmaPoints::new(n, XFunctions::default()),
+            ),
+        }
+    }
+}
+
+macro_rules! sigma_points_dispatch {
+    ($self:ident, $p:ident => $e:expr) => {
+        match $self {
+            Self::Merwe($p) => $e,
+            Self::Julier($p) => $e,
+            Self::Simplex($p) => $e,
+            Self::Cubature($p) => $e,
+        }
+    };
+}
+
+impl SigmaPoints for SigmaPointsFn {
+    type Elem = f64;
+
+    fn num_sigmas(&self) -> usize {
+        sigma_points_dispatch!(self, p => p.num_sigmas())
+    }
+
+    #[allow(non_snake_case)]
+    fn sigma_points<S>(
+        &self,
+        x: &ndarray::ArrayBase<S, ndarray::Ix1>,
+        P: &ndarray::ArrayBase<S, ndarray::Ix2>,
+    ) -> Result<ndarray::Array2<f64>, kalman::Error>
+    where
+        S: ndarray::Data<Elem = f64>,
+    {
+        sigma_points_dispatch!(self, p => p.sigma_points(x, P))
+    }
+
+    #[allow(non_snake_case)]
+    fn sigma_points_sqrt<Sx, Su>(
+        &self,
+        x: &ndarray::ArrayBase<Sx, ndarray::Ix1>,
+        U: &ndarray::ArrayBase<Su, ndarray::Ix2>,
+    ) -> Result<ndarray::Array2<f64>, kalman::Error>
+    where
+        Sx: ndarray::Data<Elem = f64>,
+        Su: ndarray::Data<Elem = f64>,
+    {
+        sigma_points_dispatch!(self, p => p.sigma_points_sqrt(x, U))
+    }
+
+    fn weights_covariance(&self) -> ndarray::Array1<f64> {
+        sigma_points_dispatch!(self, p => p.weights_covariance())
+    }
+
+    fn weights_mean(&self) -> ndarray::Array1<f64> {
+        sigma_points_dispatch!(self, p => p.weights_mean())
+    }
+}
+
+fn save_checkpoint<T: serde::Serialize>(path: &str, checkpoint: &T) -> Result<(), Error> {
+    let file = std::fs::File::create(path)?;
+    serde_json::to_writer_pretty(std::io::BufWriter::new(file), checkpoint)?;
+    Ok(())
+}
+
+/// estimates, covariances, process noises, time steps and gated updates of a
+/// filter run
+type FilterRun = (
+    Vec<ndarray::Array1<f64>>,
+    Vec<ndarray::Array2<f64>>,
+    Vec<ndarray::Array2<f64>>,
+    Vec<f64>,
+    Vec<bool>,
+);
+
+/// mixed estimates and covariances, mode probabilities, the runs of the
+/// single modes and the log-likelihood of an IMM run
+type ImmRun = (
+    Vec<ndarray::Array1<f64>>,
+    Vec<ndarray::Array2<f64>>,
+    Vec<ndarray::Array1<f64>>,
+    Vec<FilterRun>,
+    f64,
+);
+
+fn measurement(sample: &Data) -> ndarray::Array1<f64> {
+    ndarray::Array1::from(ZArgs {
+        accel_e: sample.accel[0],
+        accel_n: sample.accel[1],
+        accel_u: sample.accel[2],
+        gyro_e: sample.gyro[0],
+        gyro_n: sample.gyro[1],
+        gyro_u: sample.gyro[2],
+    })
+}
+
 struct FxArgs {
     dt: f64,
+    process_noise: kalman::discretization::BlockDiagonal<f64>,
 }
 
 impl FxArgs {
-    pub fn new(dt: f64) -> Self {
-        Self { dt }
+    pub fn new(cfg: &Config, dt: f64) -> Self {
+        use kalman::discretization::NoiseBlock;
+
+        // the radius and the sensor position are constant
+        let process_noise = kalman::discretization::BlockDiagonal::new(X::len())
+            .with_block(
+                0..2,
+                NoiseBlock::DiscreteWhiteNoise(cfg.process_noise.theta),
+            )
+            .unwrap()
+            .with_block(
+                4..7,
+                NoiseBlock::DiscreteWhiteNoise(cfg.process_noise.rotation),
+            )
+            .unwrap();
+
+        Self { dt, process_noise }
     }
 }
 
@@ -93,12 +558,7 @@ impl kalman::SetDt<f64> for FxArgs {
 impl kalman::ukf::ApplyDt<f64> for FxArgs {
     #[allow(non_snake_case)]
     fn apply_dt(&self, Q: &mut ndarray::Array2<f64>) {
-        Q.slice_mut(s![0..2, 0..2])
-            .assign(&kalman::discretization::Q_discrete_white_noise(2, self.dt, 0.001).unwrap());
-        Q[[2, 2]] = 0.0;
-        Q[[3, 3]] = 0.0;
-        Q.slice_mut(s![4..7, 4..7])
-            .assign(&kalman::discretization::Q_discrete_white_noise(3, self.dt, 0.001).unwrap());
+        Q.assign(&self.process_noise.Q(self.dt).unwrap());
     }
 }
 
@@ -113,18 +573,15 @@ impl kalman::ukf::Fx<FxArgs> for XFunctions {
     where
         S: ndarray::Data<Elem = Self::Elem>,
     {
-        let params = sensoreval_psim::models::PendulumParams {
-            radius: x[X::Radius],
-            sensor_pos: x[X::SensorPos],
-            motor: None,
-        };
-        let mut model = sensoreval_psim::models::Pendulum::new(params, args.dt);
+        let mut model = self.model(&x.view(), args.dt);
 
         let mut psim_state = ndarray::Array1::from(PendulumStateArgs {
             theta: x[X::Theta],
             theta_d: x[X::ThetaD],
         });
-        model.step(&mut psim_state);
+        model
+            .step(&mut psim_state)
+            .expect("the default integrator can't fail");
 
         self.normalize(ndarray::Array1::from(XArgs {
             theta: psim_state[PendulumState::Theta],
@@ -148,12 +605,7 @@ impl kalman::ukf::Hx for XFunctions {
         let rot = x.slice(ndarray::s![4..7]);
         let rot = rot.as_slice().unwrap();
 
-        let params = sensoreval_psim::models::PendulumParams {
-            radius: x[X::Radius],
-            sensor_pos: x[X::SensorPos],
-            motor: None,
-        };
-        let model = sensoreval_psim::models::Pendulum::new(params, 0.1);
+        let model = self.model(&x.view(), 0.1);
 
         let psim_state = ndarray::Array1::from(PendulumStateArgs {
             theta: x[X::Theta],
@@ -179,9 +631,433 @@ impl kalman::ukf::Hx for XFunctions {
     }
 }
 
+/// result of running the configured filter over a dataset
+struct Estimate {
+    xs: Vec<ndarray::Array1<f64>>,
+    Ps: Vec<ndarray::Array2<f64>>,
+    consistency: ConsistencyRecorder,
+    gated: Vec<bool>,
+    mode_names: Vec<&'static str>,
+    mode_probabilities: Vec<ndarray::Array1<f64>>,
+    /// of all measurements, before smoothing
+    log_likelihood: f64,
+}
+
+/// quality of the configured filter on a dataset, see [evaluate]
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Score {
+    /// of all measurements, larger is better
+    pub log_likelihood: f64,
+    /// of the estimates over all states which are known from the
+    /// simulation, `None` for recordings
+    pub rmse: Option<f64>,
+}
+
+/// runs the filter of `cfg` over `samples` without rendering anything
+pub(crate) fn evaluate(cfg: &Config, samples: &[Data]) -> Result<Score, Error> {
+    let estimate = Estimator::new(cfg).run(samples)?;
+    let fns = XFunctions::new(cfg);
+
+    let mut sum = 0.0;
+    let mut count = 0;
+    for (x, sample) in estimate.xs.iter().zip(samples) {
+        let actual = unwrap_opt_or!(&sample.actual, continue);
+        let n = actual.len().min(x.len());
+
+        // unknown states don't contribute to the error
+        let mut truth = x.clone();
+        truth.slice_mut(s![..n]).assign(&actual.slice(s![..n]));
+        let e = fns.subtract(x, &truth);
+
+        sum += e.dot(&e);
+        count += n;
+    }
+
+    Ok(Score {
+        log_likelihood: estimate.log_likelihood,
+        rmse: (count > 0).then(|| (sum / count as f64).sqrt()),
+    })
+}
+
+struct Estimator<'a> {
+    cfg: &'a Config,
+}
+
+impl<'a> Estimator<'a> {
+    pub fn new(cfg: &'a Config) -> Self {
+        Self { cfg }
+    }
+
+    #[allow(non_snake_case)]
+    pub fn run(&self, samples: &[Data]) -> Result<Estimate, Error> {
+        let mut consistency = ConsistencyRecorder::default();
+        let mut mode_names = Vec::new();
+        let mut mode_probabilities = Vec::new();
+        let mut log_likelihood = None;
+        let (xs, Ps, gated) = match self.cfg.filter {
+            FilterType::Ukf => {
+                let points_fn = SigmaPointsFn::new(&self.cfg.sigma_points);
+                let mut ukf = kalman::ukf::Ukf::new(
+                    7,
+                    6,
+                    &points_fn,
+                    XFunctions::new(self.cfg),
+                    FxArgs::new(self.cfg, 0.1),
+                    ZFunctions::default(),
+                );
+                let (xs, Ps, Qs, dts, gated) =
+                    self.run_filter(&mut ukf, samples, &mut consistency)?;
+
+                if self.cfg.enable_rts_smoother {
+                    let (xss, Pss) = ukf.rts_smoother(&xs, &Ps, Some(&Qs), &dts)?;
+                    (xss, Pss, gated)
+                } else {
+                    (xs, Ps, gated)
+                }
+            }
+            FilterType::SrUkf => {
+                let points_fn = SigmaPointsFn::new(&self.cfg.sigma_points);
+                let mut srukf = kalman::ukf::SrUkf::new(
+                    7,
+                    6,
+                    &points_fn,
+                    XFunctions::new(self.cfg),
+                    FxArgs::new(self.cfg, 0.1),
+                    ZFunctions::default(),
+                );
+                let (xs, Ps, _, _, gated) =
+                    self.run_filter(&mut srukf, samples, &mut consistency)?;
+                (xs, Ps, gated)
+            }
+            FilterType::Ekf => {
+                let mut ekf = kalman::ekf::Ekf::new(
+                    7,
+                    6,
+                    XFunctions::new(self.cfg),
+                    FxArgs::new(self.cfg, 0.1),
+                    ZFunctions::default(),
+                    kalman::ekf::FiniteDifferenceJacobians::default(),
+                );
+                let (xs, Ps, _, _, gated) = self.run_filter(&mut ekf, samples, &mut consistency)?;
+                (xs, Ps, gated)
+            }
+            FilterType::Particle => {
+                let mut pf = kalman::particle::ParticleFilter::new(
+                    7,
+                    6,
+                    self.cfg.num_particles,
+                    XFunctions::new(self.cfg),
+                    FxArgs::new(self.cfg, 0.1),
+                    ZFunctions::default(),
+                    0,
+                );
+                let (xs, Ps, _, _, gated) = self.run_filter(&mut pf, samples, &mut consistency)?;
+                (xs, Ps, gated)
+            }
+            FilterType::Imm => {
+                let modes_cfg = self.cfg.imm.as_ref().ok_or_else(|| {
+                    Error::InvalidConfig("the IMM filter needs the `imm` config".to_string())
+                })?;
+
+                let mut modes = vec![("free swing", XFunctions::new(self.cfg))];
+                if let Some(motor) = &modes_cfg.motor {
+                    modes.push((
+                        "motor on",
+                        XFunctions::with_motor(self.cfg, motor.clone(), modes_cfg.throttle),
+                    ));
+                }
+                if let Some(brake) = &modes_cfg.brake {
+                    modes.push((
+                        "braking",
+                        XFunctions::with_motor(
+                            self.cfg,
+                            PendulumMotor::Brake(brake.clone()),
+                            modes_cfg.brake_pressure,
+                        ),
+                    ));
+                }
+                if modes.len() < 2 {
+                    return Err(Error::InvalidConfig(
+                        "the IMM filter needs a motor or a brake".to_string(),
+                    ));
+                }
+
+                let points_fn = SigmaPointsFn::new(&self.cfg.sigma_points);
+                let mut filters = Vec::with_capacity(modes.len());
+                for (name, fns_x) in modes {
+                    let mut ukf = kalman::ukf::Ukf::new(
+                        7,
+                        6,
+                        &points_fn,
+                        fns_x,
+                        FxArgs::new(self.cfg, 0.1),
+                        ZFunctions::default(),
+                    );
+                    self.init_filter(&mut ukf);
+                    mode_names.push(name);
+                    filters.push(ukf);
+                }
+
+                let n = filters.len();
+                let stay = modes_cfg.stay_probability;
+                let M = ndarray::Array2::from_shape_fn((n, n), |(i, j)| {
+                    if i == j {
+                        stay
+                    } else {
+                        (1.0 - stay) / (n - 1) as f64
+                    }
+                });
+                let mu = ndarray::Array1::from_elem(n, 1.0 / n as f64);
+                let mut imm = kalman::imm::Imm::new(filters, mu, M, XFunctions::default())?;
+                let checkpoint: Option<kalman::checkpoint::ImmCheckpoint<f64>> =
+                    self.load_checkpoint();
+                if let Some(checkpoint) = checkpoint {
+                    if let Err(e) = imm.restore(&checkpoint) {
+                        println!("can't restore checkpoint: {e}");
+                    }
+                }
+
+                let (xs, Ps, mus, modes, imm_log_likelihood) = self.run_imm(&mut imm, samples)?;
+                log_likelihood = Some(imm_log_likelihood);
+                let gated = (0..xs.len())
+                    .map(|k| modes.iter().any(|mode| mode.4[k]))
+                    .collect();
+
+                if self.cfg.enable_rts_smoother {
+                    let smoothed = imm
+                        .filters_mut()
+                        .iter_mut()
+                        .zip(&modes)
+                        .map(|(ukf, (xs, Ps, Qs, dts, _))| ukf.rts_smoother(xs, Ps, Some(Qs), dts))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let ((xss, Pss), mus) = imm.smooth(&smoothed, &mus)?;
+                    mode_probabilities = mus;
+                    (xss, Pss, gated)
+                } else {
+                    mode_probabilities = mus;
+                    (xs, Ps, gated)
+                }
+            }
+        };
+
+        Ok(Estimate {
+            xs,
+            Ps,
+            log_likelihood: log_likelihood.unwrap_or_else(|| consistency.log_likelihood()),
+            consistency,
+            gated,
+            mode_names,
+            mode_probabilities,
+        })
+    }
+
+    /// initializes `filter` from the config
+    fn init_filter<F>(&self, filter: &mut F)
+    where
+        F: Filter<Elem = f64> + kalman::Q<Elem = f64> + kalman::R<Elem = f64> + Gating<Elem = f64>,
+    {
+        filter.set_gate(self.cfg.gate.as_ref().map(|gate| {
+            kalman::gating::Gate::with_probability(gate.probability, Z::len(), gate.action.into())
+        }));
+
+        *filter.x_mut() = ndarray::Array::from(self.cfg.initial.clone());
+        *filter.P_mut() =
+            ndarray::Array::from_diag(&ndarray::Array::from(self.cfg.initial_cov.clone()));
+        *filter.R_mut() = ndarray::Array2::from_diag(&array![
+            self.cfg.stdev.accel.x.powi(2),
+            self.cfg.stdev.accel.y.powi(2),
+            self.cfg.stdev.accel.z.powi(2),
+            self.cfg.stdev.gyro.x.powi(2),
+            self.cfg.stdev.gyro.y.powi(2),
+            self.cfg.stdev.gyro.z.powi(2),
+        ]);
+    }
+
+    /// continues from the single filter checkpoint, if there's one
+    fn restore_filter<F>(&self, filter: &mut F)
+    where
+        F: Filter<Elem = f64> + kalman::Q<Elem = f64> + kalman::R<Elem = f64>,
+    {
+        let checkpoint: Option<kalman::checkpoint::Checkpoint<f64>> = self.load_checkpoint();
+        if let Some(checkpoint) = checkpoint {
+            // e.g. a checkpoint of a different filter, keep the config values
+            if let Err(e) = checkpoint.restore(filter) {
+                println!("can't restore checkpoint: {e}");
+            }
+        }
+    }
+
+    /// initializes `filter` from the config and runs it over all samples
+    #[allow(non_snake_case)]
+    fn run_filter<F>(
+        &self,
+        filter: &mut F,
+        samples: &[Data],
+        consistency: &mut ConsistencyRecorder,
+    ) -> Result<FilterRun, kalman::Error>
+    where
+        F: Filter<Elem = f64, Meas = ndarray::Array1<f64>>
+            + SetDt<f64>
+            + kalman::Q<Elem = f64>
+            + kalman::R<Elem = f64>
+            + kalman::Innovation<Elem = f64>
+            + Gating<Elem = f64>,
+    {
+        let fns = XFunctions::new(self.cfg);
+        self.init_filter(filter);
+        self.restore_filter(filter);
+
+        let mut xs = Vec::with_capacity(samples.len());
+        let mut Ps = Vec::with_capacity(samples.len());
+        let mut Qs = Vec::with_capacity(samples.len());
+        let mut dts = Vec::with_capacity(samples.len());
+        let mut gated = Vec::with_capacity(samples.len());
+        let mut adaptive = self.cfg.adaptive.as_ref().map(|adaptive| {
+            kalman::adaptive::AdaptiveNoise::new(kalman::adaptive::AdaptiveNoiseConfig {
+                forgetting: adaptive.forgetting,
+                adapt_Q: adaptive.adapt_q,
+                adapt_R: adaptive.adapt_r,
+                ..Default::default()
+            })
+        });
+
+        let mut t_prev = match samples.get(0) {
+            Some(v) => v.time,
+            None => 0,
+        };
+        for sample in samples {
+            let z = measurement(sample);
+            let dt = (sample.time - t_prev) as f64 / 1_000_000.0f64;
+
+            filter.set_dt(&dt);
+            if let Some(adaptive) = &adaptive {
+                adaptive.before_predict(filter);
+            }
+            filter.predict()?;
+            if let Some(adaptive) = &mut adaptive {
+                adaptive.after_predict(filter);
+            }
+            filter.update(&z)?;
+            if let Some(adaptive) = &mut adaptive {
+                adaptive.after_update(filter, &fns)?;
+            }
+
+            // simulations only know the first states, theta and theta_d
+            let truth = sample.actual.as_ref().filter(|a| a.len() <= X::len());
+            consistency.record(filter, truth, &fns)?;
+
+            xs.push(filter.x().clone());
+            Ps.push(filter.P().clone());
+            Qs.push(filter.Q().clone());
+            dts.push(dt);
+            gated.push(filter.gated());
+
+            t_prev = sample.time;
+        }
+
+        self.save_checkpoint(&kalman::checkpoint::Checkpoint::new(filter));
+
+        Ok((xs, Ps, Qs, dts, gated))
+    }
+
+    /// runs an IMM of filters which were initialized with
+    /// [init_filter](Self::init_filter) over all samples
+    #[allow(non_snake_case)]
+    fn run_imm<F>(
+        &self,
+        imm: &mut kalman::imm::Imm<F, XFunctions, f64>,
+        samples: &[Data],
+    ) -> Result<ImmRun, kalman::Error>
+    where
+        F: Filter<Elem = f64, Meas = ndarray::Array1<f64>>
+            + SetDt<f64>
+            + kalman::Q<Elem = f64>
+            + kalman::R<Elem = f64>
+            + Gating<Elem = f64>,
+    {
+        let mut xs = Vec::with_capacity(samples.len());
+        let mut Ps = Vec::with_capacity(samples.len());
+        let mut mus = Vec::with_capacity(samples.len());
+        let mut log_likelihood = 0.0;
+        let mut modes =
+            vec![(Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new()); imm.filters().len()];
+
+        let mut t_prev = match samples.get(0) {
+            Some(v) => v.time,
+            None => 0,
+        };
+        for sample in samples {
+            let z = measurement(sample);
+            let dt = (sample.time - t_prev) as f64 / 1_000_000.0f64;
+
+            imm.set_dt(&dt);
+            imm.predict()?;
+            imm.update(&z)?;
+            log_likelihood += imm.likelihood()?.ln();
+
+            xs.push(imm.x().clone());
+            Ps.push(imm.P().clone());
+            mus.push(imm.mu().clone());
+            for (filter, mode) in imm.filters().iter().zip(&mut modes) {
+                mode.0.push(filter.x().clone());
+                mode.1.push(filter.P().clone());
+                mode.2.push(filter.Q().clone());
+                mode.3.push(dt);
+                mode.4.push(filter.gated());
+            }
+
+            t_prev = sample.time;
+        }
+
+        self.save_checkpoint(&imm.checkpoint());
+
+        Ok((xs, Ps, mus, modes, log_likelihood))
+    }
+
+    fn save_checkpoint<T: serde::Serialize>(&self, checkpoint: &T) {
+        if let Some(path) = &self.cfg.checkpoint_save {
+            if let Err(e) = save_checkpoint(path, checkpoint) {
+                println!("can't save checkpoint {path}: {e}");
+            }
+        }
+    }
+
+    /// a missing file isn't an error so the first ride can create it
+    fn load_checkpoint<T: serde::de::DeserializeOwned>(&self) -> Option<T> {
+        let path = self.cfg.checkpoint_load.as_ref()?;
+        if !std::path::Path::new(path).exists() {
+            return None;
+        }
+
+        let file = match std::fs::File::open(path) {
+            Ok(v) => v,
+            Err(e) => {
+                println!("can't load checkpoint {path}: {e}");
+                return None;
+            }
+        };
+        match serde_json::from_reader(std::io::BufReader::new(file)) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                println!("can't load checkpoint {path}: {e}");
+                None
+            }
+        }
+    }
+}
+
 pub(crate) struct Pendulum {
     cfg: Config,
     est: Vec<ndarray::Array1<f64>>,
+    /// covariances of `est`
+    est_P: Vec<ndarray::Array2<f64>>,
+    /// of the filter run, before smoothing
+    consistency: ConsistencyRecorder,
+    /// updates which were rejected or had their `R` inflated
+    gated: Vec<bool>,
+    /// of the IMM
+    mode_names: Vec<&'static str>,
+    mode_probabilities: Vec<ndarray::Array1<f64>>,
     font: pango::FontDescription,
     svg_speed: librsvg::SvgHandle,
     svg_height: librsvg::SvgHandle,
@@ -193,6 +1069,11 @@ impl Pendulum {
         let mut o = Self {
             cfg: (*cfg).clone(),
             est: Vec::new(),
+            est_P: Vec::new(),
+            consistency: ConsistencyRecorder::default(),
+            gated: Vec::new(),
+            mode_names: Vec::new(),
+            mode_probabilities: Vec::new(),
             font: pango::FontDescription::new(),
             svg_speed: sensoreval_graphics::utils::bytes_to_svghandle(
                 sensoreval_graphics::ICON_SPEED,
@@ -226,14 +1107,85 @@ impl Pendulum {
         x[X::Radius] - (x[X::Theta] + x[X::SensorPos]).cos() * x[X::Radius]
     }
 
+    /// NIS, NEES and innovation autocorrelation with their 95% bounds
+    fn plot_consistency(&self, plot: &mut sensoreval_utils::Plot, x: &[f64]) -> Result<(), Error> {
+        const CONFIDENCE: f64 = 0.95;
+        const MAX_LAG: usize = 100;
+
+        let mut add_bounded =
+            |rowname: &str, x: &[f64], y: &[f64], (lo, hi): (f64, f64)| -> Result<(), Error> {
+                let rowid = plot.ensure_row(rowname)?;
+
+                let mut t = sensoreval_utils::Plot::default_line();
+                t.x(x).y(y).name(rowname);
+                t.line().color(sensoreval_utils::COLOR_E);
+                plot.add_trace_to_rowid(&mut t, rowid)?;
+
+                for (name, v) in [("lower bound", lo), ("upper bound", hi)] {
+                    let mut t = sensoreval_utils::Plot::default_line();
+                    let y = vec![v; x.len()];
+                    t.x(x).y(&y).name(name);
+                    t.line().color(sensoreval_utils::COLOR_E_BAND);
+                    plot.add_trace_to_rowid(&mut t, rowid)?;
+                }
+
+                Ok(())
+            };
+
+        let nis = self.consistency.nis();
+        if nis.len() == x.len() {
+            add_bounded("nis", x, nis, self.consistency.nis_bounds(CONFIDENCE))?;
+        }
+
+        if self.gated.len() == x.len() && self.gated.iter().any(|g| *g) {
+            let y: Vec<f64> = self.gated.iter().map(|g| f64::from(u8::from(*g))).collect();
+            let mut t = sensoreval_utils::Plot::default_line();
+            t.x(x).y(&y).name("gated");
+            t.line().color(sensoreval_utils::COLOR_M);
+            plot.add_trace_to_rowname_ensure(&mut t, "gated")?;
+        }
+
+        let nees = self.consistency.nees();
+        if nees.len() == x.len() {
+            add_bounded("nees", x, nees, self.consistency.nees_bounds(CONFIDENCE))?;
+        }
+
+        // plotted over the lag time, starting at the first sample
+        if x.len() > 1 {
+            let max_lag = MAX_LAG.min(x.len() - 1);
+            let dt = (x[x.len() - 1] - x[0]) / (x.len() - 1) as f64;
+            let lags: Vec<f64> = (0..=max_lag).map(|lag| x[0] + lag as f64 * dt).collect();
+            let acf = self.consistency.autocorrelation(max_lag);
+            let bound = self.consistency.autocorrelation_bound(CONFIDENCE);
+
+            add_bounded(
+                "innovation-acf",
+                &lags,
+                acf.as_slice().unwrap(),
+                (-bound, bound),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// fails if the filter didn't run successfully over `dataset`
+    fn check_est(&self, dataset: &[Data]) -> Result<(), Error> {
+        if self.est.len() != dataset.len() {
+            return Err(Error::NoEstimate);
+        }
+
+        Ok(())
+    }
+
     fn est(&self, actual_ts: u64, dataset: &[Data], dataid: usize) -> ndarray::Array1<f64> {
         let sample = &dataset[dataid];
         let est_sampletime = &self.est[dataid];
 
         let est_now = if actual_ts > sample.time {
             let dt = (actual_ts - sample.time) as f64 / 1_000_000.0f64;
-            let fns = XFunctions::default();
-            let fxargs = FxArgs::new(dt);
+            let fns = XFunctions::new(&self.cfg);
+            let fxargs = FxArgs::new(&self.cfg, dt);
             Some(fns.fx(est_sampletime, &fxargs))
         } else {
             None
@@ -293,78 +1245,41 @@ impl render::HudRenderer for Pendulum {
     #[allow(non_snake_case)]
     fn data_changed(&mut self, ctx: &render::HudContext) {
         let samples = unwrap_opt_or!(ctx.get_dataset(), return);
-        let points_fn = kalman::sigma_points::MerweScaledSigmaPoints::new(
-            7,
-            0.1,
-            2.0,
-            -4.0,
-            XFunctions::default(),
-        );
-        let mut ukf = kalman::ukf::Ukf::new(
-            7,
-            6,
-            &points_fn,
-            XFunctions::default(),
-            FxArgs::new(0.1),
-            ZFunctions::default(),
-        );
-
-        ukf.x = ndarray::Array::from(self.cfg.initial.clone());
-        ukf.P = ndarray::Array::from_diag(&ndarray::Array::from(self.cfg.initial_cov.clone()));
-        ukf.R = ndarray::Array2::from_diag(&array![
-            self.cfg.stdev.accel.x.powi(2),
-            self.cfg.stdev.accel.y.powi(2),
-            self.cfg.stdev.accel.z.powi(2),
-            self.cfg.stdev.gyro.x.powi(2),
-            self.cfg.stdev.gyro.y.powi(2),
-            self.cfg.stdev.gyro.z.powi(2),
-        ]);
-
-        self.est.clear();
-
-        let mut t_prev = match samples.get(0) {
-            Some(v) => v.time,
-            None => 0,
-        };
-        let mut Ps = Vec::new();
-        let mut Qs = Vec::new();
-        let mut dts = Vec::new();
-        for sample in samples {
-            let z = ndarray::Array1::from(ZArgs {
-                accel_e: sample.accel[0],
-                accel_n: sample.accel[1],
-                accel_u: sample.accel[2],
-                gyro_e: sample.gyro[0],
-                gyro_n: sample.gyro[1],
-                gyro_u: sample.gyro[2],
-            });
-            let dt = (sample.time - t_prev) as f64 / 1_000_000.0f64;
-
-            ukf.set_dt(&dt);
-            ukf.predict().unwrap();
-            ukf.update(&z).unwrap();
-
-            self.est.push(ukf.x.clone());
 
-            if self.cfg.enable_rts_smoother {
-                Ps.push(ukf.P.clone());
-                Qs.push(ukf.Q.clone());
-                dts.push(dt);
+        let estimate = match Estimator::new(&self.cfg).run(samples) {
+            Ok(v) => v,
+            Err(e) => {
+                println!("can't run the filter: {e}");
+                // don't keep the estimate of the previous dataset
+                self.est.clear();
+                self.est_P.clear();
+                self.consistency = ConsistencyRecorder::default();
+                self.gated.clear();
+                self.mode_names.clear();
+                self.mode_probabilities.clear();
+                return;
             }
-
-            t_prev = sample.time;
-        }
-
-        if self.cfg.enable_rts_smoother {
-            let (xss, _) = ukf.rts_smoother(&self.est, &Ps, Some(&Qs), &dts).unwrap();
-            self.est = xss;
+        };
+        self.est = estimate.xs;
+        self.est_P = estimate.Ps;
+        self.consistency = estimate.consistency;
+        self.gated = estimate.gated;
+        self.mode_names = estimate.mode_names;
+        self.mode_probabilities = estimate.mode_probabilities;
+        println!("log-likelihood: {}", estimate.log_likelihood);
+
+        let ngated = self.gated.iter().filter(|g| **g).count();
+        if ngated > 0 {
+            println!("{ngated} of {} updates were gated", self.gated.len());
         }
 
         // stats
-        println!("x = {:.8} P = \n{:.8}", ukf.x, ukf.P);
-        let mut avg = ndarray::Array::zeros(ukf.x.dim());
-        let mut min = ndarray::Array::from_elem(ukf.x.dim(), std::f64::MAX);
-        let mut max = ndarray::Array::from_elem(ukf.x.dim(), std::f64::MIN);
+        if let (Some(x), Some(P)) = (self.est.last(), self.est_P.last()) {
+            println!("x = {x:.8} P = \n{P:.8}");
+        }
+        let mut avg = ndarray::Array::zeros(X::len());
+        let mut min = ndarray::Array::from_elem(X::len(), std::f64::MAX);
+        let mut max = ndarray::Array::from_elem(X::len(), std::f64::MIN);
         let mut max_ang = 0.0f64;
         let mut max_vel = 0.0f64;
         let mut max_acc = 0.0f64;
@@ -390,6 +1305,7 @@ impl render::HudRenderer for Pendulum {
     fn render(&self, ctx: &render::HudContext, cr: &cairo::Context) -> Result<(), Error> {
         let dataid = unwrap_opt_or!(ctx.current_data_id(), return Err(Error::SampleNotFound));
         let dataset = ctx.get_dataset().unwrap();
+        self.check_est(dataset)?;
         let est = self.est(ctx.actual_ts, dataset, dataid);
 
         let mut utilfont = self.font.utilfont();
@@ -474,6 +1390,7 @@ impl render::HudRenderer for Pendulum {
     ) -> Result<nalgebra::UnitQuaternion<f64>, Error> {
         let dataid = unwrap_opt_or!(ctx.current_data_id(), return Err(Error::SampleNotFound));
         let dataset = ctx.get_dataset().unwrap();
+        self.check_est(dataset)?;
         let est = self.est(ctx.actual_ts, dataset, dataid);
 
         let axis = nalgebra::Unit::new_normalize(nalgebra::Vector3::new(1.0, 0.0, 0.0));
@@ -489,8 +1406,9 @@ impl render::HudRenderer for Pendulum {
         plot: &mut sensoreval_utils::Plot,
     ) -> Result<(), Error> {
         let samples = ctx.get_dataset().ok_or(Error::NoDataSet)?;
+        self.check_est(samples)?;
         let x: Vec<f64> = samples.iter().map(|s| s.time_seconds()).collect();
-        let fns = XFunctions::default();
+        let fns = XFunctions::new(&self.cfg);
         let has_actual = match samples.first() {
             Some(sample) => sample.actual.is_some(),
             None => false,
@@ -543,7 +1461,7 @@ impl render::HudRenderer for Pendulum {
         }
 
         let xnames = ["p", "v", "r", "oo", "re", "rn", "ru"];
-        for i in 0..self.est[0].len() {
+        for i in 0..X::len() {
             let rowid = plot.ensure_row(
                 xnames
                     .get(i)
@@ -560,6 +1478,21 @@ impl render::HudRenderer for Pendulum {
                 .color(sensoreval_utils::COLOR_E);
             plot.add_trace_to_rowid(&mut t, rowid)?;
 
+            // 2 sigma confidence band
+            for (name, sign) in [("estimation +2sd", 1.0), ("estimation -2sd", -1.0)] {
+                let y: Vec<f64> = self
+                    .est
+                    .iter()
+                    .zip(&self.est_P)
+                    .map(|(x, cov)| x[i] + sign * 2.0 * cov[(i, i)].max(0.0).sqrt())
+                    .collect();
+                t.y(&y)
+                    .name(name)
+                    .line()
+                    .color(sensoreval_utils::COLOR_E_BAND);
+                plot.add_trace_to_rowid(&mut t, rowid)?;
+            }
+
             if has_actual {
                 let y: Vec<f64> = samples
                     .iter()
@@ -573,6 +1506,23 @@ impl render::HudRenderer for Pendulum {
             }
         }
 
+        if self.mode_probabilities.len() == x.len() {
+            let colors = [
+                sensoreval_utils::COLOR_E,
+                sensoreval_utils::COLOR_M,
+                sensoreval_utils::COLOR_A,
+            ];
+            for (i, name) in self.mode_names.iter().enumerate() {
+                let y: Vec<f64> = self.mode_probabilities.iter().map(|mu| mu[i]).collect();
+                let mut t = sensoreval_utils::Plot::default_line();
+                t.x(&x).y(&y).name(*name);
+                t.line().color(colors[i % colors.len()]);
+                plot.add_trace_to_rowname_ensure(&mut t, "mode-probability")?;
+            }
+        }
+
+        self.plot_consistency(plot, &x)?;
+
         Ok(())
     }
 
@@ -584,6 +1534,7 @@ impl render::HudRenderer for Pendulum {
     ) -> Result<(), Error> {
         const TIMESTEP: u64 = 15000;
         let dataset = ctx.get_dataset().unwrap();
+        self.check_est(dataset)?;
         let out_est = path.join("est.bin");
         let mut file = std::fs::File::create(&out_est)?;
 
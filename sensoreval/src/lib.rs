@@ -10,8 +10,12 @@ macro_rules! unwrap_opt_or {
 
 pub mod config;
 pub mod datareader;
+pub mod export;
+pub mod fit;
 mod hudrenderers;
 pub mod render;
+pub mod sweep;
+pub mod tune;
 
 mod data;
 pub use data::id_for_time;
@@ -20,5 +24,10 @@ pub use data::Data;
 mod error;
 pub use error::Error;
 
+#[cfg(test)]
+extern crate blas_src;
+#[cfg(test)]
+extern crate lapack_src;
+
 mod plotutils;
 pub use plotutils::PlotUtils;
@@ -4,6 +4,14 @@ use ndarray_linalg::Norm;
 
 pub trait PlotUtils {
     fn add_measurements(&mut self, samples: &[Data], x: &[f64]) -> Result<(), Error>;
+    /// plot the energy of the actual states in `samples`, if the model
+    /// supports it
+    fn add_energy<M: sensoreval_psim::ToEnergy>(
+        &mut self,
+        samples: &[Data],
+        x: &[f64],
+        model: &M,
+    ) -> Result<(), Error>;
 }
 
 impl<'a> PlotUtils for sensoreval_utils::Plot<'a> {
@@ -81,4 +89,41 @@ impl<'a> PlotUtils for sensoreval_utils::Plot<'a> {
 
         Ok(())
     }
+
+    fn add_energy<M: sensoreval_psim::ToEnergy>(
+        &mut self,
+        samples: &[Data],
+        x: &[f64],
+        model: &M,
+    ) -> Result<(), Error> {
+        let energy: Option<Vec<sensoreval_psim::Energy>> = samples
+            .iter()
+            .map(|s| s.actual.as_ref().and_then(|a| model.to_energy(a)))
+            .collect();
+        let energy = unwrap_opt_or!(energy, return Ok(()));
+
+        let traces: [(&str, &str, fn(&sensoreval_psim::Energy) -> f64); 3] = [
+            ("kinetic", sensoreval_utils::COLOR_M, |e| e.kinetic),
+            ("potential", sensoreval_utils::COLOR_E, |e| e.potential),
+            ("total", sensoreval_utils::COLOR_A, |e| e.total()),
+        ];
+        for (name, color, f) in traces {
+            let y: Vec<f64> = energy.iter().map(f).collect();
+
+            let mut t = Self::default_line();
+            t.x(x).y(&y).name(name);
+            t.line().color(color);
+            self.add_trace_to_rowname_ensure(&mut t, "energy")?;
+        }
+
+        let momentum: Option<Vec<f64>> = energy.iter().map(|e| e.momentum).collect();
+        if let Some(y) = momentum {
+            let mut t = Self::default_line();
+            t.x(x).y(&y).name("momentum");
+            t.line().color(sensoreval_utils::COLOR_A);
+            self.add_trace_to_rowname_ensure(&mut t, "momentum")?;
+        }
+
+        Ok(())
+    }
 }
@@ -0,0 +1,518 @@
+use crate::config;
+use crate::Data;
+use crate::Error;
+use rand::SeedableRng;
+use sensoreval_psim::ToImuSample;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Range {
+    pub start: f64,
+    /// inclusive
+    pub end: f64,
+    /// number of values, at least 2
+    pub steps: usize,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct List {
+    pub values: Vec<f64>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Uniform {
+    pub min: f64,
+    pub max: f64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Normal {
+    pub mean: f64,
+    pub stddev: f64,
+}
+
+/// values a parameter takes during the sweep
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum Distribution {
+    /// evenly spaced grid values
+    #[serde(rename = "range")]
+    Range(Range),
+    /// explicit grid values
+    #[serde(rename = "list")]
+    List(List),
+    /// drawn randomly for every run
+    #[serde(rename = "uniform")]
+    Uniform(Uniform),
+    /// drawn randomly for every run
+    #[serde(rename = "normal")]
+    Normal(Normal),
+}
+
+impl Distribution {
+    fn validate(&self) -> Result<(), Error> {
+        match self {
+            Self::Range(r) if r.steps < 2 => Err(Error::InvalidConfig(
+                "a range needs at least 2 steps".to_string(),
+            )),
+            Self::Uniform(u) if !u.min.is_finite() || !u.max.is_finite() || u.min > u.max => {
+                Err(Error::InvalidConfig(
+                    "a uniform distribution needs finite limits with min <= max".to_string(),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// returns `None` for random distributions
+    fn grid(&self) -> Option<Vec<f64>> {
+        match self {
+            Self::Range(r) => {
+                let n = r.steps;
+                Some(
+                    (0..n)
+                        .map(|i| r.start + (r.end - r.start) * i as f64 / (n - 1) as f64)
+                        .collect(),
+                )
+            }
+            Self::List(l) => Some(l.values.clone()),
+            Self::Uniform(_) | Self::Normal(_) => None,
+        }
+    }
+
+    fn sample<R: rand::Rng>(&self, rng: &mut R) -> Result<f64, Error> {
+        Ok(match self {
+            Self::Uniform(u) => rng.gen_range(u.min..=u.max),
+            Self::Normal(n) => rng.sample(rand_distr::Normal::new(n.mean, n.stddev)?),
+            Self::Range(_) | Self::List(_) => unreachable!(),
+        })
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Parameter {
+    /// dot-separated path inside of the simulator config, array elements
+    /// are selected by their index, e.g. `data.source.model.radius` or
+    /// `data.source.initial.0`
+    pub path: String,
+    pub distribution: Distribution,
+}
+
+fn default_runs() -> usize {
+    1
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SweepConfig {
+    /// simulator config, relative to the sweep config
+    pub config: String,
+    /// number of runs per grid point, only useful with random distributions
+    #[serde(default = "default_runs")]
+    pub runs: usize,
+    /// seed for the random distributions, a random one is used if not set
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub parameters: Vec<Parameter>,
+}
+
+impl SweepConfig {
+    fn validate(&self) -> Result<(), Error> {
+        for param in &self.parameters {
+            param.distribution.validate()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// summary of a single simulation
+#[derive(Clone, Copy, Debug)]
+pub struct Metrics {
+    /// largest magnitude of the specific force, unit: g
+    pub peak_g: f64,
+    /// unit: m/s
+    pub max_speed: f64,
+    /// mean time between rising zero crossings of the x rotation rate,
+    /// nan if there are less than two, unit: s
+    pub period: f64,
+    /// unit: m
+    pub max_height: f64,
+}
+
+impl Metrics {
+    pub const NAMES: [&'static str; 4] = ["peak_g", "max_speed", "period", "max_height"];
+
+    pub fn values(&self) -> [f64; 4] {
+        [self.peak_g, self.max_speed, self.period, self.max_height]
+    }
+
+    /// `samples` have to be noise-free, see [config::Config::load_data_sim].
+    /// The speed and the height are calculated from the actual states.
+    pub fn from_samples<M: ToImuSample>(model: &M, samples: &[Data]) -> Self {
+        let mut peak_g: f64 = 0.0;
+        let mut max_speed: f64 = 0.0;
+        let mut max_height = f64::NEG_INFINITY;
+        let mut crossings = Vec::new();
+        let mut last: Option<(f64, f64)> = None;
+
+        for sample in samples {
+            let actual = unwrap_opt_or!(&sample.actual, continue);
+            let t = sample.time_seconds();
+            let gyro = &sample.gyro;
+
+            peak_g = peak_g.max(sample.accel.dot(&sample.accel).sqrt() / math::GRAVITY);
+            max_speed = max_speed.max(model.to_speed(actual));
+            max_height = max_height.max(model.to_height(actual));
+
+            if let Some((tl, wl)) = last {
+                if wl < 0.0 && gyro[0] >= 0.0 {
+                    crossings.push(tl + (t - tl) * -wl / (gyro[0] - wl));
+                }
+            }
+            last = Some((t, gyro[0]));
+        }
+
+        let period = match (crossings.first(), crossings.last()) {
+            (Some(first), Some(last)) if crossings.len() >= 2 => {
+                (last - first) / (crossings.len() - 1) as f64
+            }
+            _ => f64::NAN,
+        };
+
+        Self {
+            peak_g,
+            max_speed,
+            period,
+            max_height,
+        }
+    }
+}
+
+/// result of a single simulation
+#[derive(Clone, Debug)]
+pub struct Run {
+    /// one value per [Parameter]
+    pub values: Vec<f64>,
+    pub metrics: Metrics,
+}
+
+pub struct Sweep {
+    pub cfg: SweepConfig,
+    base: toml::Value,
+    basedir: std::path::PathBuf,
+}
+
+impl Sweep {
+    pub fn load<P: AsRef<std::path::Path>>(filename: P) -> Result<Self, Error> {
+        let dir = filename
+            .as_ref()
+            .parent()
+            .expect("can't get parent dir of config");
+
+        let buffer = std::fs::read_to_string(filename.as_ref())?;
+        let cfg: SweepConfig = toml::from_str(&buffer)?;
+        cfg.validate()?;
+
+        let path = dir.join(&cfg.config);
+        let basedir = path
+            .parent()
+            .expect("can't get parent dir of config")
+            .to_path_buf();
+        let base = config::load_value(&path)?;
+
+        Ok(Self { cfg, base, basedir })
+    }
+
+    /// returns the parameter values of every run. Grid parameters form a
+    /// cartesian product and every grid point is repeated `runs` times.
+    pub fn values(&self) -> Result<Vec<Vec<f64>>, Error> {
+        let mut rng = match self.cfg.seed {
+            Some(seed) => rand::rngs::StdRng::seed_from_u64(seed),
+            None => rand::rngs::StdRng::from_entropy(),
+        };
+
+        let mut points: Vec<Vec<Option<f64>>> = vec![vec![]];
+        for param in &self.cfg.parameters {
+            points = match param.distribution.grid() {
+                Some(grid) => points
+                    .iter()
+                    .flat_map(|p| {
+                        grid.iter().map(move |v| {
+                            let mut p = p.clone();
+                            p.push(Some(*v));
+                            p
+                        })
+                    })
+                    .collect(),
+                None => points
+                    .into_iter()
+                    .map(|mut p| {
+                        p.push(None);
+                        p
+                    })
+                    .collect(),
+            };
+        }
+
+        let mut ret = Vec::with_capacity(points.len() * self.cfg.runs);
+        for point in &points {
+            for _ in 0..self.cfg.runs {
+                let values = point
+                    .iter()
+                    .zip(&self.cfg.parameters)
+                    .map(|(v, param)| match v {
+                        Some(v) => Ok(*v),
+                        None => param.distribution.sample(&mut rng),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                ret.push(values);
+            }
+        }
+
+        Ok(ret)
+    }
+
+    /// returns the simulator config with the parameters set to `values`
+    pub fn config(&self, values: &[f64]) -> Result<config::Config, Error> {
+        let mut value = self.base.clone();
+        for (param, v) in self.cfg.parameters.iter().zip(values) {
+            config::set_value(&mut value, &param.path, *v)?;
+        }
+
+        let cfg = config::from_value(value, &self.basedir)?;
+        if !matches!(cfg.data.source, config::DataSource::SimulatorData(_)) {
+            return Err(Error::NoSimulatorData);
+        }
+
+        Ok(cfg)
+    }
+
+    pub fn run(&self, values: &[f64]) -> Result<Run, Error> {
+        let cfg = self.config(values)?;
+        let sd = match &cfg.data.source {
+            config::DataSource::SimulatorData(sd) => sd,
+            _ => return Err(Error::NoSimulatorData),
+        };
+
+        // the accelerations depend on the control input, so they have to
+        // come from the model which was stepped
+        let samples = config::Config::load_data_sim(sd)?;
+        let model = sd.model.to_model_enum(sd.dt)?;
+
+        Ok(Run {
+            values: values.to_vec(),
+            metrics: Metrics::from_samples(&model, &samples),
+        })
+    }
+
+    /// runs all simulations using `nthreads` threads, the results are in
+    /// the same order as [values](Self::values)
+    pub fn run_all(&self, nthreads: usize) -> Result<Vec<Run>, Error> {
+        let values = self.values()?;
+        let nthreads = nthreads.max(1);
+
+        let mut results: Vec<(usize, Result<Run, Error>)> = std::thread::scope(|s| {
+            let handles: Vec<_> = (0..nthreads)
+                .map(|tid| {
+                    let values = &values;
+                    s.spawn(move || {
+                        values
+                            .iter()
+                            .enumerate()
+                            .skip(tid)
+                            .step_by(nthreads)
+                            .map(|(i, v)| (i, self.run(v)))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().expect("simulation thread panicked"))
+                .collect()
+        });
+
+        results.sort_by_key(|(i, _)| *i);
+        results.into_iter().map(|(_, r)| r).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn grid() {
+        let d = Distribution::Range(Range {
+            start: 1.0,
+            end: 2.0,
+            steps: 3,
+        });
+        assert_eq!(d.grid(), Some(vec![1.0, 1.5, 2.0]));
+    }
+
+    #[test]
+    fn validate() {
+        let cfg = |distribution: &str| -> SweepConfig {
+            toml::from_str(&format!(
+                r#"
+                config = "sim.toml"
+                [[parameters]]
+                path = "data.source.model.radius"
+                distribution = {distribution}
+                "#
+            ))
+            .unwrap()
+        };
+
+        assert!(
+            cfg(r#"{ type = "range", start = 1.0, end = 2.0, steps = 2 }"#)
+                .validate()
+                .is_ok()
+        );
+        assert!(
+            cfg(r#"{ type = "range", start = 1.0, end = 2.0, steps = 1 }"#)
+                .validate()
+                .is_err()
+        );
+        assert!(cfg(r#"{ type = "uniform", min = 1.0, max = 1.0 }"#)
+            .validate()
+            .is_ok());
+        assert!(cfg(r#"{ type = "uniform", min = 2.0, max = 1.0 }"#)
+            .validate()
+            .is_err());
+    }
+
+    fn pendulum_sweep() -> Sweep {
+        let cfg = toml::from_str(
+            r#"
+            config = "sim.toml"
+            runs = 2
+            seed = 1
+
+            [[parameters]]
+            path = "data.source.model.radius"
+            distribution = { type = "range", start = 1.0, end = 2.0, steps = 3 }
+
+            [[parameters]]
+            path = "data.source.initial.0"
+            distribution = { type = "uniform", min = 0.1, max = 0.5 }
+
+            [[parameters]]
+            path = "data.source.model.sensor_pos"
+            distribution = { type = "list", values = [0.0, 0.2] }
+            "#,
+        )
+        .unwrap();
+        let base = toml::from_str(
+            r#"
+            [data.source]
+            type = "simulator"
+            dt = 0.01
+            duration = 10.0
+            initial = [0.3, 0.0]
+
+            [data.source.model]
+            type = "pendulum"
+            radius = 1.0
+            "#,
+        )
+        .unwrap();
+
+        Sweep {
+            cfg,
+            base,
+            basedir: std::path::PathBuf::new(),
+        }
+    }
+
+    #[test]
+    fn values() {
+        let sweep = pendulum_sweep();
+        let values = sweep.values().unwrap();
+
+        // 3 radii x 2 sensor positions x 2 runs
+        assert_eq!(values.len(), 12);
+        let mut points = Vec::new();
+        for chunk in values.chunks(2) {
+            // runs of the same grid point only differ in the random value
+            assert_eq!((chunk[0][0], chunk[0][2]), (chunk[1][0], chunk[1][2]));
+            assert_ne!(chunk[0][1], chunk[1][1]);
+            points.push((chunk[0][0], chunk[0][2]));
+        }
+        assert_eq!(
+            points,
+            vec![
+                (1.0, 0.0),
+                (1.0, 0.2),
+                (1.5, 0.0),
+                (1.5, 0.2),
+                (2.0, 0.0),
+                (2.0, 0.2)
+            ]
+        );
+        assert!(values.iter().all(|v| (0.1..=0.5).contains(&v[1])));
+
+        // the seed makes it reproducible
+        assert_eq!(sweep.values().unwrap(), values);
+    }
+
+    #[test]
+    fn run_all() {
+        let sweep = pendulum_sweep();
+        let values = sweep.values().unwrap();
+        let runs = sweep.run_all(3).unwrap();
+
+        assert_eq!(runs.len(), values.len());
+        for (run, v) in runs.iter().zip(&values) {
+            assert_eq!(&run.values, v);
+        }
+
+        // a longer pendulum swings slower
+        assert!(runs[0].metrics.period < runs[runs.len() - 1].metrics.period);
+    }
+
+    /// a pendulum at rest, optionally driven by a pivot motor
+    fn motor_sweep(control_input: &str) -> Sweep {
+        let base = toml::from_str(&format!(
+            r#"
+            [data.source]
+            type = "simulator"
+            dt = 0.01
+            duration = 5.0
+            initial = [0.0, 0.0]
+            control_input = {control_input}
+
+            [data.source.model]
+            type = "pendulum"
+            radius = 1.0
+            motor = {{ type = "pivot", max_torque = 5.0 }}
+            "#
+        ))
+        .unwrap();
+
+        Sweep {
+            cfg: toml::from_str(r#"config = "sim.toml""#).unwrap(),
+            base,
+            basedir: std::path::PathBuf::new(),
+        }
+    }
+
+    #[test]
+    fn control_input() {
+        let free = motor_sweep("[]").run(&[]).unwrap().metrics;
+        let driven = motor_sweep("[[0.0, 1.0]]").run(&[]).unwrap().metrics;
+
+        // only gravity while resting
+        assert!((free.peak_g - 1.0).abs() < 1.0e-6, "{}", free.peak_g);
+        assert!(driven.peak_g > free.peak_g + 0.1, "{}", driven.peak_g);
+        assert!(driven.max_speed > 0.0);
+    }
+}
@@ -0,0 +1,312 @@
+use crate::config;
+use crate::hudrenderers;
+use crate::Data;
+use crate::Error;
+use serde::Deserialize;
+
+/// offset of the initial simplex vertices from the initial values, in log
+/// space. This scales every parameter by about 1.6.
+const INITIAL_STEP: f64 = 0.5;
+/// relative difference of the best and the worst cost at which the search
+/// stops
+const TOLERANCE: f64 = 1.0e-8;
+
+/// what gets optimized
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Objective {
+    /// maximize the total log-likelihood of the measurements, works with
+    /// recordings
+    #[default]
+    #[serde(rename = "likelihood")]
+    Likelihood,
+    /// minimize the RMSE of the estimates, needs simulated data
+    #[serde(rename = "rmse")]
+    Rmse,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TuneParameter {
+    /// path inside of the HUD renderer config, see [config::set_value], e.g.
+    /// `stdev.accel.x`, `initial_cov.1` or `process_noise.theta`. The value
+    /// has to be positive.
+    pub path: String,
+    /// taken from the config if not set
+    pub initial: Option<f64>,
+}
+
+fn default_max_iterations() -> usize {
+    200
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct TuneConfig {
+    /// config of the dataset and the estimator, relative to the tune config
+    pub data: String,
+    #[serde(default)]
+    pub objective: Objective,
+    #[serde(default = "default_max_iterations")]
+    pub max_iterations: usize,
+    pub parameters: Vec<TuneParameter>,
+}
+
+#[derive(Clone, Debug)]
+pub struct TuneResult {
+    /// one value per [TuneParameter]
+    pub values: ndarray::Array1<f64>,
+    /// negative log-likelihood or RMSE
+    pub cost: f64,
+    /// cost of the initial values
+    pub initial_cost: f64,
+    pub iterations: usize,
+}
+
+/// tunes the noise parameters of the HUD estimator using Nelder–Mead
+pub struct Tune {
+    pub cfg: TuneConfig,
+    samples: Vec<Data>,
+    template: toml::Value,
+    basedir: std::path::PathBuf,
+}
+
+impl Tune {
+    pub fn load<P: AsRef<std::path::Path>>(filename: P) -> Result<Self, Error> {
+        let dir = filename
+            .as_ref()
+            .parent()
+            .expect("can't get parent dir of config");
+
+        let buffer = std::fs::read_to_string(filename.as_ref())?;
+        let cfg: TuneConfig = toml::from_str(&buffer)?;
+        if cfg.parameters.is_empty() {
+            return Err(Error::InvalidConfig("no parameters to tune".to_string()));
+        }
+
+        let path = dir.join(&cfg.data);
+        let basedir = path
+            .parent()
+            .expect("can't get parent dir of config")
+            .to_path_buf();
+        let template = config::load_value(&path)?;
+
+        // loaded once, so the random noise of simulations is the same for
+        // all runs
+        let samples = config::from_value(template.clone(), &basedir)?.load_data()?;
+        if samples.is_empty() {
+            return Err(Error::NoDataSet);
+        }
+
+        Ok(Self {
+            cfg,
+            samples,
+            template,
+            basedir,
+        })
+    }
+
+    /// directory the relative paths of the template are relative to
+    pub fn basedir(&self) -> &std::path::Path {
+        &self.basedir
+    }
+
+    fn full_path(param: &TuneParameter) -> String {
+        format!("hud.renderer.{}", param.path)
+    }
+
+    pub fn initial(&self) -> Result<ndarray::Array1<f64>, Error> {
+        self.cfg
+            .parameters
+            .iter()
+            .map(|p| {
+                let v = p
+                    .initial
+                    .or_else(|| config::get_value(&self.template, &Self::full_path(p)))
+                    .ok_or_else(|| Error::InvalidConfigPath(p.path.clone()))?;
+                if v <= 0.0 {
+                    return Err(Error::InvalidConfig(format!(
+                        "{} has to be positive",
+                        p.path
+                    )));
+                }
+
+                Ok(v)
+            })
+            .collect()
+    }
+
+    /// returns the config with the parameters set to `values`
+    pub fn to_value(&self, values: &ndarray::Array1<f64>) -> Result<toml::Value, Error> {
+        let mut value = self.template.clone();
+        for (param, v) in self.cfg.parameters.iter().zip(values) {
+            config::set_value(&mut value, &Self::full_path(param), *v)?;
+        }
+
+        Ok(value)
+    }
+
+    /// only the `hud.renderer` block of [to_value](Self::to_value)
+    pub fn to_renderer_value(&self, values: &ndarray::Array1<f64>) -> Result<toml::Value, Error> {
+        let renderer = self
+            .to_value(values)?
+            .get("hud")
+            .and_then(|hud| hud.get("renderer"))
+            .cloned()
+            .ok_or(Error::NoHudRenderer)?;
+
+        let mut hud = toml::map::Map::new();
+        hud.insert("renderer".to_string(), renderer);
+        let mut root = toml::map::Map::new();
+        root.insert("hud".to_string(), toml::Value::Table(hud));
+
+        Ok(toml::Value::Table(root))
+    }
+
+    /// `log_values` are the logarithms of the parameters, so they stay
+    /// positive
+    fn cost(&self, log_values: &ndarray::Array1<f64>) -> Result<f64, Error> {
+        let values = log_values.mapv(f64::exp);
+        let mut cfg = config::from_value(self.to_value(&values)?, &self.basedir)?;
+        let pendulum = match &mut cfg.hud.renderer {
+            config::HudRenderer::Pendulum(v) => v,
+            _ => return Err(Error::NoHudRenderer),
+        };
+        // a checkpoint would override the tuned values, and every run would
+        // overwrite it
+        pendulum.checkpoint_load = None;
+        pendulum.checkpoint_save = None;
+
+        let score = match hudrenderers::pendulum::evaluate(pendulum, &self.samples) {
+            Ok(v) => v,
+            // e.g. a covariance that isn't positive definite anymore
+            Err(Error::Kalman(_)) => return Ok(f64::INFINITY),
+            Err(e) => return Err(e),
+        };
+
+        let cost = match self.cfg.objective {
+            Objective::Likelihood => -score.log_likelihood,
+            Objective::Rmse => score.rmse.ok_or(Error::NoSimulatorData)?,
+        };
+
+        Ok(if cost.is_nan() { f64::INFINITY } else { cost })
+    }
+
+    pub fn run(&self) -> Result<TuneResult, Error> {
+        let start = self.initial()?.mapv(f64::ln);
+        let mut result = nelder_mead(|x| self.cost(x), start, self.cfg.max_iterations)?;
+        result.values.mapv_inplace(f64::exp);
+
+        Ok(result)
+    }
+}
+
+/// minimizes `cost` using Nelder–Mead, starting with a simplex around `start`
+pub fn nelder_mead<F>(
+    cost: F,
+    start: ndarray::Array1<f64>,
+    max_iterations: usize,
+) -> Result<TuneResult, Error>
+where
+    F: Fn(&ndarray::Array1<f64>) -> Result<f64, Error>,
+{
+    let initial_cost = cost(&start)?;
+
+    let mut simplex = vec![(start.clone(), initial_cost)];
+    for i in 0..start.len() {
+        let mut x = start.clone();
+        x[i] += INITIAL_STEP;
+        let f = cost(&x)?;
+        simplex.push((x, f));
+    }
+
+    let mut iterations = 0;
+    while iterations < max_iterations {
+        simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        let best = simplex[0].1;
+        let worst = simplex[simplex.len() - 1].1;
+        if (worst - best).abs() <= TOLERANCE * (best.abs() + TOLERANCE) {
+            break;
+        }
+        iterations += 1;
+
+        let n = simplex.len() - 1;
+        let centroid = simplex[..n]
+            .iter()
+            .fold(ndarray::Array1::<f64>::zeros(start.len()), |acc, (x, _)| {
+                acc + x
+            })
+            / n as f64;
+        let (x_worst, f_worst) = simplex[n].clone();
+        let f_second_worst = simplex[n - 1].1;
+
+        let x_r = &centroid * 2.0 - &x_worst;
+        let f_r = cost(&x_r)?;
+
+        if f_r < best {
+            let x_e = &centroid * 3.0 - &x_worst * 2.0;
+            let f_e = cost(&x_e)?;
+            simplex[n] = if f_e < f_r { (x_e, f_e) } else { (x_r, f_r) };
+        } else if f_r < f_second_worst {
+            simplex[n] = (x_r, f_r);
+        } else {
+            // contract towards the better one of the reflected and the
+            // worst point
+            let (x_c, f_limit) = if f_r < f_worst {
+                ((&centroid + &x_r) * 0.5, f_r)
+            } else {
+                ((&centroid + &x_worst) * 0.5, f_worst)
+            };
+            let f_c = cost(&x_c)?;
+
+            if f_c < f_limit {
+                simplex[n] = (x_c, f_c);
+            } else {
+                // shrink towards the best point
+                let x_best = simplex[0].0.clone();
+                for vertex in simplex.iter_mut().skip(1) {
+                    let x = (&x_best + &vertex.0) * 0.5;
+                    let f = cost(&x)?;
+                    *vertex = (x, f);
+                }
+            }
+        }
+    }
+
+    simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+    let (values, cost) = simplex.swap_remove(0);
+
+    Ok(TuneResult {
+        values,
+        cost,
+        initial_cost,
+        iterations,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn rosenbrock() {
+        let rosenbrock = |x: &ndarray::Array1<f64>| -> Result<f64, Error> {
+            Ok((1.0 - x[0]).powi(2) + 100.0 * (x[1] - x[0].powi(2)).powi(2))
+        };
+
+        let result = nelder_mead(rosenbrock, array![-1.2, 1.0], 1000).unwrap();
+        assert!(result.iterations < 1000);
+        assert!(result.cost < 1.0e-6 && result.cost < result.initial_cost);
+        assert!(result.values.iter().all(|v| (v - 1.0).abs() < 1.0e-4));
+    }
+
+    #[test]
+    fn max_iterations() {
+        let sphere = |x: &ndarray::Array1<f64>| -> Result<f64, Error> { Ok(x.dot(x)) };
+
+        let result = nelder_mead(sphere, array![1.0, 2.0, 3.0], 5).unwrap();
+        assert_eq!(result.iterations, 5);
+        assert!(result.cost < result.initial_cost);
+    }
+}
@@ -10,3 +10,4 @@ pub mod booster_2d;
 pub mod double_pendulum_2d;
 pub mod pendulum_2d;
 pub mod pendulum_nessy;
+pub mod track_2d;
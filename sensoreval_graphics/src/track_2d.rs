@@ -0,0 +1,37 @@
+use crate::utils::CairoEx;
+
+/// draw the top view of a track with the car at position `car`, unit: m
+pub fn draw(cr: &cairo::Context, track: &[(f64, f64)], car: (f64, f64)) {
+    if track.is_empty() {
+        return;
+    }
+
+    let ssz = cr.surface_sz_user();
+    let margin = 40.0;
+
+    let (xmin, xmax, ymin, ymax) = track.iter().fold(
+        (f64::MAX, f64::MIN, f64::MAX, f64::MIN),
+        |(xmin, xmax, ymin, ymax), p| (xmin.min(p.0), xmax.max(p.0), ymin.min(p.1), ymax.max(p.1)),
+    );
+    let scale = ((ssz.0 - 2.0 * margin) / (xmax - xmin).max(1.0))
+        .min((ssz.1 - 2.0 * margin) / (ymax - ymin).max(1.0));
+
+    cr.save().unwrap();
+    cr.translate(ssz.0 / 2.0, ssz.1 / 2.0);
+    cr.scale(scale, -scale);
+    cr.translate(-(xmin + xmax) / 2.0, -(ymin + ymax) / 2.0);
+
+    // track
+    cr.set_line_width(5.0 / scale);
+    cr.move_to(track[0].0, track[0].1);
+    for p in &track[1..] {
+        cr.line_to(p.0, p.1);
+    }
+    cr.stroke().unwrap();
+
+    // car
+    cr.arc(car.0, car.1, 20.0 / scale, 0.0, 2.0 * std::f64::consts::PI);
+    cr.fill().unwrap();
+
+    cr.restore().unwrap();
+}
@@ -19,6 +19,7 @@ struct sensorevalgui_cfg
 
     void (*set_ts)(uint64_t, void *);
     void (*render)(cairo_t *, void *);
+    void (*key_pressed)(int, void *);
 
     void *pdata;
 };
@@ -7,6 +7,7 @@
 #include <QSocketNotifier>
 #include <qmlnative/qmlvideohud.h>
 #include <qmlnative/orientation.h>
+#include <qmlnative/keyhandler.h>
 #include <memory>
 #include <assert.h>
 
@@ -22,6 +23,7 @@ struct context
     QGuiApplication *app;
     QQmlApplicationEngine *engine;
     QTimer *timer;
+    KeyHandler *keyhandler;
     const struct sensorevalgui_cfg *cfg;
 };
 
@@ -67,6 +69,10 @@ extern "C" int sensorevalgui_native_create(struct context **pctx,
         url = QUrl();
     }
 
+    ctx->keyhandler = new KeyHandler(ctx->cfg);
+    assert(ctx->keyhandler);
+
+    ctx->engine->rootContext()->setContextProperty("main_keyHandler", ctx->keyhandler);
     ctx->engine->rootContext()->setContextProperty("main_orientationEnabled",
                                                    ctx->cfg->orientation_enabled);
     ctx->engine->rootContext()->setContextProperty("main_videoPath", url);
@@ -129,6 +135,9 @@ extern "C" void sensorevalgui_native_destroy(struct context *ctx)
     delete ctx->engine;
     ctx->engine = nullptr;
 
+    delete ctx->keyhandler;
+    ctx->keyhandler = nullptr;
+
     delete ctx->app;
     ctx->app = nullptr;
 
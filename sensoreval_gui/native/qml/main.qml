@@ -17,6 +17,8 @@ Window {
         focus: true
 
         Keys.onSpacePressed: {
+            main_keyHandler.keyPressed(event.key);
+
             if (player.playbackState == MediaPlayer.PausedState)
                 player.play();
             else if (player.playbackState == MediaPlayer.StoppedState) {
@@ -28,6 +30,8 @@ Window {
         }
 
         Keys.onPressed: {
+            main_keyHandler.keyPressed(event.key);
+
             if (event.key == Qt.Key_F) {
                 if (window.visibility == Window.FullScreen) {
                     window.visibility = Window.Maximized;
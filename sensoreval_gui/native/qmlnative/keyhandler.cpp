@@ -0,0 +1,13 @@
+#include "keyhandler.h"
+
+KeyHandler::KeyHandler(const struct sensorevalgui_cfg *cfg, QObject *parent)
+    : QObject(parent), m_cfg(cfg)
+{
+}
+
+void KeyHandler::keyPressed(int key)
+{
+    if (m_cfg->key_pressed) {
+        m_cfg->key_pressed(key, m_cfg->pdata);
+    }
+}
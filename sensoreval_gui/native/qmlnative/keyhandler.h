@@ -0,0 +1,23 @@
+#ifndef KEYHANDLER_H
+#define KEYHANDLER_H
+
+#include <QObject>
+
+extern "C" {
+#include <global.h>
+}
+
+class KeyHandler : public QObject
+{
+    Q_OBJECT
+
+public:
+    KeyHandler(const struct sensorevalgui_cfg *cfg, QObject *parent = 0);
+
+    Q_INVOKABLE void keyPressed(int key);
+
+private:
+    const struct sensorevalgui_cfg *m_cfg;
+};
+
+#endif // KEYHANDLER_H
@@ -38,9 +38,26 @@ impl<'a> RuntimeContext<'a> {
     }
 }
 
+/// Qt key codes passed to [Callback::key_pressed]. Letters and digits use
+/// their uppercase ASCII value.
+pub mod key {
+    pub const SPACE: i32 = 0x20;
+    pub const PLUS: i32 = 0x2b;
+    pub const MINUS: i32 = 0x2d;
+    pub const PERIOD: i32 = 0x2e;
+    pub const NUM_0: i32 = 0x30;
+    pub const R: i32 = 0x52;
+    pub const LEFT: i32 = 0x0100_0012;
+    pub const UP: i32 = 0x0100_0013;
+    pub const RIGHT: i32 = 0x0100_0014;
+    pub const DOWN: i32 = 0x0100_0015;
+}
+
 pub trait Callback {
     fn set_ts(&mut self, _ctx: &mut RuntimeContext, _ts: u64) {}
     fn render(&mut self, _ctx: &mut RuntimeContext, _cr: &cairo::Context) {}
+    /// `key` is one of the Qt key codes, see [key]
+    fn key_pressed(&mut self, _ctx: &mut RuntimeContext, _key: i32) {}
 }
 
 pub struct InnerContext<'a, 'b> {
@@ -67,6 +84,15 @@ unsafe extern "C" fn native_render(cr_ptr: *mut native::cairo_t, ctx: *mut ::std
     callback.render(&mut ctx.rtctx, &cr);
 }
 
+unsafe extern "C" fn native_key_pressed(
+    key: std::os::raw::c_int,
+    ctx: *mut ::std::os::raw::c_void,
+) {
+    let ctx = unwrap_opt_or!((ctx as *mut InnerContext).as_mut(), return);
+    let callback = unwrap_opt_or!(ctx.callback.as_mut(), return);
+    callback.key_pressed(&mut ctx.rtctx, key);
+}
+
 impl<'a, 'b> Default for Context<'a, 'b> {
     fn default() -> Self {
         Self {
@@ -79,6 +105,7 @@ impl<'a, 'b> Default for Context<'a, 'b> {
                     endoff: 0,
                     set_ts: Some(native_set_ts),
                     render: Some(native_render),
+                    key_pressed: Some(native_key_pressed),
                     pdata: std::ptr::null_mut(),
                 },
                 videopath: None,
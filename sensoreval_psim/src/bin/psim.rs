@@ -6,6 +6,12 @@ use clap::Parser as _;
 struct SimConfig {
     state: Vec<f64>,
     params: sensoreval_psim::models::Params,
+    #[serde(default)]
+    integrator: sensoreval_psim::integrator::Integrator,
+    #[serde(default)]
+    control_input: Vec<Vec<f64>>,
+    #[serde(default)]
+    state_updates: Vec<Vec<f64>>,
 }
 
 #[derive(serde::Deserialize)]
@@ -27,8 +33,24 @@ struct Cli {
 fn main() {
     let cli = Cli::parse();
     let cfgstr = std::fs::read_to_string(&cli.config).unwrap();
-    let cfg: Config = toml::from_str(&cfgstr).unwrap();
+    let mut cfg: Config = toml::from_str(&cfgstr).unwrap();
+    cfg.sim.integrator.validate().unwrap();
+    cfg.sim.params.validate().unwrap();
+    if let Some(cfgdir) = cli.config.parent() {
+        cfg.sim.params.set_basedir(cfgdir);
+    }
     let state = ndarray::Array::from(cfg.sim.state);
 
-    sensoreval_psim::run::run_sim(cli.dt, &cfg.sim.params, state);
+    if let Err(e) = sensoreval_psim::run::run_sim(
+        cli.dt,
+        cfg.sim.integrator,
+        &cfg.sim.params,
+        state,
+        0.0,
+        cfg.sim.control_input,
+        cfg.sim.state_updates,
+    ) {
+        println!("can't run the simulation: {e}");
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,34 @@
+fn default_mag_field() -> [f64; 3] {
+    [0.0, 20.0, -44.0]
+}
+
+fn default_temperature() -> f64 {
+    20.0
+}
+
+/// surroundings of the ride, used for synthesizing magnetometer and
+/// barometer data
+#[derive(Clone, serde::Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Environment {
+    /// earth magnetic field in world coordinates (east, north, up). The
+    /// default is roughly what you get in central europe. unit: uT
+    #[serde(default = "default_mag_field")]
+    pub mag_field: [f64; 3],
+    /// altitude of the ride's lowest point above sea level, unit: m
+    #[serde(default)]
+    pub altitude: f64,
+    /// unit: degrees celsius
+    #[serde(default = "default_temperature")]
+    pub temperature: f64,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self {
+            mag_field: default_mag_field(),
+            altitude: 0.0,
+            temperature: default_temperature(),
+        }
+    }
+}
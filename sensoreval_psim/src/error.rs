@@ -0,0 +1,18 @@
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    ParseFloat(#[from] std::num::ParseFloatError),
+
+    #[error("the integration diverged")]
+    IntegrationDiverged,
+    #[error("the integrator tolerances have to be positive")]
+    InvalidTolerance,
+    #[error("invalid model parameter: {0}")]
+    InvalidParameter(&'static str),
+    #[error("invalid track point in line {0}")]
+    InvalidTrackPoint(usize),
+    #[error("not enough track points")]
+    NotEnoughTrackPoints,
+}
@@ -0,0 +1,394 @@
+use crate::Error;
+
+/// numerical integration method used to advance a model by one `dt`
+#[derive(Clone, Copy, serde::Deserialize, Debug, Default, PartialEq)]
+#[serde(tag = "type")]
+pub enum Integrator {
+    #[serde(rename = "euler")]
+    Euler,
+    #[default]
+    #[serde(rename = "rk4")]
+    Rk4,
+    /// semi-implicit euler: velocities get updated first and the positions
+    /// use the new velocities
+    #[serde(rename = "symplectic_euler")]
+    SymplecticEuler,
+    /// adaptive Dormand–Prince 5(4), the output is still sampled at `dt`
+    #[serde(rename = "dopri5")]
+    DormandPrince(DormandPrince),
+}
+
+impl Integrator {
+    /// checks the parameters, call it after loading a config
+    pub fn validate(&self) -> Result<(), Error> {
+        if let Self::DormandPrince(cfg) = self {
+            // also rejects NaN
+            if !(cfg.rtol > 0.0 && cfg.atol > 0.0) {
+                return Err(Error::InvalidTolerance);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn default_rtol() -> f64 {
+    1.0e-6
+}
+
+fn default_atol() -> f64 {
+    1.0e-9
+}
+
+#[derive(Clone, Copy, serde::Deserialize, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct DormandPrince {
+    /// relative error tolerance per step
+    #[serde(default = "default_rtol")]
+    pub rtol: f64,
+    /// absolute error tolerance per step
+    #[serde(default = "default_atol")]
+    pub atol: f64,
+}
+
+/// information the symplectic euler method needs about the state layout
+pub trait Kinematic {
+    /// indices of all states whose derivative is another state, e.g.
+    /// positions and angles
+    fn positions(&self) -> &[usize];
+}
+
+/// Dormand–Prince coefficients. The models are autonomous so the nodes
+/// aren't needed.
+const DP_A: [[f64; 6]; 7] = [
+    [0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [1.0 / 5.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [3.0 / 40.0, 9.0 / 40.0, 0.0, 0.0, 0.0, 0.0],
+    [44.0 / 45.0, -56.0 / 15.0, 32.0 / 9.0, 0.0, 0.0, 0.0],
+    [
+        19372.0 / 6561.0,
+        -25360.0 / 2187.0,
+        64448.0 / 6561.0,
+        -212.0 / 729.0,
+        0.0,
+        0.0,
+    ],
+    [
+        9017.0 / 3168.0,
+        -355.0 / 33.0,
+        46732.0 / 5247.0,
+        49.0 / 176.0,
+        -5103.0 / 18656.0,
+        0.0,
+    ],
+    [
+        35.0 / 384.0,
+        0.0,
+        500.0 / 1113.0,
+        125.0 / 192.0,
+        -2187.0 / 6784.0,
+        11.0 / 84.0,
+    ],
+];
+/// difference between the 5th and 4th order weights
+const DP_E: [f64; 7] = [
+    71.0 / 57600.0,
+    0.0,
+    -71.0 / 16695.0,
+    71.0 / 1920.0,
+    -17253.0 / 339200.0,
+    22.0 / 525.0,
+    -1.0 / 40.0,
+];
+
+/// replacement for the `eom` schemes which supports all [Integrator]s
+#[derive(Clone)]
+pub struct Solver<F> {
+    core: F,
+    dt: f64,
+    integrator: Integrator,
+    /// last step size chosen by adaptive integrators
+    h: f64,
+}
+
+impl<F> Solver<F>
+where
+    F: eom::traits::Explicit<Scalar = f64, Dim = ndarray::Ix1> + Kinematic,
+{
+    pub fn new(core: F, dt: f64) -> Self {
+        Self {
+            core,
+            dt,
+            integrator: Integrator::default(),
+            h: dt,
+        }
+    }
+
+    pub fn core(&self) -> &F {
+        &self.core
+    }
+
+    pub fn core_mut(&mut self) -> &mut F {
+        &mut self.core
+    }
+
+    pub fn get_dt(&self) -> f64 {
+        self.dt
+    }
+
+    pub fn set_dt(&mut self, dt: f64) {
+        self.dt = dt;
+        self.h = dt;
+    }
+
+    pub fn integrator(&self) -> Integrator {
+        self.integrator
+    }
+
+    pub fn set_integrator(&mut self, integrator: Integrator) {
+        self.integrator = integrator;
+        self.h = self.dt;
+    }
+
+    fn f(&mut self, x: &ndarray::Array1<f64>) -> ndarray::Array1<f64> {
+        let mut k = x.clone();
+        self.core.rhs(&mut k);
+        k
+    }
+
+    /// advance `x` by `dt`
+    pub fn iterate<'a, S>(
+        &mut self,
+        x: &'a mut ndarray::ArrayBase<S, ndarray::Ix1>,
+    ) -> Result<&'a mut ndarray::ArrayBase<S, ndarray::Ix1>, Error>
+    where
+        S: ndarray::DataMut<Elem = f64>,
+    {
+        let x0 = x.to_owned();
+        let dt = self.dt;
+
+        let x1 = match self.integrator {
+            Integrator::Euler => &x0 + &(self.f(&x0) * dt),
+            Integrator::Rk4 => self.rk4(&x0, dt),
+            Integrator::SymplecticEuler => self.symplectic_euler(&x0, dt),
+            Integrator::DormandPrince(cfg) => self.dopri5(&x0, dt, &cfg)?,
+        };
+
+        x.assign(&x1);
+        Ok(x)
+    }
+
+    fn rk4(&mut self, x: &ndarray::Array1<f64>, dt: f64) -> ndarray::Array1<f64> {
+        let k1 = self.f(x);
+        let k2 = self.f(&(x + &(&k1 * (dt / 2.0))));
+        let k3 = self.f(&(x + &(&k2 * (dt / 2.0))));
+        let k4 = self.f(&(x + &(&k3 * dt)));
+
+        x + &((k1 + k2 * 2.0 + k3 * 2.0 + k4) * (dt / 6.0))
+    }
+
+    fn symplectic_euler(&mut self, x: &ndarray::Array1<f64>, dt: f64) -> ndarray::Array1<f64> {
+        // update everything explicitly first, only the velocities are kept
+        let mut x1 = x + &(self.f(x) * dt);
+
+        // positions get integrated using the new velocities
+        let k = self.f(&x1);
+        for &i in self.core.positions() {
+            x1[i] = x[i] + k[i] * dt;
+        }
+
+        x1
+    }
+
+    fn dopri5(
+        &mut self,
+        x: &ndarray::Array1<f64>,
+        dt: f64,
+        cfg: &DormandPrince,
+    ) -> Result<ndarray::Array1<f64>, Error> {
+        let mut x = x.clone();
+        let mut t = 0.0;
+
+        while t < dt {
+            let last = self.h >= dt - t;
+            let h = if last { dt - t } else { self.h };
+
+            let mut k: Vec<ndarray::Array1<f64>> = Vec::with_capacity(DP_A.len());
+            for a in DP_A.iter() {
+                let mut xi = x.clone();
+                for (kj, aj) in k.iter().zip(a.iter()) {
+                    xi.scaled_add(h * aj, kj);
+                }
+                k.push(self.f(&xi));
+            }
+
+            // the last stage is evaluated at the 5th order solution
+            let mut x1 = x.clone();
+            for (kj, aj) in k.iter().zip(DP_A[DP_A.len() - 1].iter()) {
+                x1.scaled_add(h * aj, kj);
+            }
+
+            let mut errvec = ndarray::Array1::<f64>::zeros(x.len());
+            for (kj, ej) in k.iter().zip(DP_E.iter()) {
+                errvec.scaled_add(h * ej, kj);
+            }
+
+            let mut err = 0.0;
+            ndarray::azip!((&e in &errvec, &a in &x, &b in &x1) {
+                let scale = cfg.atol + cfg.rtol * a.abs().max(b.abs());
+                err += (e / scale).powi(2);
+            });
+            let err = (err / x.len() as f64).sqrt();
+            // the step size can't recover from a NaN
+            if !err.is_finite() {
+                return Err(Error::IntegrationDiverged);
+            }
+
+            let factor = if err > 0.0 {
+                (0.9 * err.powf(-0.2)).clamp(0.2, 5.0)
+            } else {
+                5.0
+            };
+
+            // accept tiny steps anyway so we can't get stuck
+            if err <= 1.0 || h <= dt * 1.0e-9 {
+                t = if last { dt } else { t + h };
+                x = x1;
+
+                // don't let the final, shortened step shrink the next one
+                if !last || factor < 1.0 {
+                    self.h = h * factor;
+                }
+            } else {
+                self.h = h * factor;
+            }
+        }
+
+        Ok(x)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// harmonic oscillator with unit frequency, the state is the position
+    /// and the velocity
+    #[derive(Clone)]
+    struct Oscillator;
+
+    impl eom::traits::ModelSpec for Oscillator {
+        type Scalar = f64;
+        type Dim = ndarray::Ix1;
+
+        fn model_size(&self) -> usize {
+            2
+        }
+    }
+
+    impl Kinematic for Oscillator {
+        fn positions(&self) -> &[usize] {
+            &[0]
+        }
+    }
+
+    impl eom::traits::Explicit for Oscillator {
+        fn rhs<'a, S>(
+            &mut self,
+            v: &'a mut ndarray::ArrayBase<S, ndarray::Ix1>,
+        ) -> &'a mut ndarray::ArrayBase<S, ndarray::Ix1>
+        where
+            S: ndarray::DataMut<Elem = f64>,
+        {
+            let x = v[0];
+            v[0] = v[1];
+            v[1] = -x;
+            v
+        }
+    }
+
+    /// largest error against the analytic solution and largest change of
+    /// the energy
+    fn run(integrator: Integrator, dt: f64, duration: f64) -> (f64, f64) {
+        let mut solver = Solver::new(Oscillator, dt);
+        solver.set_integrator(integrator);
+
+        let mut x = ndarray::array![1.0, 0.0];
+        let mut max_error = 0.0f64;
+        let mut max_energy_error = 0.0f64;
+        for i in 1..=(duration / dt).round() as usize {
+            solver.iterate(&mut x).unwrap();
+
+            let t = i as f64 * dt;
+            max_error = max_error
+                .max((x[0] - t.cos()).abs())
+                .max((x[1] + t.sin()).abs());
+            max_energy_error = max_energy_error.max((0.5 * x.dot(&x) - 0.5).abs());
+        }
+
+        (max_error, max_energy_error)
+    }
+
+    #[test]
+    fn fixed_step() {
+        let dt = 0.01;
+        let (euler, _) = run(Integrator::Euler, dt, 10.0);
+        let (rk4, _) = run(Integrator::Rk4, dt, 10.0);
+        let (symplectic, _) = run(Integrator::SymplecticEuler, dt, 10.0);
+
+        assert!(rk4 < 1.0e-8, "{rk4}");
+        assert!(symplectic < 1.0e-2, "{symplectic}");
+        assert!(euler < 1.0e-1, "{euler}");
+        assert!(rk4 < symplectic && symplectic < euler);
+    }
+
+    #[test]
+    fn symplectic_energy() {
+        let dt = 0.01;
+
+        // the energy oscillates but doesn't drift
+        let (_, symplectic) = run(Integrator::SymplecticEuler, dt, 100.0);
+        assert!(symplectic < dt, "{symplectic}");
+
+        // while euler keeps adding energy
+        let (_, euler) = run(Integrator::Euler, dt, 100.0);
+        assert!(euler > 0.5, "{euler}");
+    }
+
+    #[test]
+    fn dopri5() {
+        // large output steps, so the step size control has to split them
+        let mut prev = f64::INFINITY;
+        for rtol in [1.0e-4, 1.0e-6, 1.0e-8] {
+            let cfg = DormandPrince {
+                rtol,
+                atol: rtol * 1.0e-3,
+            };
+            let (error, _) = run(Integrator::DormandPrince(cfg), 0.5, 20.0);
+
+            assert!(error < 10.0 * rtol, "{error} for rtol={rtol}");
+            assert!(error < prev);
+            prev = error;
+        }
+    }
+
+    #[test]
+    fn validate() {
+        let dopri5 = |rtol, atol| Integrator::DormandPrince(DormandPrince { rtol, atol });
+
+        assert!(Integrator::Euler.validate().is_ok());
+        assert!(dopri5(default_rtol(), default_atol()).validate().is_ok());
+        for (rtol, atol) in [
+            (0.0, 1.0e-9),
+            (-1.0e-6, 1.0e-9),
+            (f64::NAN, 1.0e-9),
+            (1.0e-6, 0.0),
+            (1.0e-6, f64::NAN),
+        ] {
+            assert!(matches!(
+                dopri5(rtol, atol).validate(),
+                Err(Error::InvalidTolerance)
+            ));
+        }
+    }
+}
@@ -1,6 +1,6 @@
 #[enum_dispatch::enum_dispatch]
 pub trait Model {
-    fn step<S>(&mut self, x: &mut ndarray::ArrayBase<S, ndarray::Ix1>)
+    fn step<S>(&mut self, x: &mut ndarray::ArrayBase<S, ndarray::Ix1>) -> Result<(), Error>
     where
         S: ndarray::DataMut<Elem = f64>;
     fn normalize<S>(&self, _x: &mut ndarray::ArrayBase<S, ndarray::Ix1>)
@@ -10,6 +10,7 @@ pub trait Model {
     }
     fn set_dt(&mut self, dt: f64);
     fn dt(&self) -> f64;
+    fn set_integrator(&mut self, integrator: integrator::Integrator);
 
     fn set_control_input(&mut self, _ci: Option<&[f64]>) {}
 }
@@ -39,6 +40,80 @@ pub trait ToImuSample {
     {
         0.0
     }
+
+    /// returns the speed of the sensor in m/s
+    fn to_speed<S>(&self, _state: &ndarray::ArrayBase<S, ndarray::Ix1>) -> f64
+    where
+        S: ndarray::Data<Elem = f64>,
+    {
+        0.0
+    }
+
+    /// returns the rotation from sensor to world (east, north, up)
+    /// coordinates
+    fn to_orientation<S>(
+        &self,
+        _state: &ndarray::ArrayBase<S, ndarray::Ix1>,
+    ) -> nalgebra::UnitQuaternion<f64>
+    where
+        S: ndarray::Data<Elem = f64>,
+    {
+        nalgebra::UnitQuaternion::identity()
+    }
+
+    /// unit: same as `env.mag_field`
+    fn to_mag<Sa, Sb>(
+        &self,
+        state: &ndarray::ArrayBase<Sa, ndarray::Ix1>,
+        env: &environment::Environment,
+        mag: &mut ndarray::ArrayBase<Sb, ndarray::Ix1>,
+    ) where
+        Sa: ndarray::Data<Elem = f64>,
+        Sb: ndarray::DataMut<Elem = f64>,
+    {
+        let field = nalgebra::Vector3::from(env.mag_field);
+        let m = self.to_orientation(state).inverse_transform_vector(&field);
+        mag.assign(&ndarray::array![m[0], m[1], m[2]]);
+    }
+
+    /// returns the barometer pressure in hPa
+    fn to_pressure<S>(
+        &self,
+        state: &ndarray::ArrayBase<S, ndarray::Ix1>,
+        env: &environment::Environment,
+    ) -> f64
+    where
+        S: ndarray::Data<Elem = f64>,
+    {
+        math::altitude2pressure(env.altitude + self.to_height(state))
+    }
+}
+
+/// energy of a model, unit: J or J/kg for models which don't know their mass
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Energy {
+    pub kinetic: f64,
+    /// relative to the lowest possible position
+    pub potential: f64,
+    /// angular momentum around the pivot, unit: kg*m^2/s or m^2/s
+    pub momentum: Option<f64>,
+}
+
+impl Energy {
+    pub fn total(&self) -> f64 {
+        self.kinetic + self.potential
+    }
+}
+
+#[enum_dispatch::enum_dispatch]
+pub trait ToEnergy {
+    /// returns `None` if the model doesn't support energy diagnostics
+    fn to_energy<S>(&self, _state: &ndarray::ArrayBase<S, ndarray::Ix1>) -> Option<Energy>
+    where
+        S: ndarray::Data<Elem = f64>,
+    {
+        None
+    }
 }
 
 #[enum_dispatch::enum_dispatch]
@@ -50,11 +125,15 @@ pub trait DrawState {
 
 macro_rules! impl_model_inner {
     ($field:ident) => {
-        fn step<S>(&mut self, x: &mut ndarray::ArrayBase<S, ndarray::Ix1>)
+        fn step<S>(
+            &mut self,
+            x: &mut ndarray::ArrayBase<S, ndarray::Ix1>,
+        ) -> Result<(), crate::Error>
         where
             S: ndarray::DataMut<Elem = f64>,
         {
-            self.$field.iterate(x);
+            self.$field.iterate(x)?;
+            Ok(())
         }
 
         fn set_dt(&mut self, dt: f64) {
@@ -64,6 +143,10 @@ macro_rules! impl_model_inner {
         fn dt(&self) -> f64 {
             self.$field.get_dt()
         }
+
+        fn set_integrator(&mut self, integrator: crate::integrator::Integrator) {
+            self.$field.set_integrator(integrator);
+        }
     };
 }
 
@@ -75,6 +158,16 @@ macro_rules! impl_model {
     };
 }
 
+mod error;
+pub use error::Error;
+
+pub mod environment;
+
+pub mod integrator;
 pub mod models;
 pub mod run;
+pub mod spline;
 pub mod utils;
+
+#[cfg(test)]
+extern crate lapack_src;
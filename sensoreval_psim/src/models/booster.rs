@@ -1,8 +1,6 @@
-use eom::traits::Scheme;
-use eom::traits::TimeEvolution;
-use eom::traits::TimeStep;
 use sensoreval_utils::AssignState;
 use sensoreval_utils::StateUtils;
+use std::convert::TryInto;
 
 #[derive(sensoreval_utils::macros::State)]
 pub enum State {
@@ -42,6 +40,48 @@ impl std::str::FromStr for Object {
     }
 }
 
+fn default_response_time() -> f64 {
+    0.1
+}
+
+#[derive(Clone, serde::Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Ramp {
+    /// unit: rad/s^2
+    pub max_accel: f64,
+    /// time constant of the acceleration, unit: s
+    #[serde(default = "default_response_time")]
+    pub response_time: f64,
+}
+
+#[derive(Clone, serde::Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Motor {
+    /// unit: N*m
+    pub max_torque: f64,
+    /// moment of inertia of the arm around the booster axis, unit: kg*m^2
+    pub inertia: f64,
+    /// time constant of the motor torque, unit: s
+    #[serde(default = "default_response_time")]
+    pub response_time: f64,
+}
+
+/// drives the arm towards the target speed which is given by the control
+/// input in rad/s
+#[derive(Clone, serde::Deserialize, Debug)]
+#[serde(tag = "type")]
+pub enum Drive {
+    /// accelerates with at most `max_accel`, independent of the load. The
+    /// speed stays constant without a target.
+    #[serde(rename = "ramp")]
+    Ramp(Ramp),
+    /// speed-controlled motor with a torque limit. The gondola pushes back
+    /// on the arm, so the speed varies over a revolution. The motor is off
+    /// without a target.
+    #[serde(rename = "motor")]
+    Motor(Motor),
+}
+
 #[derive(Clone, serde::Deserialize, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct Params {
@@ -55,6 +95,9 @@ pub struct Params {
     pub rs: f64,
     /// friction between booster and gondola
     pub friction: Option<f64>,
+    /// the arm acceleration stays constant if not set
+    #[serde(default)]
+    pub drive: Option<Drive>,
 }
 
 impl Params {
@@ -107,9 +150,89 @@ impl Params {
 
         theta0dd
     }
+
+    /// torque the gondola applies to the arm around the booster axis,
+    /// unit: N*m
+    pub fn reaction_torque<S>(&self, state: &ndarray::ArrayBase<S, ndarray::Ix1>) -> f64
+    where
+        S: ndarray::Data<Elem = f64>,
+    {
+        let thetab = state[State::ThetaB];
+        let thetabd = state[State::ThetaBD];
+        let thetabdd = state[State::ThetaBDD];
+        let theta0 = state[State::Theta0];
+        let theta0d = state[State::Theta0D];
+        let theta0dd = self.theta0dd(state);
+
+        // pivot position and acceleration in (y, z)
+        let (py, pz) = (self.rb * thetab.sin(), -self.rb * thetab.cos());
+        let apy = self.rb * (thetabdd * thetab.cos() - thetabd.powi(2) * thetab.sin());
+        let apz = self.rb * (thetabdd * thetab.sin() + thetabd.powi(2) * thetab.cos());
+
+        // the pivot force is what's left of gravity after accelerating the
+        // masses
+        let (mut fy, mut fz) = (0.0, 0.0);
+        let mut inertia = 0.0;
+        for o in &self.objects {
+            let t = theta0 + o.t;
+            let ay = apy + o.r * (theta0dd * t.cos() - theta0d.powi(2) * t.sin());
+            let az = apz + o.r * (theta0dd * t.sin() + theta0d.powi(2) * t.cos());
+
+            fy -= o.m * ay;
+            fz -= o.m * (az + math::GRAVITY);
+            inertia += o.m * o.r.powi(2);
+        }
+
+        let mut torque = py * fz - pz * fy;
+        if let Some(friction) = self.friction {
+            torque += inertia * friction * (theta0d - thetabd);
+        }
+
+        torque
+    }
+}
+
+#[derive(Clone, Debug)]
+struct ParamsInternal {
+    params: Params,
+    ci: Option<[f64; 1]>,
+}
+
+impl ParamsInternal {
+    /// derivative of the arm acceleration
+    fn thetabddd<S>(&self, state: &ndarray::ArrayBase<S, ndarray::Ix1>) -> f64
+    where
+        S: ndarray::Data<Elem = f64>,
+    {
+        let thetabd = state[State::ThetaBD];
+        let thetabdd = state[State::ThetaBDD];
+        let target = self.ci.map(|ci| ci[0]);
+
+        // the speed controllers are 4 times slower than the acceleration
+        // lag, which makes the speed approach the target critically damped
+        let (accel, response_time) = match &self.params.drive {
+            None => return 0.0,
+            Some(Drive::Ramp(r)) => {
+                let accel = target.map_or(0.0, |target| {
+                    ((target - thetabd) / (4.0 * r.response_time)).clamp(-r.max_accel, r.max_accel)
+                });
+                (accel, r.response_time)
+            }
+            Some(Drive::Motor(m)) => {
+                let torque = target.map_or(0.0, |target| {
+                    (m.inertia * (target - thetabd) / (4.0 * m.response_time))
+                        .clamp(-m.max_torque, m.max_torque)
+                });
+                let torque = torque + self.params.reaction_torque(state);
+                (torque / m.inertia, m.response_time)
+            }
+        };
+
+        (accel - thetabdd) / response_time
+    }
 }
 
-impl eom::traits::ModelSpec for Params {
+impl eom::traits::ModelSpec for ParamsInternal {
     type Scalar = f64;
     type Dim = ndarray::Ix1;
 
@@ -118,7 +241,17 @@ impl eom::traits::ModelSpec for Params {
     }
 }
 
-impl eom::traits::Explicit for Params {
+impl crate::integrator::Kinematic for ParamsInternal {
+    fn positions(&self) -> &[usize] {
+        &[
+            State::ThetaB as usize,
+            State::ThetaBD as usize,
+            State::Theta0 as usize,
+        ]
+    }
+}
+
+impl eom::traits::Explicit for ParamsInternal {
     fn rhs<'a, S>(
         &mut self,
         v: &'a mut ndarray::ArrayBase<S, ndarray::Ix1>,
@@ -129,12 +262,13 @@ impl eom::traits::Explicit for Params {
         let thetabd = v[State::ThetaBD];
         let thetabdd = v[State::ThetaBDD];
         let theta0d = v[State::Theta0D];
-        let theta0dd = self.theta0dd(v);
+        let theta0dd = self.params.theta0dd(v);
+        let thetabddd = self.thetabddd(v);
 
         v.assign_state(StateArgs {
             theta_b: thetabd,
             theta_bd: thetabdd,
-            theta_bdd: 0.0,
+            theta_bdd: thetabddd,
             theta_0: theta0d,
             theta_0_d: theta0dd,
         });
@@ -145,23 +279,23 @@ impl eom::traits::Explicit for Params {
 
 #[derive(Clone)]
 pub struct Booster {
-    eom: eom::explicit::RK4<Params>,
+    eom: crate::integrator::Solver<ParamsInternal>,
 }
 
 impl Booster {
     pub fn new(params: Params, dt: f64) -> Self {
         Self {
-            eom: eom::explicit::RK4::new(params, dt),
+            eom: crate::integrator::Solver::new(ParamsInternal { params, ci: None }, dt),
         }
     }
 
     pub fn params(&self) -> &Params {
-        self.eom.core()
+        &self.eom.core().params
     }
 
     /// returns the center of mass relative to theta0
     pub fn thetac(&self) -> f64 {
-        let params = self.eom.core();
+        let params = self.params();
         let mc: f64 = params.objects.iter().map(|o| o.m).sum();
         let xc = params
             .objects
@@ -182,7 +316,7 @@ impl Booster {
     /// returns a single-mass radius that behaves the same as the current
     /// (multi-mass) booster
     pub fn rc(&self) -> f64 {
-        let params = self.eom.core();
+        let params = self.params();
 
         let tmp = params
             .objects
@@ -209,6 +343,10 @@ impl Booster {
 impl crate::Model for Booster {
     impl_model_inner!(eom);
 
+    fn set_control_input(&mut self, ci: Option<&[f64]>) {
+        self.eom.core_mut().ci = ci.map(|x| x.try_into().unwrap());
+    }
+
     fn normalize<S>(&self, x: &mut ndarray::ArrayBase<S, ndarray::Ix1>)
     where
         S: ndarray::DataMut<Elem = f64>,
@@ -227,7 +365,7 @@ impl crate::ToImuSample for Booster {
         Sa: ndarray::Data<Elem = f64>,
         Sb: ndarray::DataMut<Elem = f64>,
     {
-        let params = self.eom.core();
+        let params = self.params();
         let thetab = state[State::ThetaB];
         let thetabd = state[State::ThetaBD];
         let thetabdd = state[State::ThetaBDD];
@@ -266,7 +404,7 @@ impl crate::ToImuSample for Booster {
     where
         S: ndarray::Data<Elem = f64>,
     {
-        let params = self.eom.core();
+        let params = self.params();
         let thetab = state[State::ThetaB];
         let theta0 = state[State::Theta0];
 
@@ -274,6 +412,62 @@ impl crate::ToImuSample for Booster {
             + params.rb
             + params.rs
     }
+
+    fn to_speed<S>(&self, state: &ndarray::ArrayBase<S, ndarray::Ix1>) -> f64
+    where
+        S: ndarray::Data<Elem = f64>,
+    {
+        let params = self.params();
+        let thetab = state[State::ThetaB];
+        let thetabd = state[State::ThetaBD];
+        let theta0 = state[State::Theta0] + params.thetas;
+        let theta0d = state[State::Theta0D];
+
+        let vy = params.rb * thetabd * thetab.cos() + params.rs * theta0d * theta0.cos();
+        let vz = params.rb * thetabd * thetab.sin() + params.rs * theta0d * theta0.sin();
+        vy.hypot(vz)
+    }
+
+    fn to_orientation<S>(
+        &self,
+        state: &ndarray::ArrayBase<S, ndarray::Ix1>,
+    ) -> nalgebra::UnitQuaternion<f64>
+    where
+        S: ndarray::Data<Elem = f64>,
+    {
+        nalgebra::UnitQuaternion::from_axis_angle(
+            &nalgebra::Vector3::x_axis(),
+            state[State::Theta0],
+        )
+    }
+}
+
+impl crate::ToEnergy for Booster {
+    /// the booster arm is driven, so the total energy is only constant while
+    /// it doesn't accelerate
+    fn to_energy<S>(&self, state: &ndarray::ArrayBase<S, ndarray::Ix1>) -> Option<crate::Energy>
+    where
+        S: ndarray::Data<Elem = f64>,
+    {
+        let params = self.params();
+        let thetab = state[State::ThetaB];
+        let thetabd = state[State::ThetaBD];
+        let theta0 = state[State::Theta0];
+        let theta0d = state[State::Theta0D];
+
+        let mut energy = crate::Energy::default();
+        for o in &params.objects {
+            let t = theta0 + o.t;
+            let vx = params.rb * thetabd * thetab.cos() + o.r * theta0d * t.cos();
+            let vy = params.rb * thetabd * thetab.sin() + o.r * theta0d * t.sin();
+            let y = math::rt2y(params.rb, thetab) + math::rt2y(o.r, t);
+
+            energy.kinetic += 0.5 * o.m * (vx.powi(2) + vy.powi(2));
+            energy.potential += o.m * math::GRAVITY * (y + params.rb + o.r);
+        }
+
+        Some(energy)
+    }
 }
 
 impl crate::DrawState for Booster {
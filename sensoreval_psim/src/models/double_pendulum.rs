@@ -1,6 +1,3 @@
-use eom::traits::Scheme;
-use eom::traits::TimeEvolution;
-use eom::traits::TimeStep;
 use ndarray_linalg::solve::Inverse;
 
 #[derive(Clone)]
@@ -20,6 +17,12 @@ impl eom::traits::ModelSpec for Params {
     }
 }
 
+impl crate::integrator::Kinematic for Params {
+    fn positions(&self) -> &[usize] {
+        &[2, 3]
+    }
+}
+
 impl eom::traits::Explicit for Params {
     fn rhs<'a, S>(
         &mut self,
@@ -56,15 +59,46 @@ impl eom::traits::Explicit for Params {
 }
 
 pub struct DoublePendulum {
-    eom: eom::explicit::RK4<Params>,
+    eom: crate::integrator::Solver<Params>,
 }
 
 impl DoublePendulum {
     pub fn new(m1: f64, m2: f64, l1: f64, l2: f64, dt: f64) -> Self {
         Self {
-            eom: eom::explicit::RK4::new(Params { m1, m2, l1, l2 }, dt),
+            eom: crate::integrator::Solver::new(Params { m1, m2, l1, l2 }, dt),
         }
     }
 }
 
 impl_model!(DoublePendulum, eom);
+
+impl crate::ToEnergy for DoublePendulum {
+    fn to_energy<S>(&self, state: &ndarray::ArrayBase<S, ndarray::Ix1>) -> Option<crate::Energy>
+    where
+        S: ndarray::Data<Elem = f64>,
+    {
+        let p = self.eom.core();
+        let a1d = state[0];
+        let a2d = state[1];
+        let a1 = state[2];
+        let a2 = state[3];
+        let c12 = (a1 - a2).cos();
+
+        let kinetic = 0.5 * p.m1 * (p.l1 * a1d).powi(2)
+            + 0.5
+                * p.m2
+                * ((p.l1 * a1d).powi(2)
+                    + (p.l2 * a2d).powi(2)
+                    + 2.0 * p.l1 * p.l2 * a1d * a2d * c12);
+        let potential = (p.m1 + p.m2) * math::GRAVITY * p.l1 * (1.0 - a1.cos())
+            + p.m2 * math::GRAVITY * p.l2 * (1.0 - a2.cos());
+        let momentum = p.m1 * p.l1.powi(2) * a1d
+            + p.m2 * (p.l1.powi(2) * a1d + p.l2.powi(2) * a2d + p.l1 * p.l2 * (a1d + a2d) * c12);
+
+        Some(crate::Energy {
+            kinetic,
+            potential,
+            momentum: Some(momentum),
+        })
+    }
+}
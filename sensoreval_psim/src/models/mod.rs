@@ -9,8 +9,14 @@ pub mod pendulum;
 pub use pendulum::Params as PendulumParams;
 pub use pendulum::Pendulum;
 
+pub mod track;
+pub use track::Params as TrackParams;
+pub use track::Track;
+
 use crate::DrawState;
+use crate::Error;
 use crate::Model;
+use crate::ToEnergy;
 use crate::ToImuSample;
 
 #[derive(serde::Deserialize, Debug)]
@@ -20,13 +26,31 @@ pub enum Params {
     Booster(booster::Params),
     #[serde(rename = "pendulum")]
     Pendulum(pendulum::Params),
+    #[serde(rename = "track")]
+    Track(track::Params),
 }
 
 impl Params {
-    pub fn to_model_enum(&self, dt: f64) -> ModelEnum {
-        match self {
+    pub fn to_model_enum(&self, dt: f64) -> Result<ModelEnum, Error> {
+        Ok(match self {
             Self::Booster(p) => Booster::new(p.clone(), dt).into(),
             Self::Pendulum(p) => Pendulum::new(p.clone(), dt).into(),
+            Self::Track(p) => Track::new(p.clone(), dt)?.into(),
+        })
+    }
+
+    /// checks the parameters, call it after loading a config
+    pub fn validate(&self) -> Result<(), Error> {
+        match self {
+            Self::Pendulum(p) => p.validate(),
+            Self::Booster(_) | Self::Track(_) => Ok(()),
+        }
+    }
+
+    /// make all relative paths relative to `dir`
+    pub fn set_basedir(&mut self, dir: &std::path::Path) {
+        if let Self::Track(p) = self {
+            p.filename = dir.join(&p.filename);
         }
     }
 }
@@ -34,8 +58,10 @@ impl Params {
 #[enum_dispatch::enum_dispatch(DrawState)]
 #[enum_dispatch::enum_dispatch(Model)]
 #[enum_dispatch::enum_dispatch(ToImuSample)]
+#[enum_dispatch::enum_dispatch(ToEnergy)]
 #[derive(Clone)]
 pub enum ModelEnum {
     Booster,
     Pendulum,
+    Track,
 }
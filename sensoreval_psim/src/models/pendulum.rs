@@ -1,10 +1,11 @@
-use eom::traits::Scheme;
-use eom::traits::TimeEvolution;
-use eom::traits::TimeStep;
+use crate::Error;
 use sensoreval_utils::AssignState;
 use sensoreval_utils::StateUtils;
 use std::convert::TryInto;
 
+/// angular speed below which coulomb friction fades out, unit: rad/s
+const FRICTION_SPEED_EPS: f64 = 0.01;
+
 #[derive(sensoreval_utils::macros::State)]
 pub enum State {
     Theta,
@@ -19,16 +20,108 @@ pub struct GroundMotor {
     pub ship_arc_half_angle: f64,
 }
 
+/// motor driving the pivot directly. The control input is the throttle
+/// from -1 to 1.
+#[derive(Clone, serde::Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PivotMotor {
+    /// torque at full throttle, divided by the mass. unit: m^2/s^2
+    pub max_torque: f64,
+}
+
+/// tires at the bottom of the ride which drive the ship by friction. The
+/// control input is the throttle from -1 to 1.
+#[derive(Clone, serde::Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct TireDrive {
+    /// see [GroundMotor::ship_arc_half_angle]. unit: rad
+    pub ship_arc_half_angle: f64,
+    /// surface speed of the tires at full throttle, unit: m/s
+    pub tire_speed: f64,
+    /// maximum force the tires can transfer, divided by the mass.
+    /// unit: m/s^2
+    pub max_force: f64,
+    /// slip speed at which the tires transfer about 76% of `max_force`.
+    /// unit: m/s
+    pub slip_speed: f64,
+}
+
+/// brake fins at the bottom of the ride. The control input is the brake
+/// pressure from 0 to 1.
+#[derive(Clone, serde::Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Brake {
+    /// see [GroundMotor::ship_arc_half_angle]. unit: rad
+    pub ship_arc_half_angle: f64,
+    /// braking force at full pressure, divided by the mass. unit: m/s^2
+    pub max_force: f64,
+}
+
 #[derive(Clone, serde::Deserialize, Debug)]
 #[serde(tag = "type")]
 pub enum Motor {
     #[serde(rename = "ground")]
     GroundMotor(GroundMotor),
+    #[serde(rename = "pivot")]
+    PivotMotor(PivotMotor),
+    #[serde(rename = "tire")]
+    TireDrive(TireDrive),
+    #[serde(rename = "brake")]
+    Brake(Brake),
+}
+
+impl Motor {
+    /// checks the parameters, call it after loading a config
+    pub fn validate(&self) -> Result<(), Error> {
+        match self {
+            Self::TireDrive(m) => {
+                if m.slip_speed.is_nan() || m.slip_speed <= 0.0 {
+                    return Err(Error::InvalidParameter("slip_speed has to be positive"));
+                }
+                if m.max_force.is_nan() || m.max_force < 0.0 {
+                    return Err(Error::InvalidParameter("max_force can't be negative"));
+                }
+            }
+            Self::Brake(m) => {
+                if m.max_force.is_nan() || m.max_force < 0.0 {
+                    return Err(Error::InvalidParameter("max_force can't be negative"));
+                }
+            }
+            Self::GroundMotor(_) | Self::PivotMotor(_) => (),
+        }
+
+        Ok(())
+    }
+}
+
+/// physical pendulum properties. All torques are divided by the mass so the
+/// mass itself doesn't have to be known.
+#[derive(Clone, serde::Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Physical {
+    /// distance between the pivot and the center of mass, defaults to
+    /// `radius`. unit: m
+    #[serde(default)]
+    pub cm_radius: Option<f64>,
+    /// moment of inertia around the pivot, defaults to a point mass at
+    /// `cm_radius`. unit: m^2
+    #[serde(default)]
+    pub inertia: Option<f64>,
+    /// viscous bearing friction, unit: m^2/s
+    #[serde(default)]
+    pub viscous_friction: f64,
+    /// coulomb bearing friction, unit: m^2/s^2
+    #[serde(default)]
+    pub coulomb_friction: f64,
+    /// quadratic air drag, unit: m^2
+    #[serde(default)]
+    pub drag: f64,
 }
 
 #[derive(Clone, serde::Deserialize, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct Params {
+    /// distance between the pivot and the sensor, unit: m
     pub radius: f64,
     #[serde(default)]
     pub motor: Option<Motor>,
@@ -36,6 +129,36 @@ pub struct Params {
     /// position of the sensor relative to the center of mass
     #[serde(default)]
     pub sensor_pos: f64,
+
+    /// use a physical instead of a point-mass pendulum
+    #[serde(default)]
+    pub physical: Option<Physical>,
+}
+
+impl Params {
+    /// checks the parameters, call it after loading a config
+    pub fn validate(&self) -> Result<(), Error> {
+        match &self.motor {
+            Some(motor) => motor.validate(),
+            None => Ok(()),
+        }
+    }
+
+    /// distance between the pivot and the center of mass, unit: m
+    pub fn cm_radius(&self) -> f64 {
+        self.physical
+            .as_ref()
+            .and_then(|p| p.cm_radius)
+            .unwrap_or(self.radius)
+    }
+
+    /// moment of inertia around the pivot divided by the mass, unit: m^2
+    pub fn inertia(&self) -> f64 {
+        self.physical
+            .as_ref()
+            .and_then(|p| p.inertia)
+            .unwrap_or_else(|| self.cm_radius().powi(2))
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -44,6 +167,62 @@ struct ParamsInternal {
     ci: Option<[f64; 1]>,
 }
 
+impl ParamsInternal {
+    fn thetadd<S>(&self, state: &ndarray::ArrayBase<S, ndarray::Ix1>) -> f64
+    where
+        S: ndarray::Data<Elem = f64>,
+    {
+        let theta = state[State::Theta];
+        let thetad = state[State::ThetaD];
+        let inertia = self.params.inertia();
+
+        let mut torque = -math::GRAVITY * self.params.cm_radius() * theta.sin();
+
+        if let Some(physical) = &self.params.physical {
+            torque -= physical.viscous_friction * thetad
+                + physical.coulomb_friction * (thetad / FRICTION_SPEED_EPS).tanh()
+                + physical.drag * thetad * thetad.abs();
+        }
+
+        if let (Some(ci), Some(motor)) = (&self.ci, &self.params.motor) {
+            match motor {
+                Motor::GroundMotor(m) => {
+                    if theta.abs() <= m.ship_arc_half_angle {
+                        // accelerate into the direction of movement
+                        let motor = if thetad.is_sign_negative() {
+                            -ci[0]
+                        } else {
+                            ci[0]
+                        };
+                        torque += motor * self.params.radius;
+                    }
+                }
+                Motor::PivotMotor(m) => {
+                    torque += m.max_torque * ci[0].clamp(-1.0, 1.0);
+                }
+                Motor::TireDrive(m) => {
+                    if theta.abs() <= m.ship_arc_half_angle {
+                        let slip =
+                            m.tire_speed * ci[0].clamp(-1.0, 1.0) - thetad * self.params.radius;
+                        let force = m.max_force * (slip / m.slip_speed).tanh();
+                        torque += force * self.params.radius;
+                    }
+                }
+                Motor::Brake(m) => {
+                    if theta.abs() <= m.ship_arc_half_angle {
+                        let force = m.max_force
+                            * ci[0].clamp(0.0, 1.0)
+                            * (thetad / FRICTION_SPEED_EPS).tanh();
+                        torque -= force * self.params.radius;
+                    }
+                }
+            }
+        }
+
+        torque / inertia
+    }
+}
+
 impl eom::traits::ModelSpec for ParamsInternal {
     type Scalar = f64;
     type Dim = ndarray::Ix1;
@@ -53,6 +232,12 @@ impl eom::traits::ModelSpec for ParamsInternal {
     }
 }
 
+impl crate::integrator::Kinematic for ParamsInternal {
+    fn positions(&self) -> &[usize] {
+        &[State::Theta as usize]
+    }
+}
+
 impl eom::traits::Explicit for ParamsInternal {
     fn rhs<'a, S>(
         &mut self,
@@ -61,25 +246,8 @@ impl eom::traits::Explicit for ParamsInternal {
     where
         S: ndarray::DataMut<Elem = f64>,
     {
-        let theta = v[State::Theta];
         let thetad = v[State::ThetaD];
-        let mut thetadd = (-math::GRAVITY * theta.sin()) / self.params.radius;
-
-        if let (Some(ci), Some(motor)) = (&self.ci, &self.params.motor) {
-            match motor {
-                Motor::GroundMotor(m) => {
-                    if theta.abs() <= m.ship_arc_half_angle {
-                        // accelerate into the direction of movement
-                        let motor = if thetad.is_sign_negative() {
-                            -ci[0]
-                        } else {
-                            ci[0]
-                        };
-                        thetadd += motor / self.params.radius;
-                    }
-                }
-            }
-        }
+        let thetadd = self.thetadd(v);
 
         v.assign_state(StateArgs {
             theta: thetad,
@@ -92,13 +260,13 @@ impl eom::traits::Explicit for ParamsInternal {
 
 #[derive(Clone)]
 pub struct Pendulum {
-    eom: eom::explicit::RK4<ParamsInternal>,
+    eom: crate::integrator::Solver<ParamsInternal>,
 }
 
 impl Pendulum {
     pub fn new(params: Params, dt: f64) -> Self {
         Self {
-            eom: eom::explicit::RK4::new(ParamsInternal { params, ci: None }, dt),
+            eom: crate::integrator::Solver::new(ParamsInternal { params, ci: None }, dt),
         }
     }
 }
@@ -120,12 +288,14 @@ impl crate::ToImuSample for Pendulum {
         Sa: ndarray::Data<Elem = f64>,
         Sb: ndarray::DataMut<Elem = f64>,
     {
-        let params = &self.eom.core().params;
+        let core = self.eom.core();
+        let params = &core.params;
         let ac = state[State::ThetaD].powi(2) * params.radius;
+        let at = core.thetadd(state) * params.radius;
 
         accel.assign(&ndarray::array![
             0.0,
-            0.0,
+            at + math::GRAVITY * state[State::Theta].sin(),
             ac + math::GRAVITY * (state[State::Theta] + params.sensor_pos).cos()
         ]);
     }
@@ -140,6 +310,48 @@ impl crate::ToImuSample for Pendulum {
     {
         gyro.assign(&ndarray::array![state[State::ThetaD], 0.0, 0.0]);
     }
+
+    fn to_height<S>(&self, state: &ndarray::ArrayBase<S, ndarray::Ix1>) -> f64
+    where
+        S: ndarray::Data<Elem = f64>,
+    {
+        let params = &self.eom.core().params;
+        params.radius - params.radius * (state[State::Theta] + params.sensor_pos).cos()
+    }
+
+    fn to_speed<S>(&self, state: &ndarray::ArrayBase<S, ndarray::Ix1>) -> f64
+    where
+        S: ndarray::Data<Elem = f64>,
+    {
+        self.eom.core().params.radius * state[State::ThetaD].abs()
+    }
+
+    fn to_orientation<S>(
+        &self,
+        state: &ndarray::ArrayBase<S, ndarray::Ix1>,
+    ) -> nalgebra::UnitQuaternion<f64>
+    where
+        S: ndarray::Data<Elem = f64>,
+    {
+        nalgebra::UnitQuaternion::from_axis_angle(&nalgebra::Vector3::x_axis(), state[State::Theta])
+    }
+}
+
+impl crate::ToEnergy for Pendulum {
+    fn to_energy<S>(&self, state: &ndarray::ArrayBase<S, ndarray::Ix1>) -> Option<crate::Energy>
+    where
+        S: ndarray::Data<Elem = f64>,
+    {
+        let params = &self.eom.core().params;
+        let thetad = state[State::ThetaD];
+        let inertia = params.inertia();
+
+        Some(crate::Energy {
+            kinetic: 0.5 * inertia * thetad.powi(2),
+            potential: math::GRAVITY * params.cm_radius() * (1.0 - state[State::Theta].cos()),
+            momentum: Some(inertia * thetad),
+        })
+    }
 }
 
 impl crate::DrawState for Pendulum {
@@ -150,3 +362,32 @@ impl crate::DrawState for Pendulum {
         sensoreval_graphics::pendulum_2d::draw(cr, state[State::Theta]);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn validate() {
+        let tire = |slip_speed, max_force| Params {
+            radius: 10.0,
+            motor: Some(Motor::TireDrive(TireDrive {
+                ship_arc_half_angle: 0.3,
+                tire_speed: 5.0,
+                max_force,
+                slip_speed,
+            })),
+            sensor_pos: 0.0,
+            physical: None,
+        };
+
+        assert!(tire(0.5, 2.0).validate().is_ok());
+        assert!(tire(0.5, 0.0).validate().is_ok());
+        for (slip_speed, max_force) in [(0.0, 2.0), (-0.5, 2.0), (f64::NAN, 2.0), (0.5, -1.0)] {
+            assert!(matches!(
+                tire(slip_speed, max_force).validate(),
+                Err(Error::InvalidParameter(_))
+            ));
+        }
+    }
+}
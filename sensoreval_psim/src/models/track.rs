@@ -0,0 +1,371 @@
+use crate::spline::Spline;
+use crate::Error;
+use sensoreval_utils::AssignState;
+use sensoreval_utils::StateUtils;
+use std::convert::TryInto;
+
+/// speed below which rolling friction fades out, unit: m/s
+const FRICTION_SPEED_EPS: f64 = 0.01;
+/// time a drive needs to reach its target speed without limits, unit: s
+const DRIVE_RESPONSE_TIME: f64 = 0.1;
+
+#[derive(sensoreval_utils::macros::State)]
+pub enum State {
+    /// distance along the track, unit: m
+    Distance,
+    /// unit: m/s
+    Speed,
+}
+
+/// lift hill chain or launch section
+#[derive(Clone, serde::Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Drive {
+    /// track distance where the drive begins, unit: m
+    pub start: f64,
+    /// track distance where the drive ends, unit: m
+    pub end: f64,
+    /// speed the drive moves the car at, negative values push backwards.
+    /// It gets scaled by the control input. unit: m/s
+    pub speed: f64,
+    /// maximum acceleration the drive can apply, unit: m/s^2
+    pub max_accel: f64,
+}
+
+fn default_resolution() -> f64 {
+    0.1
+}
+
+#[derive(Clone, serde::Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Params {
+    /// path to the track file, see [crate::spline::parse_points]
+    pub filename: std::path::PathBuf,
+    /// connect the last track point to the first one
+    #[serde(default)]
+    pub closed: bool,
+    /// distance between resampled track points, unit: m
+    #[serde(default = "default_resolution")]
+    pub resolution: f64,
+    /// rolling resistance coefficient, relative to the normal force
+    #[serde(default)]
+    pub rolling_friction: f64,
+    /// aerodynamic drag per mass: 0.5 * rho * c_d * A / m, unit: 1/m
+    #[serde(default)]
+    pub drag: f64,
+    /// position of the sensor relative to the track in the car frame
+    /// (right, forward, up), unit: m
+    #[serde(default)]
+    pub seat: [f64; 3],
+    /// drives which get enabled by the control input
+    #[serde(default)]
+    pub drives: Vec<Drive>,
+}
+
+#[derive(Clone, Debug)]
+struct ParamsInternal {
+    params: Params,
+    spline: std::sync::Arc<Spline>,
+    /// drive throttle, 0 to 1
+    ci: Option<[f64; 1]>,
+}
+
+impl ParamsInternal {
+    fn sdd(&self, s: f64, sd: f64) -> f64 {
+        let gravity = nalgebra::Vector3::new(0.0, 0.0, -math::GRAVITY);
+        let forward = self.spline.frame(s).column(1).into_owned();
+        let (_, d2) = self.spline.derivatives(s, &nalgebra::Vector3::zeros());
+
+        // specific force the rails apply perpendicular to the track
+        let normal = d2 * sd.powi(2) - gravity;
+        let normal = (normal - forward * normal.dot(&forward)).norm();
+
+        let mut sdd = gravity.dot(&forward)
+            - self.params.rolling_friction * normal * (sd / FRICTION_SPEED_EPS).tanh()
+            - self.params.drag * sd * sd.abs();
+
+        if let Some(ci) = &self.ci {
+            let s = self.spline.wrap(s);
+
+            if let Some(drive) = self
+                .params
+                .drives
+                .iter()
+                .find(|d| s >= d.start && s <= d.end)
+            {
+                let target = drive.speed * ci[0];
+                let accel = (target - sd) / DRIVE_RESPONSE_TIME - sdd;
+
+                // drives can only push into their own direction
+                sdd += if drive.speed.is_sign_negative() {
+                    accel.clamp(-drive.max_accel, 0.0)
+                } else {
+                    accel.clamp(0.0, drive.max_accel)
+                };
+            }
+        }
+
+        sdd
+    }
+}
+
+impl eom::traits::ModelSpec for ParamsInternal {
+    type Scalar = f64;
+    type Dim = ndarray::Ix1;
+
+    fn model_size(&self) -> usize {
+        State::len()
+    }
+}
+
+impl crate::integrator::Kinematic for ParamsInternal {
+    fn positions(&self) -> &[usize] {
+        &[State::Distance as usize]
+    }
+}
+
+impl eom::traits::Explicit for ParamsInternal {
+    fn rhs<'a, S>(
+        &mut self,
+        v: &'a mut ndarray::ArrayBase<S, ndarray::Ix1>,
+    ) -> &'a mut ndarray::ArrayBase<S, ndarray::Ix1>
+    where
+        S: ndarray::DataMut<Elem = f64>,
+    {
+        let s = v[State::Distance];
+        let sd = v[State::Speed];
+        let sdd = self.sdd(s, sd);
+
+        v.assign_state(StateArgs {
+            distance: sd,
+            speed: sdd,
+        });
+
+        v
+    }
+}
+
+/// car following a 3d track
+#[derive(Clone)]
+pub struct Track {
+    eom: crate::integrator::Solver<ParamsInternal>,
+}
+
+impl Track {
+    pub fn new(params: Params, dt: f64) -> Result<Self, Error> {
+        let spline = Spline::load(&params.filename, params.closed, params.resolution)?;
+
+        Ok(Self {
+            eom: crate::integrator::Solver::new(
+                ParamsInternal {
+                    params,
+                    spline: std::sync::Arc::new(spline),
+                    ci: None,
+                },
+                dt,
+            ),
+        })
+    }
+
+    pub fn params(&self) -> &Params {
+        &self.eom.core().params
+    }
+
+    pub fn spline(&self) -> &Spline {
+        &self.eom.core().spline
+    }
+}
+
+impl crate::Model for Track {
+    fn step<S>(&mut self, x: &mut ndarray::ArrayBase<S, ndarray::Ix1>) -> Result<(), Error>
+    where
+        S: ndarray::DataMut<Elem = f64>,
+    {
+        self.eom.iterate(x)?;
+
+        // open tracks end in a buffer stop
+        let spline = self.spline();
+        let s = x[State::Distance];
+        if !spline.closed() && (s < 0.0 || s > spline.length()) {
+            x[State::Distance] = s.clamp(0.0, spline.length());
+            x[State::Speed] = 0.0;
+        }
+
+        Ok(())
+    }
+
+    fn normalize<S>(&self, x: &mut ndarray::ArrayBase<S, ndarray::Ix1>)
+    where
+        S: ndarray::DataMut<Elem = f64>,
+    {
+        x[State::Distance] = self.spline().wrap(x[State::Distance]);
+    }
+
+    fn set_dt(&mut self, dt: f64) {
+        self.eom.set_dt(dt);
+    }
+
+    fn dt(&self) -> f64 {
+        self.eom.get_dt()
+    }
+
+    fn set_integrator(&mut self, integrator: crate::integrator::Integrator) {
+        self.eom.set_integrator(integrator);
+    }
+
+    fn set_control_input(&mut self, ci: Option<&[f64]>) {
+        self.eom.core_mut().ci = ci.map(|x| x.try_into().unwrap());
+    }
+}
+
+impl crate::ToImuSample for Track {
+    fn to_accel<Sa, Sb>(
+        &self,
+        state: &ndarray::ArrayBase<Sa, ndarray::Ix1>,
+        accel: &mut ndarray::ArrayBase<Sb, ndarray::Ix1>,
+    ) where
+        Sa: ndarray::Data<Elem = f64>,
+        Sb: ndarray::DataMut<Elem = f64>,
+    {
+        let params = self.eom.core();
+        let s = state[State::Distance];
+        let sd = state[State::Speed];
+        let sdd = params.sdd(s, sd);
+
+        let seat = nalgebra::Vector3::from(params.params.seat);
+        let (d1, d2) = params.spline.derivatives(s, &seat);
+        let specific = d2 * sd.powi(2) + d1 * sdd + nalgebra::Vector3::new(0.0, 0.0, math::GRAVITY);
+        let body = params.spline.frame(s).transpose() * specific;
+
+        accel.assign(&ndarray::array![body[0], body[1], body[2]]);
+    }
+
+    fn to_gyro<Sa, Sb>(
+        &self,
+        state: &ndarray::ArrayBase<Sa, ndarray::Ix1>,
+        gyro: &mut ndarray::ArrayBase<Sb, ndarray::Ix1>,
+    ) where
+        Sa: ndarray::Data<Elem = f64>,
+        Sb: ndarray::DataMut<Elem = f64>,
+    {
+        let rate = self.spline().angular_rate(state[State::Distance]) * state[State::Speed];
+        gyro.assign(&ndarray::array![rate[0], rate[1], rate[2]]);
+    }
+
+    fn to_height<S>(&self, state: &ndarray::ArrayBase<S, ndarray::Ix1>) -> f64
+    where
+        S: ndarray::Data<Elem = f64>,
+    {
+        let spline = self.spline();
+        let seat = nalgebra::Vector3::from(self.params().seat);
+
+        spline.position(state[State::Distance], &seat)[2] - spline.min_height()
+    }
+
+    fn to_speed<S>(&self, state: &ndarray::ArrayBase<S, ndarray::Ix1>) -> f64
+    where
+        S: ndarray::Data<Elem = f64>,
+    {
+        let seat = nalgebra::Vector3::from(self.params().seat);
+        let (d1, _) = self.spline().derivatives(state[State::Distance], &seat);
+
+        d1.norm() * state[State::Speed].abs()
+    }
+
+    fn to_orientation<S>(
+        &self,
+        state: &ndarray::ArrayBase<S, ndarray::Ix1>,
+    ) -> nalgebra::UnitQuaternion<f64>
+    where
+        S: ndarray::Data<Elem = f64>,
+    {
+        let frame = self.spline().frame(state[State::Distance]);
+        nalgebra::UnitQuaternion::from_matrix(&frame)
+    }
+}
+
+impl crate::ToEnergy for Track {
+    /// friction and drives make the total energy change
+    fn to_energy<S>(&self, state: &ndarray::ArrayBase<S, ndarray::Ix1>) -> Option<crate::Energy>
+    where
+        S: ndarray::Data<Elem = f64>,
+    {
+        let spline = self.spline();
+        let height = spline.position(state[State::Distance], &nalgebra::Vector3::zeros())[2]
+            - spline.min_height();
+
+        Some(crate::Energy {
+            kinetic: 0.5 * state[State::Speed].powi(2),
+            potential: math::GRAVITY * height,
+            momentum: None,
+        })
+    }
+}
+
+impl crate::DrawState for Track {
+    fn draw_state<S>(&self, cr: &cairo::Context, state: &ndarray::ArrayBase<S, ndarray::Ix1>)
+    where
+        S: ndarray::DataMut<Elem = f64>,
+    {
+        let spline = self.spline();
+        let track: Vec<(f64, f64)> = spline.positions().map(|p| (p[0], p[1])).collect();
+        let car = spline.position(state[State::Distance], &nalgebra::Vector3::zeros());
+
+        sensoreval_graphics::track_2d::draw(cr, &track, (car[0], car[1]));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// level and straight, so only the drive accelerates the car
+    fn params(drive_speed: f64, ci: Option<[f64; 1]>) -> ParamsInternal {
+        let points: Vec<_> = (0..5).map(|i| [i as f64 * 5.0, 0.0, 0.0, 0.0]).collect();
+
+        ParamsInternal {
+            params: Params {
+                filename: std::path::PathBuf::new(),
+                closed: false,
+                resolution: default_resolution(),
+                rolling_friction: 0.0,
+                drag: 0.0,
+                seat: [0.0; 3],
+                drives: vec![Drive {
+                    start: 5.0,
+                    end: 15.0,
+                    speed: drive_speed,
+                    max_accel: 1.0,
+                }],
+            },
+            spline: std::sync::Arc::new(Spline::new(&points, false, 0.1).unwrap()),
+            ci,
+        }
+    }
+
+    #[test]
+    fn drive() {
+        let forward = params(2.0, Some([1.0]));
+        // limited by max_accel
+        assert!((forward.sdd(10.0, 0.0) - 1.0).abs() < 1.0e-9);
+        // can't brake a car which is faster than the drive
+        assert!(forward.sdd(10.0, 5.0).abs() < 1.0e-9);
+        assert!(forward.sdd(10.0, -1.0) > 0.0);
+        // outside of the drive
+        assert!(forward.sdd(2.0, 0.0).abs() < 1.0e-9);
+
+        let backward = params(-2.0, Some([1.0]));
+        assert!((backward.sdd(10.0, 0.0) + 1.0).abs() < 1.0e-9);
+        assert!(backward.sdd(10.0, -5.0).abs() < 1.0e-9);
+        assert!(backward.sdd(10.0, 1.0) < 0.0);
+
+        // the control input scales the target speed
+        let half = params(2.0, Some([0.5]));
+        assert!((half.sdd(10.0, 0.9) - 1.0).abs() < 1.0e-9);
+        assert!(half.sdd(10.0, 1.5).abs() < 1.0e-9);
+
+        // disabled without control input
+        let off = params(2.0, None);
+        assert!(off.sdd(10.0, 0.0).abs() < 1.0e-9);
+    }
+}
@@ -3,48 +3,158 @@ use crate::Model;
 use sensoreval_graphics::utils::CairoEx;
 use sensoreval_graphics::utils::ToUtilFont;
 
+/// change of the live control input per key press
+const CONTROL_INPUT_STEP: f64 = 0.1;
+/// longest time the simulation thread sleeps, unit: s
+const MAX_SLEEP: f64 = 0.01;
+
 type State = std::sync::Arc<std::sync::RwLock<ndarray::Array1<f64>>>;
-struct GuiCallback<F> {
+
+/// user input which gets applied by the simulation thread
+struct Controls {
+    paused: bool,
+    /// number of single steps requested while paused
+    steps: u64,
+    reset: bool,
+    /// simulation speed relative to real time
+    speed: f64,
+    /// overrides the configured control input
+    control_input: Option<f64>,
+    control_input_changed: bool,
+    /// current simulation time, unit: s
+    t: f64,
+}
+
+impl Default for Controls {
+    fn default() -> Self {
+        Self {
+            paused: false,
+            steps: 0,
+            reset: false,
+            speed: 1.0,
+            control_input: None,
+            control_input_changed: false,
+            t: 0.0,
+        }
+    }
+}
+
+type SharedControls = std::sync::Arc<std::sync::Mutex<Controls>>;
+
+struct GuiCallback<'a, F> {
     state: State,
+    controls: SharedControls,
+    font: sensoreval_graphics::utils::Font<'a>,
     render_state: F,
 }
 
-impl<F: Fn(&cairo::Context, &State)> GuiCallback<F> {
+impl<'a, F: Fn(&cairo::Context, &State)> GuiCallback<'a, F> {
     pub fn new<M: 'static + Model + Send + Sync>(
         mut model: M,
         initial: ndarray::Array1<f64>,
+        start_off: f64,
+        control_input: Vec<Vec<f64>>,
+        state_updates: Vec<Vec<f64>>,
+        font: sensoreval_graphics::utils::Font<'a>,
         render_state: F,
     ) -> Self {
         let o = Self {
-            state: std::sync::Arc::new(std::sync::RwLock::new(initial)),
+            state: std::sync::Arc::new(std::sync::RwLock::new(initial.clone())),
+            controls: SharedControls::default(),
+            font,
             render_state,
         };
 
         let state_lock = o.state.clone();
+        let controls_lock = o.controls.clone();
         std::thread::spawn(move || {
-            let tstart = std::time::Instant::now();
-            let mut idx = 0u64;
-            let mut x = state_lock.read().unwrap().clone();
             let dt = model.dt();
+            let mut x = initial.clone();
+            let mut idx = 0u64;
+            let mut timed_array_ci = sensoreval_utils::TimedArray::new(&control_input);
+            let mut timed_array_su = sensoreval_utils::TimedArray::new(&state_updates);
+            let mut live_ci = None;
+            let mut configured_ci: Option<Vec<f64>> = None;
 
-            fn secs_since_last_calc(tstart: &std::time::Instant, idx: &u64, dt: &f64) -> f64 {
-                tstart.elapsed().as_secs_f64() - *idx as f64 * *dt
-            }
+            let mut tlast = std::time::Instant::now();
+            // simulation time we're behind real time, unit: s
+            let mut backlog = 0.0;
 
             loop {
-                let niter = (secs_since_last_calc(&tstart, &idx, &dt) / dt) as u64;
+                let elapsed = tlast.elapsed().as_secs_f64();
+                tlast = std::time::Instant::now();
+
+                let (niter, speed) = {
+                    let mut controls = controls_lock.lock().unwrap();
+
+                    if controls.reset {
+                        controls.reset = false;
+                        x.assign(&initial);
+                        idx = 0;
+                        backlog = 0.0;
+                        timed_array_ci = sensoreval_utils::TimedArray::new(&control_input);
+                        timed_array_su = sensoreval_utils::TimedArray::new(&state_updates);
+                        configured_ci = None;
+                        controls.control_input_changed = true;
+                    }
+
+                    if controls.control_input_changed {
+                        controls.control_input_changed = false;
+                        live_ci = controls.control_input;
+
+                        match live_ci {
+                            Some(ci) => model.set_control_input(Some(&[ci][..])),
+                            None => model.set_control_input(configured_ci.as_deref()),
+                        }
+                    }
+
+                    let niter = if controls.paused {
+                        backlog = 0.0;
+                        std::mem::take(&mut controls.steps)
+                    } else {
+                        backlog += elapsed * controls.speed;
+                        let niter = (backlog / dt) as u64;
+                        backlog -= niter as f64 * dt;
+                        niter
+                    };
+
+                    (niter, controls.speed)
+                };
 
-                for _ in 0..niter as usize {
-                    model.step(&mut x);
+                let mut ndone = 0;
+                for n in 0..niter {
+                    let t = (idx + n) as f64 * dt + start_off;
+
+                    if let Some(control_input) = timed_array_ci.next(t + dt) {
+                        configured_ci = Some(control_input.to_vec());
+                        if live_ci.is_none() {
+                            model.set_control_input(Some(control_input));
+                        }
+                    }
+
+                    if let Some(newstate) = timed_array_su.next(t + dt) {
+                        for (i, val) in newstate.iter().enumerate() {
+                            if !val.is_nan() {
+                                x[i] = *val;
+                            }
+                        }
+                    }
+
+                    if let Err(e) = model.step(&mut x) {
+                        // wait for a reset
+                        println!("simulation stopped: {e}");
+                        controls_lock.lock().unwrap().paused = true;
+                        break;
+                    }
+                    ndone += 1;
                 }
 
-                idx += niter;
+                idx += ndone;
                 state_lock.write().unwrap().assign(&x);
+                controls_lock.lock().unwrap().t = idx as f64 * dt + start_off;
 
-                let waittime = secs_since_last_calc(&tstart, &idx, &dt);
-                if waittime > 0.0 {
-                    std::thread::sleep(std::time::Duration::from_secs_f64(waittime));
-                }
+                let waittime = ((dt - backlog) / speed).clamp(0.0, MAX_SLEEP);
+                std::thread::sleep(std::time::Duration::from_secs_f64(waittime));
             }
         });
 
@@ -52,7 +162,7 @@ impl<F: Fn(&cairo::Context, &State)> GuiCallback<F> {
     }
 }
 
-impl<F: Fn(&cairo::Context, &State)> sensoreval_gui::Callback for GuiCallback<F> {
+impl<'a, F: Fn(&cairo::Context, &State)> sensoreval_gui::Callback for GuiCallback<'a, F> {
     fn render(&mut self, _ctx: &mut sensoreval_gui::RuntimeContext, cr: &cairo::Context) {
         // clear
         cr.set_source_rgba_u32(0x00000000);
@@ -60,25 +170,96 @@ impl<F: Fn(&cairo::Context, &State)> sensoreval_gui::Callback for GuiCallback<F>
 
         cr.set_source_rgba_u32(0xffffffff);
         (self.render_state)(cr, &self.state);
+
+        let status = {
+            let controls = self.controls.lock().unwrap();
+            let mut status = format!("t={:.2}s speed={}x", controls.t, controls.speed);
+            if let Some(ci) = controls.control_input {
+                status += &format!(" ci={ci:.1}");
+            }
+            if controls.paused {
+                status += " PAUSED";
+            }
+            status
+        };
+
+        cr.move_to(10.0, 10.0);
+        self.font.draw(cr, &status);
+    }
+
+    fn key_pressed(&mut self, _ctx: &mut sensoreval_gui::RuntimeContext, code: i32) {
+        use sensoreval_gui::key;
+
+        let mut controls = self.controls.lock().unwrap();
+        match code {
+            key::SPACE => controls.paused = !controls.paused,
+            key::PERIOD | key::RIGHT => {
+                controls.paused = true;
+                controls.steps += 1;
+            }
+            key::R => controls.reset = true,
+            key::PLUS => controls.speed *= 2.0,
+            key::MINUS => controls.speed /= 2.0,
+            key::UP | key::DOWN => {
+                let step = if code == key::UP {
+                    CONTROL_INPUT_STEP
+                } else {
+                    -CONTROL_INPUT_STEP
+                };
+                controls.control_input = Some(controls.control_input.unwrap_or(0.0) + step);
+                controls.control_input_changed = true;
+            }
+            key::NUM_0 => {
+                controls.control_input = None;
+                controls.control_input_changed = true;
+            }
+            _ => (),
+        }
     }
 }
 
-pub fn run_sim(dt: f64, params: &crate::models::Params, state: ndarray::Array1<f64>) {
-    let model = params.to_model_enum(dt);
+/// `control_input` and `state_updates` are arrays where the first element
+/// is a time in seconds followed by the model-specific values. The
+/// simulation starts at `start_off`, unit: s
+///
+/// keys: space pauses, `.` or right steps once, `r` resets, `+`/`-` change
+/// the speed, up/down change the control input live and `0` goes back to
+/// the configured control input
+pub fn run_sim(
+    dt: f64,
+    integrator: crate::integrator::Integrator,
+    params: &crate::models::Params,
+    state: ndarray::Array1<f64>,
+    start_off: f64,
+    control_input: Vec<Vec<f64>>,
+    state_updates: Vec<Vec<f64>>,
+) -> Result<(), crate::Error> {
+    let mut model = params.to_model_enum(dt)?;
+    model.set_integrator(integrator);
     let model_copy = model.clone();
 
     let mut font = pango::FontDescription::new();
     font.set_family("Archivo Black");
     font.set_absolute_size(30.0 * f64::from(pango::SCALE));
-    let _font = font.into_utilfont();
+    let font = font.into_utilfont();
 
     let mut gui = sensoreval_gui::Context::default();
-    gui.set_callback(Some(GuiCallback::new(model, state, move |cr, state| {
-        // clone state so the lock can stay unlocked while drawing
-        let state = state.read().unwrap().clone();
-        model_copy.draw_state(cr, &state);
-    })));
+    gui.set_callback(Some(GuiCallback::new(
+        model,
+        state,
+        start_off,
+        control_input,
+        state_updates,
+        font,
+        move |cr, state| {
+            // clone state so the lock can stay unlocked while drawing
+            let state = state.read().unwrap().clone();
+            model_copy.draw_state(cr, &state);
+        },
+    )));
 
     gui.set_timer_ms(15);
     gui.start().unwrap();
+
+    Ok(())
 }
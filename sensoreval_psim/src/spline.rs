@@ -0,0 +1,493 @@
+use crate::Error;
+
+/// number of catmull-rom samples per segment used for measuring the length
+const SUBSTEPS: usize = 32;
+/// step size for numeric derivatives, unit: m
+const DIFF_STEP: f64 = 1.0e-3;
+
+type Vector3 = nalgebra::Vector3<f64>;
+
+/// resampled track point
+#[derive(Clone, Copy, Debug)]
+struct Knot {
+    /// unit: m
+    pos: Vector3,
+    /// unit: rad
+    bank: f64,
+    /// unbanked right-hand vector of the car
+    right: Vector3,
+}
+
+impl Knot {
+    fn weighted(knots: [&Self; 4], w: [f64; 4]) -> Self {
+        let mut ret = Self {
+            pos: Vector3::zeros(),
+            bank: 0.0,
+            right: Vector3::zeros(),
+        };
+
+        for (k, w) in knots.iter().zip(w.iter()) {
+            ret.pos += k.pos * *w;
+            ret.bank += k.bank * *w;
+            ret.right += k.right * *w;
+        }
+
+        ret
+    }
+}
+
+/// smooth 3d curve through a list of points, parameterized by arc length
+///
+/// The points are interpolated using catmull-rom splines and resampled at
+/// equal distances. Evaluation uses a uniform cubic B-spline on top of the
+/// resampled points so the second derivative is continuous.
+#[derive(Debug)]
+pub struct Spline {
+    knots: Vec<Knot>,
+    /// distance between two knots, unit: m
+    spacing: f64,
+    closed: bool,
+    /// unit: m
+    length: f64,
+    /// lowest z coordinate, unit: m
+    min_height: f64,
+}
+
+fn catmull_rom(p: [[f64; 4]; 4], t: f64) -> [f64; 4] {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    let mut ret = [0.0; 4];
+
+    for (i, v) in ret.iter_mut().enumerate() {
+        *v = 0.5
+            * (2.0 * p[1][i]
+                + (-p[0][i] + p[2][i]) * t
+                + (2.0 * p[0][i] - 5.0 * p[1][i] + 4.0 * p[2][i] - p[3][i]) * t2
+                + (-p[0][i] + 3.0 * p[1][i] - 3.0 * p[2][i] + p[3][i]) * t3);
+    }
+
+    ret
+}
+
+fn xyz(p: &[f64; 4]) -> Vector3 {
+    Vector3::new(p[0], p[1], p[2])
+}
+
+/// parse a track file. Every line contains one point `x y z [bank]`, values
+/// can be separated by whitespace or commas and `#` starts a comment
+pub fn parse_points(s: &str) -> Result<Vec<[f64; 4]>, Error> {
+    let mut points = Vec::new();
+
+    for (i, line) in s.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let values = line
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|v| !v.is_empty())
+            .map(|v| v.parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match values.len() {
+            3 => points.push([values[0], values[1], values[2], 0.0]),
+            4 => points.push([values[0], values[1], values[2], values[3]]),
+            _ => return Err(Error::InvalidTrackPoint(i + 1)),
+        }
+    }
+
+    Ok(points)
+}
+
+impl Spline {
+    /// load points from a track file, see [parse_points]
+    pub fn load<P: AsRef<std::path::Path>>(
+        path: P,
+        closed: bool,
+        resolution: f64,
+    ) -> Result<Self, Error> {
+        let s = std::fs::read_to_string(path)?;
+        Self::new(&parse_points(&s)?, closed, resolution)
+    }
+
+    /// points: list of `[x, y, z, bank]`, unit: m and rad
+    /// closed: connect the last point to the first one
+    /// resolution: approximate distance between resampled points, unit: m
+    pub fn new(points: &[[f64; 4]], closed: bool, resolution: f64) -> Result<Self, Error> {
+        let n = points.len();
+        if n < 2 || (closed && n < 3) {
+            return Err(Error::NotEnoughTrackPoints);
+        }
+
+        let point = |i: isize| {
+            if closed {
+                points[math::pymod(i, n as isize) as usize]
+            } else {
+                points[i.clamp(0, n as isize - 1) as usize]
+            }
+        };
+
+        // densely sample the catmull-rom spline
+        let nsegments = if closed { n } else { n - 1 };
+        let mut dense = Vec::with_capacity(nsegments * SUBSTEPS + 1);
+        for i in 0..nsegments as isize {
+            let p = [point(i - 1), point(i), point(i + 1), point(i + 2)];
+            for j in 0..SUBSTEPS {
+                dense.push(catmull_rom(p, j as f64 / SUBSTEPS as f64));
+            }
+        }
+        dense.push(point(nsegments as isize));
+
+        let mut dist = Vec::with_capacity(dense.len());
+        dist.push(0.0);
+        for i in 1..dense.len() {
+            let d = (xyz(&dense[i]) - xyz(&dense[i - 1])).norm();
+            dist.push(dist[i - 1] + d);
+        }
+        let length = *dist.last().unwrap();
+        if !length.is_normal() {
+            return Err(Error::NotEnoughTrackPoints);
+        }
+
+        // resample at equal distances
+        let nknots = if closed {
+            ((length / resolution).round() as usize).max(3)
+        } else {
+            ((length / resolution).ceil() as usize).max(1) + 1
+        };
+        let spacing = if closed {
+            length / nknots as f64
+        } else {
+            length / (nknots - 1) as f64
+        };
+
+        let mut knots = Vec::with_capacity(nknots);
+        let mut id = 0;
+        for k in 0..nknots {
+            let s = k as f64 * spacing;
+            while id + 2 < dist.len() && dist[id + 1] < s {
+                id += 1;
+            }
+
+            let seglen = dist[id + 1] - dist[id];
+            let t = if seglen > 0.0 {
+                ((s - dist[id]) / seglen).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+
+            let a = &dense[id];
+            let b = &dense[id + 1];
+            knots.push(Knot {
+                pos: xyz(a) + (xyz(b) - xyz(a)) * t,
+                bank: a[3] + (b[3] - a[3]) * t,
+                right: Vector3::zeros(),
+            });
+        }
+
+        let min_height = knots.iter().map(|k| k.pos[2]).fold(f64::INFINITY, f64::min);
+
+        let mut o = Self {
+            knots,
+            spacing,
+            closed,
+            length,
+            min_height,
+        };
+        o.update_right_vectors();
+
+        Ok(o)
+    }
+
+    /// calculate the unbanked right-hand vectors
+    ///
+    /// They are kept horizontal where possible. The sign is chosen to be
+    /// continuous so the car stays inverted at the top of a loop, and they're
+    /// transported along vertical sections where horizontal isn't defined.
+    fn update_right_vectors(&mut self) {
+        let up = Vector3::new(0.0, 0.0, 1.0);
+        let mut prev: Option<Vector3> = None;
+
+        for k in 0..self.knots.len() {
+            let forward =
+                (self.knot(k as isize + 1).pos - self.knot(k as isize - 1).pos).normalize();
+            let level = forward.cross(&up);
+
+            let right = if level.norm() > 0.1 {
+                let right = level.normalize();
+                match prev {
+                    Some(prev) if prev.dot(&right) < 0.0 => -right,
+                    _ => right,
+                }
+            } else {
+                let reference = prev.unwrap_or_else(|| Vector3::new(1.0, 0.0, 0.0));
+                (reference - forward * reference.dot(&forward)).normalize()
+            };
+
+            self.knots[k].right = right;
+            prev = Some(right);
+        }
+    }
+
+    fn knot(&self, i: isize) -> &Knot {
+        let n = self.knots.len() as isize;
+        if self.closed {
+            &self.knots[math::pymod(i, n) as usize]
+        } else {
+            &self.knots[i.clamp(0, n - 1) as usize]
+        }
+    }
+
+    fn blend(&self, s: f64) -> Knot {
+        let u = s / self.spacing;
+        let i = u.floor();
+        let t = u - i;
+        let i = i as isize;
+
+        let t2 = t * t;
+        let t3 = t2 * t;
+        let w = [
+            (1.0 - t).powi(3) / 6.0,
+            (3.0 * t3 - 6.0 * t2 + 4.0) / 6.0,
+            (-3.0 * t3 + 3.0 * t2 + 3.0 * t + 1.0) / 6.0,
+            t3 / 6.0,
+        ];
+
+        Knot::weighted(
+            [
+                self.knot(i - 1),
+                self.knot(i),
+                self.knot(i + 1),
+                self.knot(i + 2),
+            ],
+            w,
+        )
+    }
+
+    /// total length, unit: m
+    pub fn length(&self) -> f64 {
+        self.length
+    }
+
+    pub fn closed(&self) -> bool {
+        self.closed
+    }
+
+    /// lowest z coordinate of the track, unit: m
+    pub fn min_height(&self) -> f64 {
+        self.min_height
+    }
+
+    /// map `s` into `[0, length)` for closed tracks
+    pub fn wrap(&self, s: f64) -> f64 {
+        if self.closed {
+            math::pymod(s, self.length)
+        } else {
+            s
+        }
+    }
+
+    /// positions of the resampled points
+    pub fn positions(&self) -> impl Iterator<Item = &Vector3> {
+        self.knots.iter().map(|k| &k.pos)
+    }
+
+    /// car frame at distance `s`. The columns are the right, forward and up
+    /// vectors in world coordinates
+    pub fn frame(&self, s: f64) -> nalgebra::Matrix3<f64> {
+        let forward = (self.blend(s + DIFF_STEP).pos - self.blend(s - DIFF_STEP).pos).normalize();
+        let k = self.blend(s);
+        let right = (k.right - forward * k.right.dot(&forward)).normalize();
+
+        // roll around the forward axis, positive values bank to the right
+        let right = right * k.bank.cos() + forward.cross(&right) * k.bank.sin();
+        let up = right.cross(&forward);
+
+        nalgebra::Matrix3::from_columns(&[right, forward, up])
+    }
+
+    /// world position of `offset` (car frame) at distance `s`
+    pub fn position(&self, s: f64, offset: &Vector3) -> Vector3 {
+        self.blend(s).pos + self.frame(s) * offset
+    }
+
+    /// first and second derivative of [position](Self::position) with
+    /// respect to `s`
+    pub fn derivatives(&self, s: f64, offset: &Vector3) -> (Vector3, Vector3) {
+        let h = DIFF_STEP;
+        let a = self.position(s - h, offset);
+        let b = self.position(s, offset);
+        let c = self.position(s + h, offset);
+
+        ((c - a) / (2.0 * h), (c - b * 2.0 + a) / h.powi(2))
+    }
+
+    /// rotation of the car frame per meter, in car coordinates
+    pub fn angular_rate(&self, s: f64) -> Vector3 {
+        let h = DIFF_STEP;
+        let frame = self.frame(s);
+        let dframe = (self.frame(s + h) - self.frame(s - h)) / (2.0 * h);
+        let w = frame.transpose() * dframe;
+
+        Vector3::new(w[(2, 1)], w[(0, 2)], w[(1, 0)])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const RADIUS: f64 = 10.0;
+
+    fn straight(bank: f64) -> Spline {
+        let points: Vec<_> = (0..5).map(|i| [i as f64 * 5.0, 0.0, 0.0, bank]).collect();
+        Spline::new(&points, false, 0.1).unwrap()
+    }
+
+    /// counterclockwise in the xy plane
+    fn circle() -> Spline {
+        let n = 128;
+        let points: Vec<_> = (0..n)
+            .map(|i| {
+                let a = i as f64 / n as f64 * std::f64::consts::TAU;
+                [RADIUS * a.cos(), RADIUS * a.sin(), 0.0, 0.0]
+            })
+            .collect();
+        Spline::new(&points, true, 0.1).unwrap()
+    }
+
+    fn assert_vec_eq(a: &Vector3, b: &Vector3, epsilon: f64) {
+        assert!((a - b).norm() < epsilon, "{a} != {b}");
+    }
+
+    #[test]
+    fn parse() {
+        let points = parse_points("# x y z bank\n0 0 0\n\n1, 2, 3, 0.5 # comment\n").unwrap();
+        assert_eq!(points, vec![[0.0, 0.0, 0.0, 0.0], [1.0, 2.0, 3.0, 0.5]]);
+
+        assert!(matches!(
+            parse_points("0 0 0\n1 2\n"),
+            Err(Error::InvalidTrackPoint(2))
+        ));
+        assert!(matches!(
+            Spline::new(&[[0.0; 4], [0.0; 4]], false, 0.1),
+            Err(Error::NotEnoughTrackPoints)
+        ));
+    }
+
+    #[test]
+    fn wrap() {
+        let spline = circle();
+        let length = spline.length();
+        assert!((length - std::f64::consts::TAU * RADIUS).abs() < 1.0e-2);
+
+        assert!((spline.wrap(length + 1.0) - 1.0).abs() < 1.0e-9);
+        assert!((spline.wrap(-1.0) - (length - 1.0)).abs() < 1.0e-9);
+        for s in [0.0, 1.0, 20.0] {
+            let zero = Vector3::zeros();
+            assert_vec_eq(
+                &spline.position(s, &zero),
+                &spline.position(s + length, &zero),
+                1.0e-9,
+            );
+            assert_vec_eq(
+                &spline.position(s, &zero),
+                &spline.position(s - length, &zero),
+                1.0e-9,
+            );
+        }
+
+        // open tracks don't wrap
+        let spline = straight(0.0);
+        assert_eq!(spline.wrap(spline.length() + 1.0), spline.length() + 1.0);
+    }
+
+    #[test]
+    fn straight_frame() {
+        let spline = straight(0.0);
+        assert!((spline.length() - 20.0).abs() < 1.0e-9);
+
+        let frame = spline.frame(10.0);
+        assert_vec_eq(
+            &frame.column(0).into_owned(),
+            &Vector3::new(0.0, -1.0, 0.0),
+            1.0e-9,
+        );
+        assert_vec_eq(
+            &frame.column(1).into_owned(),
+            &Vector3::new(1.0, 0.0, 0.0),
+            1.0e-9,
+        );
+        assert_vec_eq(
+            &frame.column(2).into_owned(),
+            &Vector3::new(0.0, 0.0, 1.0),
+            1.0e-9,
+        );
+
+        // the seat offset is in car coordinates
+        let seat = Vector3::new(0.0, 0.0, 1.5);
+        assert_vec_eq(
+            &spline.position(10.0, &seat),
+            &Vector3::new(10.0, 0.0, 1.5),
+            1.0e-6,
+        );
+        assert_vec_eq(&spline.angular_rate(10.0), &Vector3::zeros(), 1.0e-6);
+    }
+
+    #[test]
+    fn banking() {
+        // banked to the right, so the right-hand vector points down
+        let spline = straight(std::f64::consts::FRAC_PI_2);
+
+        let frame = spline.frame(10.0);
+        assert_vec_eq(
+            &frame.column(0).into_owned(),
+            &Vector3::new(0.0, 0.0, -1.0),
+            1.0e-9,
+        );
+        assert_vec_eq(
+            &frame.column(1).into_owned(),
+            &Vector3::new(1.0, 0.0, 0.0),
+            1.0e-9,
+        );
+        assert_vec_eq(
+            &frame.column(2).into_owned(),
+            &Vector3::new(0.0, -1.0, 0.0),
+            1.0e-9,
+        );
+    }
+
+    #[test]
+    fn circular_frame() {
+        let spline = circle();
+
+        for s in [0.0, 10.0, 33.0] {
+            let p = spline.position(s, &Vector3::zeros());
+            assert!((p.norm() - RADIUS).abs() < 1.0e-3);
+
+            let frame = spline.frame(s);
+            let tangent = Vector3::new(-p[1], p[0], 0.0) / p.norm();
+            assert_vec_eq(&frame.column(1).into_owned(), &tangent, 1.0e-3);
+            assert_vec_eq(
+                &frame.column(2).into_owned(),
+                &Vector3::new(0.0, 0.0, 1.0),
+                1.0e-9,
+            );
+            // the right-hand vector points outwards
+            assert_vec_eq(&frame.column(0).into_owned(), &(p / p.norm()), 1.0e-3);
+
+            // centripetal acceleration at unit speed
+            let (d1, d2) = spline.derivatives(s, &Vector3::zeros());
+            assert!((d1.norm() - 1.0).abs() < 1.0e-3);
+            assert_vec_eq(&d2, &(-p / p.norm().powi(2)), 1.0e-3);
+
+            // turning left
+            assert_vec_eq(
+                &spline.angular_rate(s),
+                &Vector3::new(0.0, 0.0, 1.0 / RADIUS),
+                1.0e-3,
+            );
+        }
+    }
+}
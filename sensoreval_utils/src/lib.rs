@@ -11,6 +11,7 @@ mod plot;
 pub use plot::Plot;
 pub use plot::COLOR_A;
 pub use plot::COLOR_E;
+pub use plot::COLOR_E_BAND;
 pub use plot::COLOR_M;
 
 mod python;
@@ -5,6 +5,8 @@ use std::io::Write;
 pub const COLOR_A: &str = "#1f77b4";
 pub const COLOR_M: &str = "#ff7f0e";
 pub const COLOR_E: &str = "#2ca02c";
+/// confidence bands of [COLOR_E]
+pub const COLOR_E_BAND: &str = "rgba(44, 160, 44, 0.4)";
 
 static ID2NAME: [&str; 3] = ["e", "n", "u"];
 
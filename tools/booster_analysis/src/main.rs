@@ -63,6 +63,7 @@ fn multi2single(opt: &Multi2SingleOpt) {
             thetas: 0.0,
             rs: 0.5,
             friction: None,
+            drive: None,
         },
         opt.dt,
     );
@@ -77,6 +78,7 @@ fn multi2single(opt: &Multi2SingleOpt) {
             thetas: 0.0,
             rs: 0.5,
             friction: None,
+            drive: None,
         },
         opt.dt,
     );
@@ -94,8 +96,8 @@ fn multi2single(opt: &Multi2SingleOpt) {
         xs.push(x.clone());
         xs_multi.push(x_multi.clone());
 
-        model.step(&mut x);
-        model_multi.step(&mut x_multi);
+        model.step(&mut x).unwrap();
+        model_multi.step(&mut x_multi).unwrap();
     }
 
     for arg in &[("single", &xs), ("multi", &xs_multi)] {
@@ -594,6 +596,7 @@ fn plot(plotopt: &PlotOpt) {
                 thetas: opt.thetas,
                 rs: opt.rs,
                 friction: opt.friction,
+                drive: None,
             },
             0.001,
         );
@@ -611,7 +614,7 @@ fn plot(plotopt: &PlotOpt) {
         for (i, s) in samples.iter().enumerate() {
             if i != 0 {
                 model.set_dt(s.time_seconds() - samples[i - 1].time_seconds());
-                model.step(&mut state);
+                model.step(&mut state).unwrap();
             }
 
             states.push(state.clone());